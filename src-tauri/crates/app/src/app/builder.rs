@@ -13,14 +13,16 @@ use crate::{
     config::{VeilConfig, VeilConfigEvent},
     error::FrontendError,
     events::EventSystemHandler,
-    queue::{QueueEvent, QueueOrigin},
-    systems::{player::PlayerEvent, ui::UIUpdateEvent, utils::data_path},
+    queue::{QueueEvent, QueueOrigin, sync_unavailable_tracks},
+    systems::{player::PlayerEvent, ui::UIUpdateEvent, utils::data_path, watcher::restart_watcher},
 };
 
 pub fn make_specta_type_builder() -> Builder {
     let specta_builder = Builder::<tauri::Wry>::new()
         .commands(collect_commands![
             commands::music_folder::select_music_folder,
+            commands::music_folder::cancel_import,
+            commands::music_folder::get_track_tags,
             commands::db::get_album_with_tracks,
             commands::db::get_artist_with_albums,
             commands::db::get_all_albums,
@@ -126,17 +128,26 @@ pub fn handle_tauri_setup(
             }
         }
 
-        let app_handle = app_handle.clone();
+        let lastfm_handle = app_handle.clone();
         tauri::async_runtime::spawn(async move {
             if let Some(l) = event.payload.last_fm_enabled {
-                let state = app_handle.state::<VeilState>();
+                let state = lastfm_handle.state::<VeilState>();
                 let mut lastfm = state.lastfm.lock().await;
                 lastfm.enable(l);
             };
         });
 
-        let mut config = lock_or_log(state.config.write(), "Config Write").unwrap();
-        config.update_config_and_write(event.payload).unwrap();
+        let watch_changed =
+            event.payload.music_dir.is_some() || event.payload.watch_music_dir.is_some();
+
+        {
+            let mut config = lock_or_log(state.config.write(), "Config Write").unwrap();
+            config.update_config_and_write(event.payload).unwrap();
+        }
+
+        if watch_changed && let Err(e) = restart_watcher(&app_handle) {
+            logging::error!("Failed to restart the music directory watcher: {:?}", e);
+        }
     });
 
     let app_handle = app.handle();
@@ -152,6 +163,8 @@ pub fn handle_tauri_setup(
         fs::write(covers.join("placeholder.png"), pc)?;
     }
 
+    sync_unavailable_tracks(&state)?;
+
     // Populate the queue if a queue origin exists
     let mut queue = lock_or_log(state.queue.lock(), "Queue Mutex").unwrap();
     if let Some(queue_origin) = queue.origin() {
@@ -175,6 +188,7 @@ pub fn handle_tauri_setup(
     }
 
     initiate_track_ended_thread(&app_handle);
+    restart_watcher(app_handle)?;
 
     Ok(())
 }
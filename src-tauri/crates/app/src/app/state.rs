@@ -2,7 +2,7 @@ use logging::{lock_or_log, try_with_log};
 use std::{
     env,
     fs::create_dir,
-    sync::{Arc, Mutex, RwLock},
+    sync::{Arc, Mutex, RwLock, atomic::AtomicBool},
 };
 use tauri::{AppHandle, State};
 use tauri_specta::Event;
@@ -13,7 +13,7 @@ use crate::{
     discord::DiscordState,
     error::FrontendError,
     queue::QueueSystem,
-    systems::{player::PlayerEvent, utils::data_path},
+    systems::{player::PlayerEvent, utils::data_path, watcher::LibraryWatcher},
 };
 
 pub struct VeilState {
@@ -24,6 +24,10 @@ pub struct VeilState {
     pub config: Arc<RwLock<VeilConfig>>,
     pub lastfm: Arc<tokio::sync::Mutex<lastfm::LastFM>>,
     pub resume_notify: Arc<Notify>,
+    /// Set to stop the library import in progress
+    pub import_cancelled: Arc<AtomicBool>,
+    /// Imports changes to the music directory, if watching it is enabled
+    pub library_watcher: Mutex<Option<LibraryWatcher>>,
 }
 
 pub type TauriState<'a> = State<'a, VeilState>;
@@ -94,6 +98,8 @@ pub fn initialize_state(app: &mut tauri::App) -> Result<VeilState, FrontendError
         lastfm.set_session_key(session_key);
     }
 
+    let db = try_with_log!("Database", || db::Database::new(path.clone()))?;
+
     let player = try_with_log!("Music Player", || {
         media_controls::Player::new(platform_config)
     })?;
@@ -104,11 +110,13 @@ pub fn initialize_state(app: &mut tauri::App) -> Result<VeilState, FrontendError
             veil_config.playback.queue_origin,
             veil_config.playback.repeat_mode,
         ))),
-        db: Arc::new(db::Database::new(path.clone())),
+        db: Arc::new(db),
         lastfm: Arc::new(tokio::sync::Mutex::new(lastfm)),
         config: Arc::new(RwLock::new(veil_config)),
         discord: Mutex::new(discord),
         resume_notify: Arc::new(Notify::new()),
+        import_cancelled: Arc::new(AtomicBool::new(false)),
+        library_watcher: Mutex::new(None),
     })
 }
 
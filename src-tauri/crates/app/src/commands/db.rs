@@ -84,15 +84,13 @@ pub fn search_db(search_str: &str, state: TauriState) -> Result<Vec<Search>, Fro
 #[tauri::command]
 #[specta::specta]
 pub fn new_playlist(name: String, state: TauriState) -> Result<u32, FrontendError> {
-    state.db.insert::<NewPlaylist>(NewPlaylist {
+    let id = state.db.insert::<NewPlaylist>(NewPlaylist {
         name: &name,
         description: "",
         cover_path: "/placeholder.png",
     })?;
 
-    let latest = state.db.latest::<Playlists>()?;
-
-    Ok(latest.id)
+    Ok(id)
 }
 
 #[tauri::command]
@@ -47,10 +47,27 @@ pub struct IntegrationsConfig {
 }
 
 /// Music library settings
-#[derive(Serialize, Deserialize, Type, Clone, Default)]
+#[derive(Serialize, Deserialize, Type, Clone)]
 pub struct LibraryConfig {
     /// The directory where all the music files are
     pub music_dir: Option<String>,
+
+    /// If changes to the music directory should be imported while VEIL is open
+    #[serde(default = "watch_music_dir_default")]
+    pub watch_music_dir: bool,
+}
+
+impl Default for LibraryConfig {
+    fn default() -> Self {
+        Self {
+            music_dir: None,
+            watch_music_dir: watch_music_dir_default(),
+        }
+    }
+}
+
+fn watch_music_dir_default() -> bool {
+    true
 }
 
 /// Playback behavior and queue state
@@ -86,6 +103,8 @@ pub struct VeilConfigEvent {
 
     pub music_dir: Option<String>,
 
+    pub watch_music_dir: Option<bool>,
+
     pub last_fm_session_key: Option<String>,
 
     pub queue_origin: Option<QueueOrigin>,
@@ -106,7 +125,7 @@ impl VeilConfig {
                 ui: UiConfig {
                     theme: ThemeMode::Dark,
                 },
-                library: LibraryConfig { music_dir: None },
+                library: LibraryConfig::default(),
                 integrations: IntegrationsConfig {
                     discord_enabled: false,
                     last_fm_enabled: false,
@@ -130,6 +149,9 @@ impl VeilConfig {
 
         // Update library related preferences
         self.library.music_dir = config.music_dir.or(self.library.music_dir.take());
+        self.library.watch_music_dir = config
+            .watch_music_dir
+            .unwrap_or(self.library.watch_music_dir);
 
         // Update integration related preferences
         self.integrations.last_fm_session_key = config
@@ -211,6 +233,22 @@ mod tests {
         assert_eq!(config.library.music_dir, Some("hello".to_owned()));
     }
 
+    #[test]
+    fn update_watch_music_dir() {
+        let mut config = VeilConfig::default();
+
+        assert_eq!(config.library.watch_music_dir, true);
+
+        config.update_config({
+            VeilConfigEvent {
+                watch_music_dir: Some(false),
+                ..VeilConfigEvent::default()
+            }
+        });
+
+        assert_eq!(config.library.watch_music_dir, false);
+    }
+
     #[test]
     fn update_discord_enabled() {
         let mut config = VeilConfig::default();
@@ -1,4 +1,4 @@
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
 
 use logging::lock_or_log;
 use rand::{Rng, SeedableRng, rngs::SmallRng};
@@ -73,6 +73,9 @@ pub struct QueueSystem {
     /// Internal state for PRNG.
     rng: SmallRng,
 
+    /// Tracks whose music folder can't be reached, which are skipped over.
+    unavailable: HashSet<u32>,
+
     /// If the queue has reached the end.
     pub reached_end: bool,
 }
@@ -108,6 +111,7 @@ impl QueueSystem {
             current_index: 0,
             origin,
             rng,
+            unavailable: HashSet::new(),
             reached_end: false,
         }
     }
@@ -140,17 +144,29 @@ impl QueueSystem {
         self.global_queue = new_global;
     }
 
-    /// Internal method to get the previous index.
+    /// Replace the tracks that are skipped over, because their music folder can't be reached
+    pub fn set_unavailable(&mut self, track_ids: HashSet<u32>) {
+        logging::debug!("Setting {} tracks as unavailable", track_ids.len());
+        self.unavailable = track_ids;
+    }
+
+    /// Whether the music folder of a track could be reached on the last import
+    pub fn is_available(&self, track_id: u32) -> bool {
+        !self.unavailable.contains(&track_id)
+    }
+
+    /// Internal method to get the index before `from`.
     ///
     /// Ignores repeat modes.
-    fn get_previous_index(&self) -> usize {
-        (self.current_index - 1) % self.global_queue.len()
+    fn get_previous_index(&self, from: usize) -> usize {
+        let len = self.global_queue.len();
+        (from + len - 1) % len
     }
 
-    /// Internal method to get the next index.
+    /// Internal method to get the index after `from`.
     ///
     /// Handles the repeat mode as well.
-    fn get_next_index(&mut self) -> Option<usize> {
+    fn get_next_index(&mut self, from: usize) -> Option<usize> {
         logging::debug!(
             "Getting next index with repeat mode: {:?}",
             self.repeat_mode
@@ -158,7 +174,7 @@ impl QueueSystem {
 
         self.reached_end = false;
 
-        let idx = self.current_index + 1;
+        let idx = from + 1;
         match self.repeat_mode {
             RepeatMode::None => {
                 if idx >= self.global_queue.len() {
@@ -170,8 +186,31 @@ impl QueueSystem {
                 }
             }
             RepeatMode::Queue => Some(idx % self.global_queue.len()),
-            RepeatMode::Track => Some(self.current_index),
+            RepeatMode::Track => Some(from),
+        }
+    }
+
+    /// Internal method to step from the current index in a direction, skipping over
+    /// unavailable tracks.
+    fn step_index(&mut self, dir: Direction) -> Option<usize> {
+        let mut idx = self.current_index;
+
+        // Each track is looked at once at most, so a queue of only unavailable
+        // tracks doesn't loop forever
+        for _ in 0..self.global_queue.len() {
+            idx = match dir {
+                Direction::Next => self.get_next_index(idx)?,
+                Direction::Previous => self.get_previous_index(idx),
+            };
+
+            if self.is_available(self.track_at_index(idx)) {
+                return Some(idx);
+            }
+
+            logging::debug!("Skipping unavailable track at index {idx}");
         }
+
+        None
     }
 
     /// Internal method to get a track from the queue.
@@ -180,7 +219,12 @@ impl QueueSystem {
         // of checking the previous direction.
         match mode {
             Mode::Consume => {
-                if let Some(track) = self.personal_queue.pop_front() {
+                while let Some(track) = self.personal_queue.pop_front() {
+                    if !self.is_available(track) {
+                        logging::debug!("Dropping unavailable track {track} from personal queue");
+                        continue;
+                    }
+
                     logging::debug!("Consuming next track from personal queue");
 
                     if self.personal_queue.is_empty() {
@@ -191,9 +235,13 @@ impl QueueSystem {
                 }
             }
             Mode::Peek => {
-                if let Some(track) = self.personal_queue.front() {
+                if let Some(&track) = self
+                    .personal_queue
+                    .iter()
+                    .find(|&&track| self.is_available(track))
+                {
                     logging::debug!("Peeking at next track from personal queue");
-                    return Some(*track);
+                    return Some(track);
                 }
             }
         };
@@ -210,13 +258,12 @@ impl QueueSystem {
                 self.personal_consumed = false;
             }
 
-            return Some(track);
+            if self.is_available(track) {
+                return Some(track);
+            }
         }
 
-        let idx = match dir {
-            Direction::Next => self.get_next_index(),
-            Direction::Previous => Some(self.get_previous_index()),
-        };
+        let idx = self.step_index(dir);
 
         logging::debug!(
             "{} track from non-shuffled global queue at index {idx:?}",
@@ -491,6 +538,16 @@ impl QueueEvent {
     }
 }
 
+/// Update which tracks the queue skips over, after the library was imported
+pub fn sync_unavailable_tracks(state: &VeilState) -> Result<(), FrontendError> {
+    let unavailable = state.db.unavailable_track_ids()?;
+
+    let mut queue = lock_or_log(state.queue.lock(), "Queue Mutex")?;
+    queue.set_unavailable(unavailable.into_iter().collect());
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -698,4 +755,51 @@ mod tests {
         queue.next();
         assert_eq!(first, queue.next());
     }
+
+    #[test]
+    fn skips_unavailable_tracks() {
+        let mut queue = QueueSystem::new(None, RepeatMode::None);
+        queue.set_global(vec![0, 1, 2, 3]);
+        queue.set_unavailable(HashSet::from([1, 2]));
+
+        assert_eq!(queue.peek_next(), Some(3));
+        assert_eq!(queue.next(), Some(3));
+        assert_eq!(queue.current_index, 3);
+
+        assert_eq!(queue.previous(), Some(0));
+        assert_eq!(queue.current_index, 0);
+    }
+
+    #[test]
+    fn drops_unavailable_personal_tracks() {
+        let mut queue = QueueSystem::new(None, RepeatMode::None);
+        queue.enqueue_personal(50);
+        queue.enqueue_personal(20);
+        queue.set_global(vec![0, 1, 2]);
+        queue.set_unavailable(HashSet::from([50]));
+
+        assert_eq!(queue.peek_next(), Some(20));
+        assert_eq!(queue.next(), Some(20));
+        assert!(queue.personal_queue.is_empty());
+    }
+
+    #[test]
+    fn only_unavailable_tracks_returns_none() {
+        let mut queue = QueueSystem::new(None, RepeatMode::Queue);
+        queue.set_global(vec![0, 1, 2]);
+        queue.set_unavailable(HashSet::from([0, 1, 2]));
+
+        assert_eq!(queue.next(), None);
+        assert_eq!(queue.previous(), None);
+        assert_eq!(queue.current_index, 0);
+    }
+
+    #[test]
+    fn previous_wraps_around() {
+        let mut queue = QueueSystem::new(None, RepeatMode::None);
+        queue.set_global(vec![0, 1, 2]);
+
+        assert_eq!(queue.previous(), Some(2));
+        assert_eq!(queue.current_index, 2);
+    }
 }
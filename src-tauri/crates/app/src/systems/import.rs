@@ -0,0 +1,1644 @@
+use anyhow::Context;
+use common::{
+    AlbumType, Albums, ArtistRole, Artists, DuplicateFile, FileStamp, NewAlbum, NewArtist,
+    NewTrack, TrackFile, traits::Hashable,
+};
+use logging::lock_or_log;
+use metadata_audio::{
+    Metadata,
+    properties::{Codec, StreamProperties},
+};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::ipc::Channel;
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fs, io,
+    num::NonZero,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, SyncSender},
+    },
+    thread,
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use crate::{
+    commands::music_folder::MetadataEvent,
+    systems::utils::{data_path, sanitize_string},
+};
+
+/// Tracks written to the database per transaction
+const BATCH_SIZE: usize = 512;
+
+/// Parsed tracks are written at least this often, so progress keeps moving on slow disks
+const FLUSH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How many parsed tracks each worker can have waiting on the writer
+const QUEUE_DEPTH: usize = 64;
+
+const EVENT_ID: usize = 1;
+
+/// Held for the whole import, so an import from the folder picker and one from the
+/// watcher don't write the same tracks at once
+static IMPORT_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImportOutcome {
+    Finished(ScanSummary),
+    /// Tracks written before the import was cancelled are kept
+    Cancelled,
+}
+
+/// How the files in the music folder compared to the library
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, Type)]
+pub struct ScanSummary {
+    /// Files that weren't in the library
+    pub added: usize,
+    /// Files that changed since they were imported
+    pub updated: usize,
+    /// Tracks whose file is gone, or is missing the tags a track needs now
+    pub removed: usize,
+    /// Tracks whose file was moved or renamed, which keep their playlist entries
+    pub moved: usize,
+    /// Files that haven't changed, which aren't read again
+    pub unchanged: usize,
+    /// Copies of a track already in the library, which are skipped
+    pub duplicates: usize,
+    /// Tracks kept as unavailable, because the music folder couldn't be reached
+    pub unavailable: usize,
+    /// Unavailable tracks whose music folder could be reached again
+    pub restored: usize,
+}
+
+impl ScanSummary {
+    /// Whether the library was changed at all
+    pub fn changed(&self) -> bool {
+        self.added + self.updated + self.removed + self.moved + self.unavailable + self.restored > 0
+    }
+}
+
+/// A file that has to be read, because it's new or changed since it was imported
+struct PendingFile {
+    path: PathBuf,
+    /// The track imported from this file before, if any
+    track_id: Option<u32>,
+    file: FileStamp,
+}
+
+/// What parsing a single file came up with
+enum Parsed {
+    Track(Box<ParsedTrack>),
+    /// The file is missing the tags a track needs. A track imported from it before is
+    /// removed.
+    Skipped {
+        track_id: Option<u32>,
+    },
+    /// The file couldn't be read, it might still be being copied or be locked by a tag
+    /// editor. Its track is left as is, so the file is read again on the next scan.
+    Unreadable {
+        track_id: Option<u32>,
+        file: FileStamp,
+    },
+}
+
+/// The metadata of a track, owned so it can be sent from a worker to the writer
+struct ParsedTrack {
+    path: PathBuf,
+    track_id: Option<u32>,
+    file: FileStamp,
+    artist: String,
+    album_artist: String,
+    album: String,
+    name: String,
+    year: u16,
+    compilation: bool,
+    duration: f32,
+    track_number: Option<u32>,
+    track_total: Option<u32>,
+    disc_number: Option<u32>,
+    disc_total: Option<u32>,
+    genre: Option<String>,
+    composer: Option<String>,
+    release_date: Option<String>,
+    artists: Vec<String>,
+    remixers: Vec<String>,
+    composers: Vec<String>,
+    stream: StreamProperties,
+    /// Front cover, back covers and artist photos are only used without one
+    cover: Option<Vec<u8>>,
+}
+
+impl ParsedTrack {
+    /// Albums are grouped by album artist, so compilations stay a single album
+    fn from_metadata(pending: &PendingFile, metadata: &Metadata) -> Option<ParsedTrack> {
+        let (Some(artist), Some(album_artist), Some(album), Some(name)) = (
+            metadata.artist.as_deref(),
+            metadata.album_artist_or_artist(),
+            metadata.album.as_deref(),
+            metadata.name.as_deref(),
+        ) else {
+            return None;
+        };
+
+        let owned =
+            |values: &[Cow<'_, str>]| values.iter().map(|value| value.to_string()).collect();
+
+        Some(ParsedTrack {
+            path: pending.path.clone(),
+            track_id: pending.track_id,
+            file: pending.file,
+            artist: artist.to_owned(),
+            album_artist: album_artist.to_owned(),
+            album: album.to_owned(),
+            name: name.to_owned(),
+            year: metadata.year.unwrap_or(0),
+            compilation: metadata.compilation,
+            duration: metadata.duration,
+            track_number: metadata.track_number,
+            track_total: metadata.track_total,
+            disc_number: metadata.disc_number,
+            disc_total: metadata.disc_total,
+            genre: metadata.genre.as_deref().map(str::to_owned),
+            composer: metadata.composer.as_deref().map(str::to_owned),
+            release_date: metadata.release_date.as_deref().map(str::to_owned),
+            artists: owned(&metadata.artists),
+            remixers: owned(&metadata.remixers),
+            composers: owned(&metadata.composers),
+            stream: metadata.stream,
+            cover: metadata.front_cover().map(|picture| picture.data.to_vec()),
+        })
+    }
+}
+
+/// Bring the library up to date with `music_folder`.
+///
+/// Only files that are new or changed since they were imported are read, on a pool of
+/// worker threads, while a single writer inserts them in batches. A new file that's a
+/// track whose file is gone takes over that track, so moving files keeps their playlist
+/// entries. Tracks of the music folder whose file is gone otherwise are removed at the
+/// end. Setting `cancelled` stops the import after the batch being written.
+pub fn import_library(
+    db: &db::Database,
+    music_folder: &Path,
+    cancelled: &AtomicBool,
+    on_event: &Channel<MetadataEvent>,
+) -> anyhow::Result<ImportOutcome> {
+    let _import = lock_or_log(IMPORT_LOCK.lock(), "Import Mutex")?;
+
+    on_event.send(MetadataEvent::Started { id: EVENT_ID })?;
+
+    // Every track path starts with the folder it was imported from
+    let track_prefix = music_folder.join("").to_string_lossy().into_owned();
+
+    if !is_reachable(music_folder) {
+        // Scanning an unmounted drive would find nothing and remove every track in it
+        let summary = ScanSummary {
+            unavailable: db.set_tracks_available(&track_prefix, false)?,
+            ..ScanSummary::default()
+        };
+
+        on_event.send(MetadataEvent::Total {
+            id: EVENT_ID,
+            total: 0,
+        })?;
+        on_event.send(MetadataEvent::Finished {
+            id: EVENT_ID,
+            summary,
+        })?;
+
+        return Ok(ImportOutcome::Finished(summary));
+    }
+
+    let restored = db.set_tracks_available(&track_prefix, true)?;
+
+    let scan = Scan::new(db, music_folder, &track_prefix)?;
+    on_event.send(MetadataEvent::Total {
+        id: EVENT_ID,
+        total: scan.pending,
+    })?;
+
+    let next_folder = AtomicUsize::new(0);
+    let workers = thread::available_parallelism().map_or(4, NonZero::get);
+
+    let mut writer = Writer::new(
+        db,
+        music_folder,
+        scan.changed_albums,
+        scan.missing,
+        scan.moved_away,
+    )?;
+    writer.gone_duplicates = scan.gone_duplicates;
+    writer.summary.unchanged = scan.unchanged;
+    writer.summary.duplicates = scan.duplicates;
+    writer.summary.restored = restored;
+
+    let outcome = thread::scope(|scope| {
+        let (sender, receiver) = mpsc::sync_channel(workers * QUEUE_DEPTH);
+
+        for _ in 0..workers {
+            let sender = sender.clone();
+            let (folders, next_folder) = (&scan.folders, &next_folder);
+            scope.spawn(move || parse_folders(folders, next_folder, cancelled, &sender));
+        }
+
+        // Workers hold the only senders, so the writer stops once they're all done
+        drop(sender);
+
+        writer.run(&receiver, cancelled, on_event)
+    })?;
+
+    writer.tidy_albums()?;
+
+    match outcome {
+        ImportOutcome::Finished(summary) => {
+            on_event.send(MetadataEvent::Finished {
+                id: EVENT_ID,
+                summary,
+            })?;
+        }
+        ImportOutcome::Cancelled => on_event.send(MetadataEvent::Cancelled { id: EVENT_ID })?,
+    }
+
+    Ok(outcome)
+}
+
+/// The music folder compared against the tracks already in the library
+struct Scan {
+    /// Files that have to be read, grouped by the folder they're in
+    folders: Vec<Vec<PendingFile>>,
+    pending: usize,
+    unchanged: usize,
+    /// Copies of a track that haven't changed since they were skipped
+    duplicates: usize,
+    /// Files skipped as a copy of a track that are gone
+    gone_duplicates: Vec<String>,
+    /// Tracks of the music folder whose file is gone
+    missing: Vec<TrackFile>,
+    /// Tracks of another folder whose file is gone, which a file of the music folder
+    /// might have been moved from
+    moved_away: Vec<TrackFile>,
+    /// Albums of tracks that changed or are gone
+    changed_albums: HashSet<u32>,
+}
+
+impl Scan {
+    fn new(db: &db::Database, music_folder: &Path, track_prefix: &str) -> Result<Self, db::Error> {
+        let mut known: HashMap<PathBuf, TrackFile> = HashMap::new();
+        let mut moved_away = Vec::new();
+
+        // Tracks of another folder are left alone, an import of it might have been
+        // cancelled or its drive unmounted. Ones whose file is gone are still looked for,
+        // in case the folder was moved here.
+        for track in db.track_files()? {
+            if track.path.starts_with(track_prefix) {
+                known.insert(PathBuf::from(&track.path), track);
+            } else if !Path::new(&track.path).exists() {
+                moved_away.push(track);
+            }
+        }
+
+        let mut duplicate_files: HashMap<PathBuf, DuplicateFile> = db
+            .duplicate_files()?
+            .into_iter()
+            .filter(|duplicate| duplicate.path.starts_with(track_prefix))
+            .map(|duplicate| (PathBuf::from(&duplicate.path), duplicate))
+            .collect();
+
+        let mut pending = Vec::new();
+        let mut unchanged = 0;
+        let mut copies = Vec::new();
+        let mut changed_albums = HashSet::new();
+
+        for path in Metadata::recursive_dir(music_folder) {
+            let duplicate = duplicate_files.remove(&path);
+
+            let file = match file_stamp(&path) {
+                Ok(file) => file,
+                Err(e) => {
+                    // Left as is, it might be readable again on the next scan
+                    logging::error!("Failed to stat {}: {:?}", path.display(), e);
+                    known.remove(&path);
+                    continue;
+                }
+            };
+
+            let track_id = match known.remove(&path) {
+                Some(track) if track.file == Some(file) => {
+                    unchanged += 1;
+                    continue;
+                }
+                Some(track) => {
+                    changed_albums.insert(track.album_id);
+                    Some(track.id)
+                }
+                None => {
+                    // Skipped as a copy before, and hasn't changed since
+                    if let Some(duplicate) = duplicate.filter(|duplicate| duplicate.file == file) {
+                        copies.push((path, duplicate.track_id, file));
+                        continue;
+                    }
+
+                    None
+                }
+            };
+
+            pending.push(PendingFile {
+                path,
+                track_id,
+                file,
+            });
+        }
+
+        let missing: Vec<_> = known.into_values().collect();
+
+        // A copy of a track whose file is gone is read again, to take over the track
+        let missing_ids: HashSet<u32> = missing
+            .iter()
+            .chain(&moved_away)
+            .map(|track| track.id)
+            .collect();
+        let mut duplicates = 0;
+
+        for (path, track_id, file) in copies {
+            if missing_ids.contains(&track_id) {
+                pending.push(PendingFile {
+                    path,
+                    track_id: None,
+                    file,
+                });
+            } else {
+                duplicates += 1;
+            }
+        }
+        changed_albums.extend(
+            missing
+                .iter()
+                .chain(&moved_away)
+                .map(|track| track.album_id),
+        );
+
+        Ok(Self {
+            pending: pending.len(),
+            folders: group_by_folder(pending),
+            unchanged,
+            duplicates,
+            gone_duplicates: duplicate_files
+                .into_keys()
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect(),
+            missing,
+            moved_away,
+            changed_albums,
+        })
+    }
+}
+
+/// An unmounted drive leaves either nothing or an empty mount point behind, neither of
+/// which should be imported
+pub fn is_reachable(music_folder: &Path) -> bool {
+    fs::read_dir(music_folder).is_ok_and(|mut entries| entries.next().is_some())
+}
+
+fn file_stamp(path: &Path) -> io::Result<FileStamp> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_nanos() as i64);
+
+    #[cfg(unix)]
+    let inode = {
+        use std::os::unix::fs::MetadataExt;
+        Some(metadata.ino() as i64)
+    };
+    #[cfg(not(unix))]
+    let inode = None;
+
+    Ok(FileStamp {
+        size: metadata.len() as i64,
+        modified,
+        inode,
+    })
+}
+
+/// Files grouped by the folder they're in, in the order the folders were found
+fn group_by_folder(files: Vec<PendingFile>) -> Vec<Vec<PendingFile>> {
+    let mut folders: Vec<Vec<PendingFile>> = Vec::new();
+    let mut indices: HashMap<PathBuf, usize> = HashMap::new();
+
+    for file in files {
+        let parent = file
+            .path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let idx = *indices.entry(parent).or_insert_with(|| {
+            folders.push(Vec::new());
+            folders.len() - 1
+        });
+
+        folders[idx].push(file);
+    }
+
+    folders
+}
+
+/// A worker takes whole folders at a time, so only the first track of a folder has its
+/// pictures read and reaches the writer before the rest of the folder does.
+fn parse_folders(
+    folders: &[Vec<PendingFile>],
+    next_folder: &AtomicUsize,
+    cancelled: &AtomicBool,
+    sender: &SyncSender<Parsed>,
+) {
+    let mut buffer = Vec::with_capacity(1024 * 64);
+
+    while let Some(folder) = folders.get(next_folder.fetch_add(1, Ordering::Relaxed)) {
+        for (idx, pending) in folder.iter().enumerate() {
+            if cancelled.load(Ordering::Relaxed) {
+                return;
+            }
+
+            buffer.clear();
+
+            let parsed = match Metadata::from_file(&mut buffer, &pending.path, idx > 0) {
+                Ok(metadata) => ParsedTrack::from_metadata(pending, &metadata).map_or(
+                    Parsed::Skipped {
+                        track_id: pending.track_id,
+                    },
+                    |track| Parsed::Track(Box::new(track)),
+                ),
+                Err(e) => {
+                    logging::error!(
+                        "Failed to read metadata for {}: {:?}",
+                        pending.path.display(),
+                        e
+                    );
+                    Parsed::Unreadable {
+                        track_id: pending.track_id,
+                        file: pending.file,
+                    }
+                }
+            };
+
+            // The writer only hangs up when it failed, which is reported from there
+            if sender.send(parsed).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Owns every write to the database during an import
+struct Writer<'a> {
+    db: &'a db::Database,
+    music_folder: &'a Path,
+    artists: HashMap<String, u32>,
+    albums: HashMap<(u32, String), u32>,
+    album_covers: HashMap<u32, String>,
+    album_paths: HashMap<u32, String>,
+    /// Albums whose tracks changed, their type and duration are brought up to date
+    /// afterwards
+    changed_albums: HashSet<u32>,
+    /// Tracks whose file is gone, unless a new file turns out to be where it was moved
+    missing: HashMap<u32, TrackFile>,
+    /// Missing tracks of another folder, which are never removed
+    moved_away: HashSet<u32>,
+    /// Missing tracks by the inode and size of their file, to recognize a renamed file
+    /// whose tags were edited as well
+    missing_inodes: HashMap<(i64, i64), u32>,
+    /// Files skipped as a copy of a track that are gone, forgotten once every file was
+    /// read
+    gone_duplicates: Vec<String>,
+    summary: ScanSummary,
+    /// Files that were written or skipped
+    processed: usize,
+}
+
+impl<'a> Writer<'a> {
+    fn new(
+        db: &'a db::Database,
+        music_folder: &'a Path,
+        changed_albums: HashSet<u32>,
+        missing: Vec<TrackFile>,
+        moved_away: Vec<TrackFile>,
+    ) -> Result<Self, db::Error> {
+        let albums = db.all::<Albums>()?;
+
+        let missing_inodes = missing
+            .iter()
+            .chain(&moved_away)
+            .filter_map(|track| {
+                let file = track.file?;
+                Some(((file.inode?, file.size), track.id))
+            })
+            .collect();
+
+        Ok(Self {
+            db,
+            music_folder,
+            artists: db
+                .all::<Artists>()?
+                .into_iter()
+                .map(|a| (a.name, a.id))
+                .collect(),
+            albums: albums
+                .iter()
+                .map(|a| ((a.artist_id, a.name.clone()), a.id))
+                .collect(),
+            album_covers: albums
+                .iter()
+                .map(|a| (a.id, a.cover_path.clone()))
+                .collect(),
+            album_paths: albums.into_iter().map(|a| (a.id, a.path)).collect(),
+            changed_albums,
+            moved_away: moved_away.iter().map(|track| track.id).collect(),
+            missing: missing
+                .into_iter()
+                .chain(moved_away)
+                .map(|track| (track.id, track))
+                .collect(),
+            missing_inodes,
+            gone_duplicates: Vec::new(),
+            summary: ScanSummary::default(),
+            processed: 0,
+        })
+    }
+
+    fn run(
+        &mut self,
+        receiver: &Receiver<Parsed>,
+        cancelled: &AtomicBool,
+        on_event: &Channel<MetadataEvent>,
+    ) -> anyhow::Result<ImportOutcome> {
+        let mut pending = Vec::with_capacity(BATCH_SIZE);
+        let mut last_flush = Instant::now();
+        let mut reported = 0;
+
+        loop {
+            let timeout = FLUSH_INTERVAL.saturating_sub(last_flush.elapsed());
+            let disconnected = match receiver.recv_timeout(timeout) {
+                Ok(parsed) => {
+                    pending.push(parsed);
+                    false
+                }
+                Err(RecvTimeoutError::Timeout) => false,
+                Err(RecvTimeoutError::Disconnected) => true,
+            };
+
+            let is_cancelled = cancelled.load(Ordering::Relaxed);
+
+            if pending.len() >= BATCH_SIZE
+                || last_flush.elapsed() >= FLUSH_INTERVAL
+                || disconnected
+                || is_cancelled
+            {
+                self.flush(&mut pending)?;
+                last_flush = Instant::now();
+
+                if self.processed != reported {
+                    reported = self.processed;
+                    on_event.send(MetadataEvent::Progress {
+                        id: EVENT_ID,
+                        current: reported,
+                    })?;
+                }
+            }
+
+            if is_cancelled {
+                return Ok(ImportOutcome::Cancelled);
+            }
+
+            if disconnected {
+                // Every file was read, so tracks that weren't found elsewhere are gone
+                self.remove_missing()?;
+                return Ok(ImportOutcome::Finished(self.summary));
+            }
+        }
+    }
+
+    /// Write every pending track in a single transaction
+    fn flush(&mut self, pending: &mut Vec<Parsed>) -> anyhow::Result<()> {
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let db = self.db;
+        db.write_batch(|batch| {
+            pending.iter().try_for_each(|parsed| match parsed {
+                Parsed::Track(track) => self.write_track(batch, track),
+                Parsed::Skipped {
+                    track_id: Some(track_id),
+                } => {
+                    batch.delete_track(*track_id)?;
+                    self.summary.removed += 1;
+                    Ok(())
+                }
+                Parsed::Unreadable {
+                    track_id: None,
+                    file,
+                } => {
+                    // A file that was moved keeps the track it was moved from until it
+                    // can be read
+                    self.moved_from(&[], *file);
+                    Ok(())
+                }
+                Parsed::Skipped { track_id: None } | Parsed::Unreadable { .. } => Ok(()),
+            })
+        })?;
+
+        self.processed += pending.len();
+        pending.clear();
+
+        Ok(())
+    }
+
+    fn write_track(&mut self, batch: &db::Batch, track: &ParsedTrack) -> anyhow::Result<()> {
+        let track_path = &track.path;
+
+        let artist_id = self
+            .artist_id(batch, &track.artist)
+            .with_context(|| format!("Failed to insert artist for {}", track_path.display()))?;
+        let album_artist_id = self
+            .artist_id(batch, &track.album_artist)
+            .with_context(|| {
+                format!("Failed to insert album artist for {}", track_path.display())
+            })?;
+
+        let (album_id, cover_path) = self.album(batch, track, album_artist_id)?;
+
+        let stream = track.stream;
+        let new_track = NewTrack {
+            duration: track.duration.round() as u32,
+            album_name: &track.album,
+            album_id,
+            artist_name: &track.artist,
+            artist_id,
+            name: &track.name,
+            number: track.track_number.map_or(-1, |n| n as i32),
+            path: &track_path.to_string_lossy(),
+            cover_path: &cover_path,
+            disc_number: track.disc_number.unwrap_or(1),
+            track_total: track.track_total,
+            disc_total: track.disc_total,
+            genre: track.genre.as_deref(),
+            composer: track.composer.as_deref(),
+            release_date: track.release_date.as_deref(),
+            codec: (stream.codec != Codec::Unknown).then(|| stream.codec.name()),
+            sample_rate: (stream.sample_rate > 0).then_some(stream.sample_rate),
+            bit_depth: stream.bit_depth.map(u32::from),
+            channels: (stream.channels > 0).then_some(u32::from(stream.channels)),
+            bitrate: (stream.bitrate > 0).then_some(stream.bitrate),
+            lossless: stream.codec.is_lossless(),
+            file: track.file,
+        };
+
+        // Tracks are told apart by their file, the hash only recognizes copies of a track
+        let same_tracks = batch.tracks_by_hash(&new_track.make_hash())?;
+        let moved_from = match track.track_id {
+            Some(_) => None,
+            None => self.moved_from(&same_tracks, track.file),
+        };
+
+        let path = new_track.path;
+        let file = new_track.file;
+
+        // Edited tags update the track imported from the file, even if they now match
+        // another track's. Files skipped as copies of it are read again, as they might not
+        // be copies anymore.
+        let track_id = match track.track_id.or(moved_from) {
+            Some(track_id) => {
+                batch
+                    .update_track(track_id, new_track)
+                    .and_then(|()| batch.delete_track_artists(track_id))
+                    .and_then(|()| batch.delete_duplicates_of(track_id))
+                    .and_then(|()| batch.delete_duplicate_file(path))
+                    .with_context(|| {
+                        format!("Failed to update track for {}", track_path.display())
+                    })?;
+
+                if moved_from.is_some() {
+                    self.relocate_album(batch, album_id, track_path)
+                        .with_context(|| {
+                            format!("Failed to move album for {}", track_path.display())
+                        })?;
+                    self.summary.moved += 1;
+                } else {
+                    self.summary.updated += 1;
+                }
+
+                track_id
+            }
+            // A copy of a track that's already in the library
+            None if !same_tracks.is_empty() => {
+                batch
+                    .insert_duplicate_file(path, same_tracks[0], file)
+                    .with_context(|| format!("Failed to skip copy {}", track_path.display()))?;
+                self.summary.duplicates += 1;
+                return Ok(());
+            }
+            None => {
+                let track_id = batch
+                    .insert::<NewTrack>(new_track)
+                    .and_then(|track_id| {
+                        batch.delete_duplicate_file(path)?;
+                        Ok(track_id)
+                    })
+                    .with_context(|| {
+                        format!("Failed to insert track for {}", track_path.display())
+                    })?;
+                self.summary.added += 1;
+                track_id
+            }
+        };
+
+        self.credit_artists(batch, track, artist_id)
+            .and_then(|credits| batch.insert_track_artists(track_id, &credits))
+            .with_context(|| format!("Failed to credit artists for {}", track_path.display()))?;
+
+        self.changed_albums.insert(album_id);
+
+        Ok(())
+    }
+
+    /// The album of a track and its cover, which is created on the first track of it
+    fn album(
+        &mut self,
+        batch: &db::Batch,
+        track: &ParsedTrack,
+        album_artist_id: u32,
+    ) -> anyhow::Result<(u32, String)> {
+        let track_path = &track.path;
+        let key = (album_artist_id, track.album.clone());
+
+        if let Some(&id) = self.albums.get(&key) {
+            let cover_path = self.album_covers.get(&id).unwrap();
+            return Ok((id, cover_path.clone()));
+        }
+
+        let album_path = get_album_path(self.music_folder, track_path);
+        let mut cover_path = get_cover_path(&track.album_artist, &track.album);
+
+        if !Path::new(&cover_path).exists() {
+            if let Some(cover) = &track.cover {
+                fs::write(&cover_path, cover).with_context(|| {
+                    format!(
+                        "Failed to write the raw picture data to disk for {}",
+                        track_path.display()
+                    )
+                })?;
+            } else {
+                let album_path = Path::new(&album_path);
+                if album_path.join("cover.jpg").exists() {
+                    fs::copy(album_path.join("cover.jpg"), &cover_path).with_context(|| {
+                        format!("Failed to copy cover.jpg for {}", track_path.display())
+                    })?;
+                } else if album_path.join("cover.png").exists() {
+                    fs::copy(album_path.join("cover.png"), &cover_path).with_context(|| {
+                        format!("Failed to copy cover.png for {}", track_path.display())
+                    })?;
+                } else {
+                    cover_path = data_path()
+                        .join("covers")
+                        .join("placeholder.png")
+                        .to_str()
+                        .unwrap()
+                        .to_owned();
+                }
+            }
+        }
+
+        let id = batch
+            .insert_album::<NewAlbum>(NewAlbum {
+                artist_id: album_artist_id,
+                artist_name: &track.album_artist,
+                name: &track.album,
+                cover_path: &cover_path,
+                year: track.year,
+                album_type: &AlbumType::Unknown,
+                track_count: 0,
+                duration: 0,
+                path: &album_path.to_string_lossy(),
+                compilation: track.compilation,
+            })
+            .with_context(|| format!("Failed to insert album for {}", track_path.display()))?;
+
+        self.albums.insert(key, id);
+        self.album_covers.insert(id, cover_path.clone());
+
+        Ok((id, cover_path))
+    }
+
+    fn artist_id(&mut self, batch: &db::Batch, name: &str) -> Result<u32, db::Error> {
+        if let Some(&id) = self.artists.get(name) {
+            return Ok(id);
+        }
+
+        let id = batch.insert::<NewArtist>(NewArtist { name })?;
+        self.artists.insert(name.to_owned(), id);
+
+        Ok(id)
+    }
+
+    /// The missing track a new file was moved from, matched by its hash, or by the
+    /// inode and size of its file in case its tags changed too
+    fn moved_from(&mut self, same_tracks: &[u32], file: FileStamp) -> Option<u32> {
+        let track_id = same_tracks
+            .iter()
+            .copied()
+            .find(|id| self.missing.contains_key(id))
+            .or_else(|| {
+                let id = *self.missing_inodes.get(&(file.inode?, file.size))?;
+                self.missing.contains_key(&id).then_some(id)
+            })?;
+
+        self.missing.remove(&track_id);
+        Some(track_id)
+    }
+
+    /// Point an album at the folder a track of it was moved to, once the folder it was
+    /// in is gone
+    fn relocate_album(
+        &mut self,
+        batch: &db::Batch,
+        album_id: u32,
+        track_path: &Path,
+    ) -> Result<(), db::Error> {
+        let album_path = get_album_path(self.music_folder, track_path)
+            .to_string_lossy()
+            .into_owned();
+
+        if self
+            .album_paths
+            .get(&album_id)
+            .is_some_and(|path| *path == album_path || Path::new(path).is_dir())
+        {
+            return Ok(());
+        }
+
+        batch.update_album_path(album_id, &album_path)?;
+        self.album_paths.insert(album_id, album_path);
+
+        Ok(())
+    }
+
+    /// Remove tracks of the music folder whose file is gone, and wasn't moved, and forget
+    /// copies that are gone
+    fn remove_missing(&mut self) -> Result<(), db::Error> {
+        // Another folder's drive might just be unmounted, its tracks are left as is
+        self.missing
+            .retain(|track_id, _| !self.moved_away.contains(track_id));
+
+        self.db.write_batch(|batch| {
+            self.missing
+                .keys()
+                .try_for_each(|&track_id| batch.delete_track(track_id))
+        })?;
+        self.summary.removed += self.missing.len();
+        self.missing.clear();
+
+        self.db.write_batch(|batch| {
+            self.gone_duplicates
+                .iter()
+                .try_for_each(|path| batch.delete_duplicate_file(path))
+        })?;
+        self.gone_duplicates.clear();
+
+        Ok(())
+    }
+
+    /// Remove albums and artists that were left without tracks, and bring the type and
+    /// duration of the changed albums that are left up to date
+    fn tidy_albums(&self) -> Result<(), db::Error> {
+        // Stored album covers aren't cleaned up upon album deletion.
+        self.db.delete_orphans()?;
+
+        for &album_id in &self.changed_albums {
+            let (total_duration, track_count) = self.db.get_album_duration(album_id)?;
+            if track_count > 0 {
+                let album_type = AlbumType::get(track_count, total_duration);
+                self.db
+                    .update_album_type(album_id, album_type, total_duration, track_count)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Every artist credited on a track, the primary artist being the track artist
+    fn credit_artists(
+        &mut self,
+        batch: &db::Batch,
+        track: &ParsedTrack,
+        artist_id: u32,
+    ) -> Result<Vec<(u32, ArtistRole)>, db::Error> {
+        let mut credits = vec![(artist_id, ArtistRole::Primary)];
+
+        let featured = track.artists.iter().skip(1);
+        let remixers = track.remixers.iter();
+        let composers = track.composers.iter();
+
+        for (name, role) in featured
+            .map(|name| (name, ArtistRole::Featured))
+            .chain(remixers.map(|name| (name, ArtistRole::Remixer)))
+            .chain(composers.map(|name| (name, ArtistRole::Composer)))
+        {
+            let id = self.artist_id(batch, name)?;
+            if !credits.contains(&(id, role)) {
+                credits.push((id, role));
+            }
+        }
+
+        Ok(credits)
+    }
+}
+
+fn get_cover_path(artist: &str, album: &str) -> String {
+    // have to sanitize the artist and album names to avoid issues with file paths
+    let p = data_path().to_str().unwrap().to_owned();
+    p + "/covers/" + &sanitize_string(artist) + " - " + &sanitize_string(album) + ".jpg"
+}
+
+fn get_album_path(music_folder: &Path, full_path: &Path) -> PathBuf {
+    let rel = full_path.strip_prefix(music_folder).unwrap();
+
+    let comps: Vec<_> = rel.parent().unwrap().components().collect();
+
+    match comps.len() {
+        0 => music_folder.to_path_buf(),
+        1 => music_folder.join(comps[0]), // only album
+        _ => music_folder.join(comps[0]).join(comps[1]), // artist/album or album/subfolder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{NewPlaylist, Tracks};
+    use metadata_audio::flac_writer::VorbisCommentBlock;
+    use std::{sync::Arc, time::SystemTime};
+    use tauri::ipc::InvokeResponseBody;
+
+    /// A music folder in the temp directory, removed once dropped
+    struct Library(PathBuf);
+
+    impl Library {
+        fn new(name: &str) -> Library {
+            let path = std::env::temp_dir().join(format!("import_{}_{name}", std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            Library(path)
+        }
+
+        /// Write a FLAC file holding `tags` to `path` in the library
+        fn add(&self, path: &str, tags: &[(&str, &str)]) -> PathBuf {
+            let path = self.0.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, flac(tags)).unwrap();
+            path
+        }
+    }
+
+    impl Drop for Library {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// A FLAC file of 10 seconds without any audio, which is all the tags are read from
+    fn flac(tags: &[(&str, &str)]) -> Vec<u8> {
+        // 44.1 kHz, 2 channels, 16 bits, 441000 samples
+        let mut stream_info = vec![0u8; 34];
+        stream_info[10..18].copy_from_slice(&[0x0A, 0xC4, 0x42, 0xF0, 0x00, 0x06, 0xBA, 0xA8]);
+
+        let comment = VorbisCommentBlock {
+            vendor: String::new(),
+            comments: tags
+                .iter()
+                .map(|(key, value)| ((*key).to_owned(), (*value).to_owned()))
+                .collect(),
+        };
+
+        let mut file = b"fLaC".to_vec();
+        for (block_type, data) in [(0, stream_info), (0x80 | 4, comment.to_bytes())] {
+            file.push(block_type);
+            file.extend_from_slice(&(data.len() as u32).to_be_bytes()[1..]);
+            file.extend(data);
+        }
+
+        file
+    }
+
+    fn tags<'a>(album: &'a str, name: &'a str, number: &'a str) -> [(&'a str, &'a str); 4] {
+        [
+            ("ARTIST", "Artist"),
+            ("ALBUM", album),
+            ("TITLE", name),
+            ("TRACKNUMBER", number),
+        ]
+    }
+
+    fn import(db: &db::Database, library: &Library) -> ImportOutcome {
+        let on_event = Channel::new(|_| Ok(()));
+        import_library(db, &library.0, &AtomicBool::new(false), &on_event).unwrap()
+    }
+
+    fn summary(db: &db::Database, library: &Library) -> ScanSummary {
+        match import(db, library) {
+            ImportOutcome::Finished(summary) => summary,
+            ImportOutcome::Cancelled => panic!("Import was cancelled"),
+        }
+    }
+
+    /// How many files a rescan read, as reported before reading them
+    fn files_read(db: &db::Database, library: &Library) -> usize {
+        let total = Arc::new(AtomicUsize::new(0));
+        let on_event = Channel::new({
+            let total = total.clone();
+            move |body| {
+                if let InvokeResponseBody::Json(json) = body {
+                    let event: serde_json::Value = serde_json::from_str(&json).unwrap();
+                    if event["event"] == "Total" {
+                        let files = event["data"]["total"].as_u64().unwrap();
+                        total.store(files as usize, Ordering::Relaxed);
+                    }
+                }
+                Ok(())
+            }
+        });
+
+        import_library(db, &library.0, &AtomicBool::new(false), &on_event).unwrap();
+        total.load(Ordering::Relaxed)
+    }
+
+    fn modified(path: &Path) -> SystemTime {
+        fs::metadata(path).unwrap().modified().unwrap()
+    }
+
+    fn set_modified(path: &Path, modified: SystemTime) {
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    /// A playlist holding the given tracks
+    fn playlist(db: &db::Database, track_ids: &[u32]) -> u32 {
+        let playlist_id = db
+            .insert(NewPlaylist {
+                name: "Playlist",
+                description: "",
+                cover_path: "",
+            })
+            .unwrap();
+
+        for track_id in track_ids {
+            db.insert_track_to_playlist(&playlist_id, track_id).unwrap();
+        }
+
+        playlist_id
+    }
+
+    fn playlist_track_ids(db: &db::Database, playlist_id: u32) -> Vec<u32> {
+        let playlist = db.get_playlist_with_tracks(&playlist_id).unwrap();
+        let mut track_ids: Vec<u32> = playlist.tracks.iter().map(|track| track.id).collect();
+        track_ids.sort_unstable();
+        track_ids
+    }
+
+    fn track_by_name(db: &db::Database, name: &str) -> Tracks {
+        db.all::<Tracks>()
+            .unwrap()
+            .into_iter()
+            .find(|track| track.name == name)
+            .unwrap_or_else(|| panic!("No track named {name}"))
+    }
+
+    #[test]
+    fn imports_music_folder() {
+        let library = Library::new("folder");
+        library.add("Artist/Album/01.flac", &tags("Album", "First", "1"));
+        library.add("Artist/Album/02.flac", &tags("Album", "Second", "2"));
+        library.add(
+            "Artist/Single/01.flac",
+            &[
+                ("ARTIST", "Artist"),
+                ("ARTIST", "Guest"),
+                ("ALBUM", "Single"),
+                ("TITLE", "Only"),
+                ("DISCNUMBER", "2"),
+            ],
+        );
+        // Missing the tags a track needs, and not audio at all
+        library.add("Artist/Album/03.flac", &[("TITLE", "Untitled")]);
+        fs::write(library.0.join("Artist/Album/notes.txt"), "notes").unwrap();
+
+        let db = db::Database::options().build().unwrap();
+        assert_eq!(
+            summary(&db, &library),
+            ScanSummary {
+                added: 3,
+                ..ScanSummary::default()
+            }
+        );
+
+        assert_eq!(db.rows::<Tracks>().unwrap(), 3);
+        assert_eq!(db.rows::<Artists>().unwrap(), 2);
+
+        let first = track_by_name(&db, "First");
+        assert_eq!(first.album_name, "Album");
+        assert_eq!(first.artist_name, "Artist");
+        assert_eq!(first.number, 1);
+        assert_eq!(first.duration, 10);
+        assert_eq!(first.codec.as_deref(), Some("FLAC"));
+        assert!(first.available);
+
+        let album = db.by_id::<Albums>(&first.album_id).unwrap();
+        assert_eq!(album.track_count, 2);
+        assert_eq!(album.duration, 20);
+        assert_eq!(album.path, library.0.join("Artist/Album").to_string_lossy());
+
+        let only = track_by_name(&db, "Only");
+        assert_eq!(only.number, -1);
+        assert_eq!(only.disc_number, 2);
+        assert_ne!(only.album_id, first.album_id);
+        assert_eq!(db.track_artist_ids(only.id).unwrap().len(), 2);
+
+        // Nothing changed since
+        assert_eq!(
+            summary(&db, &library),
+            ScanSummary {
+                unchanged: 3,
+                ..ScanSummary::default()
+            }
+        );
+    }
+
+    #[test]
+    fn writes_more_tracks_than_fit_a_batch() {
+        let library = Library::new("batches");
+        let total = BATCH_SIZE * 2 + 10;
+        for i in 0..total {
+            let (album, name) = (format!("Album {}", i / 100), format!("Track {i}"));
+            library.add(&format!("{album}/{i}.flac"), &tags(&album, &name, "1"));
+        }
+
+        let db = db::Database::options().build().unwrap();
+        assert_eq!(summary(&db, &library).added, total);
+
+        let tracks = db.all::<Tracks>().unwrap();
+        let ids: HashSet<u32> = tracks.iter().map(|track| track.id).collect();
+        assert_eq!(ids.len(), total);
+
+        // Every track got the ID of its own row back
+        for track in tracks {
+            let i: usize = track.name.trim_start_matches("Track ").parse().unwrap();
+            assert!(
+                track.path.ends_with(&format!("/{i}.flac")),
+                "{}",
+                track.path
+            );
+        }
+    }
+
+    #[test]
+    fn cancelled_import_keeps_written_tracks() {
+        let library = Library::new("cancelled");
+        let total = BATCH_SIZE * 2 + 10;
+        for i in 0..total {
+            let (album, name) = (format!("Album {}", i / 100), format!("Track {i}"));
+            library.add(&format!("{album}/{i}.flac"), &tags(&album, &name, "1"));
+        }
+
+        // Cancelled as soon as the first batch is written
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let on_event = Channel::new({
+            let cancelled = cancelled.clone();
+            move |body| {
+                if let InvokeResponseBody::Json(json) = body
+                    && json.contains("Progress")
+                {
+                    cancelled.store(true, Ordering::Relaxed);
+                }
+                Ok(())
+            }
+        });
+
+        let db = db::Database::options().build().unwrap();
+        let outcome = import_library(&db, &library.0, &cancelled, &on_event).unwrap();
+        assert_eq!(outcome, ImportOutcome::Cancelled);
+
+        let written = db.rows::<Tracks>().unwrap() as usize;
+        assert!(written > 0 && written < total, "{written} of {total}");
+
+        // Picked up where it was left
+        assert_eq!(
+            summary(&db, &library),
+            ScanSummary {
+                added: total - written,
+                unchanged: written,
+                ..ScanSummary::default()
+            }
+        );
+        assert_eq!(db.rows::<Tracks>().unwrap() as usize, total);
+    }
+
+    #[test]
+    fn rescan_only_reads_changed_files() {
+        let library = Library::new("rescan");
+        let kept = library.add("Album/01.flac", &tags("Album", "Kept", "1"));
+        let resized = library.add("Album/02.flac", &tags("Album", "Resized", "2"));
+        let touched = library.add("Album/03.flac", &tags("Album", "Touched", "3"));
+        let deleted = library.add("Album/04.flac", &tags("Album", "Deleted", "4"));
+
+        let db = db::Database::options().build().unwrap();
+        summary(&db, &library);
+        let ids: Vec<u32> = ["Kept", "Resized", "Touched"]
+            .iter()
+            .map(|name| track_by_name(&db, name).id)
+            .collect();
+
+        // Same size and modification time, so the new tags aren't read
+        let kept_modified = modified(&kept);
+        fs::write(&kept, flac(&tags("Album", "Kipt", "1"))).unwrap();
+        set_modified(&kept, kept_modified);
+
+        fs::write(&resized, flac(&tags("Album", "Resized Longer", "2"))).unwrap();
+        set_modified(&resized, modified(&kept));
+
+        fs::write(&touched, flac(&tags("Album", "Tauched", "3"))).unwrap();
+        set_modified(&touched, modified(&touched) + Duration::from_secs(60));
+
+        fs::remove_file(&deleted).unwrap();
+        library.add("Album/05.flac", &tags("Album", "Added", "5"));
+
+        assert_eq!(
+            summary(&db, &library),
+            ScanSummary {
+                added: 1,
+                updated: 2,
+                removed: 1,
+                unchanged: 1,
+                ..ScanSummary::default()
+            }
+        );
+
+        assert_eq!(track_by_name(&db, "Kept").id, ids[0]);
+        assert_eq!(track_by_name(&db, "Resized Longer").id, ids[1]);
+        assert_eq!(track_by_name(&db, "Tauched").id, ids[2]);
+        assert_eq!(db.rows::<Tracks>().unwrap(), 4);
+        assert!(
+            db.all::<Tracks>()
+                .unwrap()
+                .iter()
+                .all(|track| track.name != "Deleted")
+        );
+
+        let album_id = track_by_name(&db, "Kept").album_id;
+        let album = db.by_id::<Albums>(&album_id).unwrap();
+        assert_eq!(album.track_count, 4);
+    }
+
+    #[test]
+    fn unreadable_file_keeps_its_track() {
+        let library = Library::new("unreadable");
+        let path = library.add("Album/01.flac", &tags("Album", "First", "1"));
+
+        let db = db::Database::options().build().unwrap();
+        summary(&db, &library);
+        let track_id = track_by_name(&db, "First").id;
+        let playlist_id = playlist(&db, &[track_id]);
+
+        // Cut off halfway through, like a file that's still being copied
+        let data = fs::read(&path).unwrap();
+        fs::write(&path, &data[..20]).unwrap();
+
+        assert_eq!(summary(&db, &library), ScanSummary::default());
+        assert_eq!(track_by_name(&db, "First").id, track_id);
+        assert_eq!(playlist_track_ids(&db, playlist_id), vec![track_id]);
+
+        // Read again once it's whole, as its stamp wasn't stored
+        fs::write(&path, flac(&tags("Album", "Edited", "1"))).unwrap();
+
+        assert_eq!(
+            summary(&db, &library),
+            ScanSummary {
+                updated: 1,
+                ..ScanSummary::default()
+            }
+        );
+        assert_eq!(track_by_name(&db, "Edited").id, track_id);
+        assert_eq!(playlist_track_ids(&db, playlist_id), vec![track_id]);
+    }
+
+    #[test]
+    fn file_without_tags_removes_its_track() {
+        let library = Library::new("untagged");
+        let path = library.add("Album/01.flac", &tags("Album", "First", "1"));
+        library.add("Album/02.flac", &tags("Album", "Second", "2"));
+
+        let db = db::Database::options().build().unwrap();
+        summary(&db, &library);
+        let track_id = track_by_name(&db, "First").id;
+        let playlist_id = playlist(&db, &[track_id]);
+
+        fs::write(&path, flac(&[("TITLE", "First")])).unwrap();
+
+        assert_eq!(
+            summary(&db, &library),
+            ScanSummary {
+                removed: 1,
+                unchanged: 1,
+                ..ScanSummary::default()
+            }
+        );
+        assert!(db.by_id::<Tracks>(&track_id).is_err());
+        assert!(playlist_track_ids(&db, playlist_id).is_empty());
+    }
+
+    #[test]
+    fn tracks_of_other_folders_are_left_alone() {
+        let first = Library::new("first_folder");
+        first.add("Album/01.flac", &tags("Album", "First", "1"));
+        first.add("Album/02.flac", &tags("Album", "Second", "2"));
+        let second = Library::new("second_folder");
+        second.add("Other/01.flac", &tags("Other", "Third", "1"));
+
+        let db = db::Database::options().build().unwrap();
+        summary(&db, &first);
+
+        // Like an import from the folder picker, while the first folder is watched
+        assert_eq!(
+            summary(&db, &second),
+            ScanSummary {
+                added: 1,
+                ..ScanSummary::default()
+            }
+        );
+        assert_eq!(
+            summary(&db, &first),
+            ScanSummary {
+                unchanged: 2,
+                ..ScanSummary::default()
+            }
+        );
+        assert_eq!(db.rows::<Tracks>().unwrap(), 3);
+    }
+
+    #[test]
+    fn tracks_of_unmounted_folders_are_kept() {
+        let first = Library::new("mounted_folder");
+        first.add("Album/01.flac", &tags("Album", "First", "1"));
+        let second = Library::new("unmounted_folder");
+        second.add("Other/01.flac", &tags("Other", "Second", "1"));
+        second.add("Other/02.flac", &tags("Other", "Third", "2"));
+
+        let db = db::Database::options().build().unwrap();
+        summary(&db, &first);
+        summary(&db, &second);
+        let track_ids = [
+            track_by_name(&db, "Second").id,
+            track_by_name(&db, "Third").id,
+        ];
+        let playlist_id = playlist(&db, &track_ids);
+
+        // Like the drive of the second folder being unmounted
+        fs::remove_dir_all(&second.0).unwrap();
+
+        assert_eq!(
+            summary(&db, &first),
+            ScanSummary {
+                unchanged: 1,
+                ..ScanSummary::default()
+            }
+        );
+        assert_eq!(db.rows::<Tracks>().unwrap(), 3);
+        assert_eq!(playlist_track_ids(&db, playlist_id), track_ids);
+    }
+
+    #[test]
+    fn moved_music_folder_keeps_its_tracks() {
+        let first = Library::new("moved_from");
+        first.add("Album/01.flac", &tags("Album", "First", "1"));
+        first.add("Album/02.flac", &tags("Album", "Second", "2"));
+
+        let db = db::Database::options().build().unwrap();
+        summary(&db, &first);
+        let track_ids = [
+            track_by_name(&db, "First").id,
+            track_by_name(&db, "Second").id,
+        ];
+        let playlist_id = playlist(&db, &track_ids);
+
+        let second = Library::new("moved_to");
+        fs::remove_dir(&second.0).unwrap();
+        fs::rename(&first.0, &second.0).unwrap();
+
+        assert_eq!(
+            summary(&db, &second),
+            ScanSummary {
+                moved: 2,
+                ..ScanSummary::default()
+            }
+        );
+        assert_eq!(playlist_track_ids(&db, playlist_id), track_ids);
+
+        let track = db.by_id::<Tracks>(&track_ids[0]).unwrap();
+        assert!(track.path.starts_with(&*second.0.to_string_lossy()));
+        let album = db.by_id::<Albums>(&track.album_id).unwrap();
+        assert_eq!(album.path, second.0.join("Album").to_string_lossy());
+    }
+
+    #[test]
+    fn renamed_file_keeps_its_track() {
+        let library = Library::new("renamed");
+        let path = library.add("Album/01.flac", &tags("Album", "First", "1"));
+        library.add("Album/02.flac", &tags("Album", "Second", "2"));
+
+        let db = db::Database::options().build().unwrap();
+        summary(&db, &library);
+        let track_id = track_by_name(&db, "First").id;
+        let playlist_id = playlist(&db, &[track_id]);
+
+        let renamed = library.0.join("Album/01 - First.flac");
+        fs::rename(&path, &renamed).unwrap();
+
+        assert_eq!(
+            summary(&db, &library),
+            ScanSummary {
+                moved: 1,
+                unchanged: 1,
+                ..ScanSummary::default()
+            }
+        );
+        assert_eq!(db.rows::<Tracks>().unwrap(), 2);
+        assert_eq!(playlist_track_ids(&db, playlist_id), vec![track_id]);
+        assert_eq!(
+            db.by_id::<Tracks>(&track_id).unwrap().path,
+            renamed.to_string_lossy()
+        );
+    }
+
+    #[test]
+    fn moved_album_keeps_its_tracks() {
+        let library = Library::new("moved_album");
+        library.add("Artist/Album/01.flac", &tags("Album", "First", "1"));
+        library.add("Artist/Album/02.flac", &tags("Album", "Second", "2"));
+
+        let db = db::Database::options().build().unwrap();
+        summary(&db, &library);
+        let mut track_ids = [
+            track_by_name(&db, "First").id,
+            track_by_name(&db, "Second").id,
+        ];
+        track_ids.sort_unstable();
+        let playlist_id = playlist(&db, &track_ids);
+        let album_id = track_by_name(&db, "First").album_id;
+
+        fs::rename(
+            library.0.join("Artist/Album"),
+            library.0.join("Artist/Album (2005)"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            summary(&db, &library),
+            ScanSummary {
+                moved: 2,
+                ..ScanSummary::default()
+            }
+        );
+        assert_eq!(playlist_track_ids(&db, playlist_id), track_ids);
+        assert_eq!(track_by_name(&db, "First").album_id, album_id);
+        assert_eq!(
+            db.by_id::<Albums>(&album_id).unwrap().path,
+            library.0.join("Artist/Album (2005)").to_string_lossy()
+        );
+    }
+
+    #[test]
+    fn renamed_file_with_edited_tags_keeps_its_track() {
+        let library = Library::new("renamed_edited");
+        let path = library.add("Album/01.flac", &tags("Album", "First", "1"));
+
+        let db = db::Database::options().build().unwrap();
+        summary(&db, &library);
+        let track_id = track_by_name(&db, "First").id;
+        let playlist_id = playlist(&db, &[track_id]);
+
+        // Edited in place, like a tag editor does with padding to spare, so only the
+        // inode and size still match
+        let renamed = library.0.join("Album/01 - Fifth.flac");
+        fs::rename(&path, &renamed).unwrap();
+        fs::write(&renamed, flac(&tags("Album", "Fifth", "1"))).unwrap();
+
+        assert_eq!(
+            summary(&db, &library),
+            ScanSummary {
+                moved: 1,
+                ..ScanSummary::default()
+            }
+        );
+        assert_eq!(track_by_name(&db, "Fifth").id, track_id);
+        assert_eq!(playlist_track_ids(&db, playlist_id), vec![track_id]);
+    }
+
+    #[test]
+    fn edited_tags_update_the_track() {
+        let library = Library::new("edited");
+        library.add("Album/01.flac", &tags("Album", "First", "1"));
+        let path = library.add("Album/02.flac", &tags("Album", "Second", "2"));
+
+        let db = db::Database::options().build().unwrap();
+        summary(&db, &library);
+        let first_id = track_by_name(&db, "First").id;
+        let track_id = track_by_name(&db, "Second").id;
+        let playlist_id = playlist(&db, &[track_id]);
+
+        // Now the same track as the first one, as far as the hash can tell
+        let modified = modified(&path);
+        fs::write(&path, flac(&tags("Album", "First", "2"))).unwrap();
+        set_modified(&path, modified + Duration::from_secs(60));
+
+        assert_eq!(
+            summary(&db, &library),
+            ScanSummary {
+                updated: 1,
+                unchanged: 1,
+                ..ScanSummary::default()
+            }
+        );
+
+        let track = db.by_id::<Tracks>(&track_id).unwrap();
+        assert_eq!(track.name, "First");
+        assert_eq!(track.number, 2);
+        assert_eq!(db.by_id::<Tracks>(&first_id).unwrap().number, 1);
+        assert_eq!(db.rows::<Tracks>().unwrap(), 2);
+        assert_eq!(playlist_track_ids(&db, playlist_id), vec![track_id]);
+    }
+
+    #[test]
+    fn copies_are_skipped() {
+        let library = Library::new("copies");
+        let path = library.add("Album/01.flac", &tags("Album", "First", "1"));
+
+        let db = db::Database::options().build().unwrap();
+        summary(&db, &library);
+        let track_id = track_by_name(&db, "First").id;
+
+        fs::create_dir(library.0.join("Backup")).unwrap();
+        fs::copy(&path, library.0.join("Backup/01.flac")).unwrap();
+
+        let skipped = ScanSummary {
+            unchanged: 1,
+            duplicates: 1,
+            ..ScanSummary::default()
+        };
+        assert_eq!(summary(&db, &library), skipped);
+
+        let tracks = db.all::<Tracks>().unwrap();
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].id, track_id);
+        assert_eq!(tracks[0].path, path.to_string_lossy());
+
+        // The copy isn't read again until it changes
+        assert_eq!(files_read(&db, &library), 0);
+        assert_eq!(summary(&db, &library), skipped);
+    }
+
+    #[test]
+    fn copy_takes_over_removed_track() {
+        let library = Library::new("copy_of_removed");
+        let path = library.add("Album/01.flac", &tags("Album", "First", "1"));
+
+        let db = db::Database::options().build().unwrap();
+        summary(&db, &library);
+        let track_id = track_by_name(&db, "First").id;
+        let playlist_id = playlist(&db, &[track_id]);
+
+        let copy = library.0.join("Album/02.flac");
+        fs::copy(&path, &copy).unwrap();
+        summary(&db, &library);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            summary(&db, &library),
+            ScanSummary {
+                moved: 1,
+                ..ScanSummary::default()
+            }
+        );
+        assert_eq!(
+            db.by_id::<Tracks>(&track_id).unwrap().path,
+            copy.to_string_lossy()
+        );
+        assert_eq!(playlist_track_ids(&db, playlist_id), vec![track_id]);
+    }
+}
@@ -1,4 +1,6 @@
+pub mod import;
 pub mod lastfm;
 pub mod player;
 pub mod ui;
 pub mod utils;
+pub mod watcher;
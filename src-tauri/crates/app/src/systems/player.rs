@@ -275,6 +275,16 @@ impl PlayerEvent {
     ) -> Result<(), FrontendError> {
         let state = handle.state::<VeilState>();
 
+        {
+            let queue = lock_or_log(state.queue.lock(), "Queue Mutex")?;
+            if !track.available || !queue.is_available(track.id) {
+                return Err(FrontendError::Player(format!(
+                    "{} can't be played, its music folder can't be reached",
+                    track.name
+                )));
+            }
+        }
+
         let should_scrobble = {
             let mut player = lock_or_log(state.player.write(), "Player Write Lock")?;
             player.should_scrobble()
@@ -374,8 +384,13 @@ impl PlayerEvent {
     ) -> Result<(), FrontendError> {
         let state = handle.state::<VeilState>();
         let track_id = {
-            let queue = lock_or_log(state.queue.lock(), "Queue Mutex").unwrap();
-            queue.current()
+            let mut queue = lock_or_log(state.queue.lock(), "Queue Mutex").unwrap();
+
+            // The current track may have become unavailable since it was queued
+            match queue.current() {
+                Some(track_id) if !queue.is_available(track_id) => queue.next(),
+                track_id => track_id,
+            }
         };
 
         if let Some(track_id) = track_id {
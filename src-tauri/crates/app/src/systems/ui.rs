@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 use specta::Type;
 use tauri_specta::Event;
 
-use crate::queue::RepeatMode;
+use crate::{queue::RepeatMode, systems::import::ScanSummary};
 
 #[derive(Serialize, Deserialize, Type, Clone)]
 pub enum PlayButtonState {
@@ -30,4 +30,9 @@ pub enum UIUpdateEvent {
     TrackChange {
         track: Tracks,
     },
+
+    /// The library changed while watching the music directory
+    LibraryUpdated {
+        summary: ScanSummary,
+    },
 }
@@ -0,0 +1,363 @@
+use logging::lock_or_log;
+use notify_debouncer_full::{
+    DebounceEventResult, new_debouncer,
+    notify::{EventKind, RecursiveMode},
+};
+use tauri::{AppHandle, Manager, ipc::Channel};
+use tauri_specta::Event;
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    VeilState,
+    queue::sync_unavailable_tracks,
+    systems::{
+        import::{self, ImportOutcome},
+        ui::UIUpdateEvent,
+    },
+};
+
+/// Changes are imported once the music directory has been quiet for this long
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// How often to check whether the music directory is still mounted, or has come back
+const MOUNT_POLL: Duration = Duration::from_secs(5);
+
+/// Watches the music directory on its own thread, which stops once this is dropped
+pub struct LibraryWatcher {
+    stopped: Arc<AtomicBool>,
+}
+
+impl LibraryWatcher {
+    /// Start watching `music_dir`, calling `rescan` whenever something in it changed.
+    ///
+    /// `rescan` is also called every time watching (re)starts, to catch up on what changed
+    /// while the directory wasn't watched. The flag passed to it is set once the watcher
+    /// is dropped, which should cancel the rescan.
+    pub fn spawn(
+        music_dir: PathBuf,
+        rescan: impl Fn(&Path, &AtomicBool) + Send + 'static,
+    ) -> io::Result<Self> {
+        Self::spawn_with(music_dir, rescan, import::is_reachable, MOUNT_POLL)
+    }
+
+    /// Like [`LibraryWatcher::spawn`], checking whether the directory is mounted with
+    /// `is_reachable` every `mount_poll`
+    fn spawn_with(
+        music_dir: PathBuf,
+        rescan: impl Fn(&Path, &AtomicBool) + Send + 'static,
+        is_reachable: impl Fn(&Path) -> bool + Send + 'static,
+        mount_poll: Duration,
+    ) -> io::Result<Self> {
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let thread_stopped = stopped.clone();
+        thread::Builder::new()
+            .name(String::from("library-watcher"))
+            .spawn(move || {
+                watch(
+                    &music_dir,
+                    &thread_stopped,
+                    &rescan,
+                    &is_reachable,
+                    mount_poll,
+                );
+            })?;
+
+        Ok(Self { stopped })
+    }
+}
+
+impl Drop for LibraryWatcher {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Start watching the music directory in the config, replacing the previous watcher.
+/// Nothing is watched if watching is disabled, or there's no music directory.
+pub fn restart_watcher(handle: &AppHandle) -> anyhow::Result<()> {
+    let state = handle.state::<VeilState>();
+
+    let music_dir = {
+        let config = lock_or_log(state.config.read(), "Config Read")?;
+        config
+            .library
+            .music_dir
+            .clone()
+            .filter(|_| config.library.watch_music_dir)
+    };
+
+    let mut watcher = lock_or_log(state.library_watcher.lock(), "Library Watcher Mutex")?;
+
+    // Dropping the previous watcher stops it
+    *watcher = None;
+
+    if let Some(music_dir) = music_dir {
+        let handle = handle.clone();
+        *watcher = Some(LibraryWatcher::spawn(
+            PathBuf::from(music_dir),
+            move |music_dir, stopped| rescan(&handle, music_dir, stopped),
+        )?);
+    }
+
+    Ok(())
+}
+
+fn rescan(handle: &AppHandle, music_dir: &Path, stopped: &AtomicBool) {
+    let state = handle.state::<VeilState>();
+
+    // Progress is only shown for imports from the folder picker
+    let on_event = Channel::new(|_| Ok(()));
+
+    match import::import_library(&state.db, music_dir, stopped, &on_event) {
+        Ok(ImportOutcome::Finished(summary)) if summary.changed() => {
+            if let Err(e) = sync_unavailable_tracks(&state) {
+                logging::error!("Failed to update unavailable tracks in queue: {:?}", e);
+            }
+
+            if let Err(e) = UIUpdateEvent::emit(&UIUpdateEvent::LibraryUpdated { summary }, handle)
+            {
+                logging::error!("Failed to emit library update: {:?}", e);
+            }
+        }
+        Ok(_) => {}
+        Err(e) => {
+            logging::error!(
+                "Failed to import changes in {}: {:?}",
+                music_dir.display(),
+                e
+            );
+        }
+    }
+}
+
+fn watch(
+    music_dir: &Path,
+    stopped: &AtomicBool,
+    rescan: &dyn Fn(&Path, &AtomicBool),
+    is_reachable: &dyn Fn(&Path) -> bool,
+    mount_poll: Duration,
+) {
+    // Whether the tracks in the directory were already marked as unavailable
+    let mut offline = false;
+
+    while !stopped.load(Ordering::Relaxed) {
+        if !is_reachable(music_dir) {
+            if !offline {
+                rescan(music_dir, stopped);
+                offline = true;
+            }
+
+            thread::sleep(mount_poll);
+            continue;
+        }
+
+        offline = false;
+
+        let (sender, receiver) = mpsc::channel::<DebounceEventResult>();
+        let mut debouncer = match new_debouncer(DEBOUNCE, None, sender) {
+            Ok(debouncer) => debouncer,
+            Err(e) => {
+                logging::error!("Failed to create music directory watcher: {:?}", e);
+                return;
+            }
+        };
+
+        if let Err(e) = debouncer.watch(music_dir, RecursiveMode::Recursive) {
+            logging::error!("Failed to watch {}: {:?}", music_dir.display(), e);
+            thread::sleep(mount_poll);
+            continue;
+        }
+
+        rescan(music_dir, stopped);
+
+        'watching: while !stopped.load(Ordering::Relaxed) && is_reachable(music_dir) {
+            let mut changed = false;
+            let mut timeout = mount_poll;
+
+            // Copying an album over keeps the events coming, it's imported once it's done
+            loop {
+                match receiver.recv_timeout(timeout) {
+                    Ok(Ok(events)) => {
+                        changed |= events.iter().any(|event| changes_library(event.kind));
+                    }
+                    Ok(Err(errors)) => {
+                        for e in errors {
+                            logging::error!("Error watching {}: {:?}", music_dir.display(), e);
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => break 'watching,
+                }
+
+                timeout = DEBOUNCE;
+            }
+
+            // An unmount shows up as everything being removed, which mustn't empty the library
+            if changed && is_reachable(music_dir) {
+                rescan(music_dir, stopped);
+            }
+        }
+
+        // The debouncer is dropped here, so an unmounted directory is watched anew
+        // once it's back
+    }
+}
+
+fn changes_library(kind: EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Any | EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify_debouncer_full::notify::event::{
+        AccessKind, CreateKind, ModifyKind, RemoveKind, RenameMode,
+    };
+    use std::{fs, sync::Mutex, time::Instant};
+
+    const POLL: Duration = Duration::from_millis(20);
+
+    /// A watched music directory, whose reachability is up to the test
+    struct Watched {
+        dir: PathBuf,
+        reachable: Arc<AtomicBool>,
+        /// Whether the directory was reachable, for every rescan
+        rescans: Arc<Mutex<Vec<bool>>>,
+        watcher: Option<LibraryWatcher>,
+    }
+
+    impl Watched {
+        fn new(name: &str) -> Watched {
+            let dir = std::env::temp_dir().join(format!("watcher_{}_{name}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+
+            let reachable = Arc::new(AtomicBool::new(true));
+            let rescans = Arc::new(Mutex::new(Vec::new()));
+
+            let watcher = LibraryWatcher::spawn_with(
+                dir.clone(),
+                {
+                    let (reachable, rescans) = (reachable.clone(), rescans.clone());
+                    move |_, _| {
+                        let reachable = reachable.load(Ordering::SeqCst);
+                        rescans.lock().unwrap().push(reachable);
+                    }
+                },
+                {
+                    let reachable = reachable.clone();
+                    move |_| reachable.load(Ordering::SeqCst)
+                },
+                POLL,
+            )
+            .unwrap();
+
+            Watched {
+                dir,
+                reachable,
+                rescans,
+                watcher: Some(watcher),
+            }
+        }
+
+        fn rescans(&self) -> Vec<bool> {
+            self.rescans.lock().unwrap().clone()
+        }
+
+        /// The rescans once there's been `count` of them
+        fn wait_for(&self, count: usize) -> Vec<bool> {
+            let start = Instant::now();
+            while self.rescans().len() < count && start.elapsed() < Duration::from_secs(10) {
+                thread::sleep(POLL);
+            }
+
+            self.rescans()
+        }
+
+        /// The rescans after the watcher had time to do more than it should
+        fn settle(&self) -> Vec<bool> {
+            thread::sleep(POLL * 10);
+            self.rescans()
+        }
+    }
+
+    impl Drop for Watched {
+        fn drop(&mut self) {
+            self.watcher = None;
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn library_changes() {
+        assert!(changes_library(EventKind::Create(CreateKind::File)));
+        assert!(changes_library(EventKind::Modify(ModifyKind::Name(
+            RenameMode::Both
+        ))));
+        assert!(changes_library(EventKind::Remove(RemoveKind::Folder)));
+        assert!(!changes_library(EventKind::Access(AccessKind::Read)));
+        assert!(!changes_library(EventKind::Other));
+    }
+
+    #[test]
+    fn rescans_once_watching_starts() {
+        let watched = Watched::new("start");
+        assert_eq!(watched.wait_for(1), [true]);
+        assert_eq!(watched.settle(), [true]);
+    }
+
+    #[test]
+    fn rescans_after_changes() {
+        let watched = Watched::new("changes");
+        watched.wait_for(1);
+
+        fs::write(watched.dir.join("01.flac"), b"").unwrap();
+        assert_eq!(watched.wait_for(2), [true, true]);
+    }
+
+    #[test]
+    fn survives_unmount_and_remount() {
+        let watched = Watched::new("remount");
+        watched.wait_for(1);
+
+        // Tracks are marked as unavailable once, however long it's gone
+        watched.reachable.store(false, Ordering::SeqCst);
+        assert_eq!(watched.wait_for(2), [true, false]);
+        assert_eq!(watched.settle(), [true, false]);
+
+        // Catches up on what changed while it was gone once it's back
+        watched.reachable.store(true, Ordering::SeqCst);
+        assert_eq!(watched.wait_for(3), [true, false, true]);
+        assert_eq!(watched.settle(), [true, false, true]);
+
+        watched.reachable.store(false, Ordering::SeqCst);
+        assert_eq!(watched.wait_for(4), [true, false, true, false]);
+    }
+
+    #[test]
+    fn stops_once_dropped() {
+        let mut watched = Watched::new("dropped");
+        watched.wait_for(1);
+
+        watched.watcher = None;
+        thread::sleep(POLL * 2);
+        watched.reachable.store(false, Ordering::SeqCst);
+
+        assert_eq!(watched.settle(), [true]);
+    }
+}
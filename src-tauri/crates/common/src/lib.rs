@@ -31,6 +31,18 @@ impl AlbumType {
     }
 }
 
+/// How an artist is credited on a track
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize, Type))]
+pub enum ArtistRole {
+    /// Main artist of the track
+    Primary,
+    /// Any track artist after the main one
+    Featured,
+    Remixer,
+    Composer,
+}
+
 #[derive(Debug)]
 #[cfg_attr(feature = "serialization", derive(Serialize, Deserialize, Type))]
 pub struct Artists {
@@ -72,6 +84,9 @@ pub struct Albums {
 
     /// Path to album folder from where it was imported
     pub path: String,
+
+    /// Whether the album is a compilation of different artists
+    pub compilation: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -83,13 +98,13 @@ pub struct Tracks {
     /// ID of album in database
     pub album_id: u32,
 
-    /// ID of artist in database
+    /// ID of track artist in database, the album artist is linked through the album
     pub artist_id: u32,
 
     /// Album name
     pub album_name: String,
 
-    /// Artist name
+    /// Track artist name
     pub artist_name: String,
 
     /// Track name
@@ -109,6 +124,46 @@ pub struct Tracks {
 
     /// Hash of the metadata
     pub hash: String,
+
+    /// Disc number in album, 1 if the track isn't tagged with one
+    pub disc_number: u32,
+
+    /// Amount of tracks on the disc
+    pub track_total: Option<u32>,
+
+    /// Amount of discs in album
+    pub disc_total: Option<u32>,
+
+    /// Genre of track
+    pub genre: Option<String>,
+
+    /// Composer of track
+    pub composer: Option<String>,
+
+    /// Release date as tagged, e.g. "2005-09-12" or just "2005"
+    pub release_date: Option<String>,
+
+    /// Codec of the audio, e.g. "FLAC" or "MP3"
+    pub codec: Option<String>,
+
+    /// Sample rate in Hz
+    pub sample_rate: Option<u32>,
+
+    /// Bits per sample, which lossy codecs don't have
+    pub bit_depth: Option<u32>,
+
+    /// Amount of audio channels
+    pub channels: Option<u32>,
+
+    /// Average bitrate in kbit/s
+    pub bitrate: Option<u32>,
+
+    /// Whether the codec is lossless
+    pub lossless: bool,
+
+    /// Whether the file can be reached, tracks of a music folder that's unmounted are kept
+    /// but can't be played
+    pub available: bool,
 }
 
 #[derive(Debug)]
@@ -165,6 +220,9 @@ pub struct ArtistWithAlbums {
 
     /// All albums belonging to artist
     pub albums: Vec<AlbumWithTracks>,
+
+    /// Tracks the artist is credited on from albums that don't belong to them
+    pub appears_on: Vec<Tracks>,
 }
 
 pub struct NewArtist<'a> {
@@ -198,19 +256,22 @@ pub struct NewAlbum<'a> {
 
     /// Path to album folder from where it was imported
     pub path: &'a str,
+
+    /// Whether the album is a compilation of different artists
+    pub compilation: bool,
 }
 
 pub struct NewTrack<'a> {
     /// ID of album in database
     pub album_id: u32,
 
-    /// ID of artist in database
+    /// ID of track artist in database
     pub artist_id: u32,
 
     /// Album name
     pub album_name: &'a str,
 
-    /// Artist name
+    /// Track artist name
     pub artist_name: &'a str,
 
     /// Track name
@@ -227,6 +288,45 @@ pub struct NewTrack<'a> {
 
     /// Path to track file
     pub path: &'a str,
+
+    /// Disc number in album
+    pub disc_number: u32,
+
+    /// Amount of tracks on the disc
+    pub track_total: Option<u32>,
+
+    /// Amount of discs in album
+    pub disc_total: Option<u32>,
+
+    /// Genre of track
+    pub genre: Option<&'a str>,
+
+    /// Composer of track
+    pub composer: Option<&'a str>,
+
+    /// Release date as tagged
+    pub release_date: Option<&'a str>,
+
+    /// Codec of the audio
+    pub codec: Option<&'a str>,
+
+    /// Sample rate in Hz
+    pub sample_rate: Option<u32>,
+
+    /// Bits per sample
+    pub bit_depth: Option<u32>,
+
+    /// Amount of audio channels
+    pub channels: Option<u32>,
+
+    /// Average bitrate in kbit/s
+    pub bitrate: Option<u32>,
+
+    /// Whether the codec is lossless
+    pub lossless: bool,
+
+    /// State of the track file when it was read
+    pub file: FileStamp,
 }
 
 pub struct NewPlaylist<'a> {
@@ -239,3 +339,46 @@ pub struct NewPlaylist<'a> {
     /// Path to playlist cover in VEIL local app data
     pub cover_path: &'a str,
 }
+
+/// Size, modification time and inode of a track file, a file whose stamp hasn't
+/// changed since it was imported doesn't have to be read again
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileStamp {
+    /// Size in bytes
+    pub size: i64,
+
+    /// Modification time in nanoseconds since the Unix epoch
+    pub modified: i64,
+
+    /// Inode of the file, on platforms that have them
+    pub inode: Option<i64>,
+}
+
+/// The file of a track in database, as compared against the music folder on rescans
+#[derive(Debug)]
+pub struct TrackFile {
+    /// ID of track in database
+    pub id: u32,
+
+    /// ID of album in database
+    pub album_id: u32,
+
+    /// Path to track file
+    pub path: String,
+
+    /// Stamp of the file when it was imported, missing on tracks imported before stamps were stored
+    pub file: Option<FileStamp>,
+}
+
+/// A file skipped as a copy of a track, which isn't read again until it changes
+#[derive(Debug)]
+pub struct DuplicateFile {
+    /// Path to the copy
+    pub path: String,
+
+    /// ID of the track it's a copy of
+    pub track_id: u32,
+
+    /// Stamp of the file when it was skipped
+    pub file: FileStamp,
+}
@@ -1,8 +1,8 @@
 use rusqlite::{Result, ToSql, types::FromSql};
 
 use crate::{
-    AlbumType, Albums, Artists, NewAlbum, NewArtist, NewPlaylist, NewTrack, Playlists, Search,
-    Tracks,
+    AlbumType, Albums, ArtistRole, Artists, NewAlbum, NewArtist, NewPlaylist, NewTrack, Playlists,
+    Search, Tracks,
 };
 
 impl FromSql for AlbumType {
@@ -32,6 +32,34 @@ impl ToSql for AlbumType {
     }
 }
 
+impl FromSql for ArtistRole {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        match value {
+            rusqlite::types::ValueRef::Text(text) => match text {
+                b"primary" => Ok(ArtistRole::Primary),
+                b"featured" => Ok(ArtistRole::Featured),
+                b"remixer" => Ok(ArtistRole::Remixer),
+                b"composer" => Ok(ArtistRole::Composer),
+                _ => Err(rusqlite::types::FromSqlError::InvalidType),
+            },
+            _ => Err(rusqlite::types::FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl ToSql for ArtistRole {
+    fn to_sql(&self) -> Result<rusqlite::types::ToSqlOutput<'_>> {
+        let text = match self {
+            ArtistRole::Primary => "primary",
+            ArtistRole::Featured => "featured",
+            ArtistRole::Remixer => "remixer",
+            ArtistRole::Composer => "composer",
+        };
+
+        Ok(text.into())
+    }
+}
+
 pub trait HasArtists {
     /// Return an Option<u32> of `artist_id`
     fn get_artist_id(&self) -> Option<u32>;
@@ -68,6 +96,7 @@ impl<'a> Hashable for NewAlbum<'a> {
     }
 }
 
+/// Only used to recognize copies of a track, tracks themselves are told apart by their file
 impl<'a> Hashable for NewTrack<'a> {
     fn make_hash(&self) -> String {
         let formatted_string = format!(
@@ -122,6 +151,7 @@ impl<'a> Insertable for NewAlbum<'a> {
             &self.duration,
             &self.cover_path,
             &self.path,
+            &self.compilation,
         ]
     }
 }
@@ -142,6 +172,21 @@ impl<'a> Insertable for NewTrack<'a> {
             &self.duration,
             &self.cover_path,
             &self.path,
+            &self.disc_number,
+            &self.track_total,
+            &self.disc_total,
+            &self.genre,
+            &self.composer,
+            &self.release_date,
+            &self.codec,
+            &self.sample_rate,
+            &self.bit_depth,
+            &self.channels,
+            &self.bitrate,
+            &self.lossless,
+            &self.file.size,
+            &self.file.modified,
+            &self.file.inode,
         ]
     }
 }
@@ -190,6 +235,7 @@ impl Queryable for Albums {
             duration: row.get(7)?,
             cover_path: row.get(8)?,
             path: row.get(9)?,
+            compilation: row.get(10)?,
         })
     }
 
@@ -212,6 +258,19 @@ impl Queryable for Tracks {
             cover_path: row.get(8)?,
             path: row.get(9)?,
             hash: row.get(10)?,
+            disc_number: row.get(11)?,
+            track_total: row.get(12)?,
+            disc_total: row.get(13)?,
+            genre: row.get(14)?,
+            composer: row.get(15)?,
+            release_date: row.get(16)?,
+            codec: row.get(17)?,
+            sample_rate: row.get(18)?,
+            bit_depth: row.get(19)?,
+            channels: row.get(20)?,
+            bitrate: row.get(21)?,
+            lossless: row.get(22)?,
+            available: row.get(26)?,
         })
     }
 
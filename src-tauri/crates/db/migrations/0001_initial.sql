@@ -115,4 +115,4 @@ BEGIN
 END;
 
 CREATE INDEX IF NOT EXISTS idx_playlist_tracks_playlist_id
-ON playlist_tracks (playlist_id);
\ No newline at end of file
+ON playlist_tracks (playlist_id);
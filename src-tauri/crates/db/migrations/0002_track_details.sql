@@ -0,0 +1,6 @@
+ALTER TABLE tracks ADD COLUMN disc_number INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE tracks ADD COLUMN track_total INTEGER;
+ALTER TABLE tracks ADD COLUMN disc_total INTEGER;
+ALTER TABLE tracks ADD COLUMN genre TEXT;
+ALTER TABLE tracks ADD COLUMN composer TEXT;
+ALTER TABLE tracks ADD COLUMN release_date TEXT;
@@ -0,0 +1 @@
+ALTER TABLE albums ADD COLUMN compilation INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,13 @@
+CREATE TABLE track_artists (
+    track_id    INTEGER NOT NULL REFERENCES tracks(id) ON DELETE CASCADE,
+    artist_id   INTEGER NOT NULL REFERENCES artists(id) ON DELETE CASCADE,
+    role        TEXT    NOT NULL,
+    PRIMARY KEY (track_id, artist_id, role)
+);
+
+-- Credit the track artist of tracks imported before track_artists existed
+INSERT INTO track_artists (track_id, artist_id, role)
+SELECT id, artist_id, 'primary' FROM tracks;
+
+CREATE INDEX idx_track_artists_artist_id
+ON track_artists (artist_id);
@@ -0,0 +1,6 @@
+ALTER TABLE tracks ADD COLUMN codec TEXT;
+ALTER TABLE tracks ADD COLUMN sample_rate INTEGER;
+ALTER TABLE tracks ADD COLUMN bit_depth INTEGER;
+ALTER TABLE tracks ADD COLUMN channels INTEGER;
+ALTER TABLE tracks ADD COLUMN bitrate INTEGER;
+ALTER TABLE tracks ADD COLUMN lossless INTEGER NOT NULL DEFAULT 0;
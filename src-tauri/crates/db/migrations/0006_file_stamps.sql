@@ -0,0 +1,6 @@
+ALTER TABLE tracks ADD COLUMN file_size INTEGER;
+ALTER TABLE tracks ADD COLUMN file_modified INTEGER;
+ALTER TABLE tracks ADD COLUMN file_inode INTEGER;
+
+CREATE INDEX idx_tracks_album_id
+ON tracks (album_id);
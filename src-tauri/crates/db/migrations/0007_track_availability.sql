@@ -0,0 +1 @@
+ALTER TABLE tracks ADD COLUMN available INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,100 @@
+-- Tracks are told apart by their file, and two files can carry the same tags. The hash
+-- used to be unique, and SQLite can't drop a constraint, so the table is rebuilt.
+CREATE TABLE tracks_new (
+    id          INTEGER NOT NULL PRIMARY KEY,
+    album_id    INTEGER NOT NULL REFERENCES albums(id) ON DELETE CASCADE,
+    artist_id   INTEGER NOT NULL REFERENCES artists(id),
+    album_name  TEXT    NOT NULL,
+    artist_name TEXT    NOT NULL,
+    name        TEXT    NOT NULL,
+    number      INTEGER NOT NULL,
+    duration    INTEGER NOT NULL,
+    cover_path  TEXT    NOT NULL,
+    path        TEXT    NOT NULL,
+    hash        TEXT    NOT NULL,
+    disc_number INTEGER NOT NULL DEFAULT 1,
+    track_total INTEGER,
+    disc_total  INTEGER,
+    genre       TEXT,
+    composer    TEXT,
+    release_date TEXT,
+    codec       TEXT,
+    sample_rate INTEGER,
+    bit_depth   INTEGER,
+    channels    INTEGER,
+    bitrate     INTEGER,
+    lossless    INTEGER NOT NULL DEFAULT 0,
+    file_size   INTEGER,
+    file_modified INTEGER,
+    file_inode  INTEGER,
+    available   INTEGER NOT NULL DEFAULT 1
+);
+
+INSERT INTO tracks_new (
+    id,
+    album_id,
+    artist_id,
+    album_name,
+    artist_name,
+    name,
+    number,
+    duration,
+    cover_path,
+    path,
+    hash,
+    disc_number,
+    track_total,
+    disc_total,
+    genre,
+    composer,
+    release_date,
+    codec,
+    sample_rate,
+    bit_depth,
+    channels,
+    bitrate,
+    lossless,
+    file_size,
+    file_modified,
+    file_inode,
+    available
+)
+SELECT
+    id,
+    album_id,
+    artist_id,
+    album_name,
+    artist_name,
+    name,
+    number,
+    duration,
+    cover_path,
+    path,
+    hash,
+    disc_number,
+    track_total,
+    disc_total,
+    genre,
+    composer,
+    release_date,
+    codec,
+    sample_rate,
+    bit_depth,
+    channels,
+    bitrate,
+    lossless,
+    file_size,
+    file_modified,
+    file_inode,
+    available
+FROM tracks;
+
+DROP TABLE tracks;
+
+ALTER TABLE tracks_new RENAME TO tracks;
+
+CREATE INDEX idx_tracks_album_id
+ON tracks (album_id);
+
+CREATE INDEX idx_tracks_hash
+ON tracks (hash);
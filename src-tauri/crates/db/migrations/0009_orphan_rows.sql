@@ -0,0 +1,32 @@
+-- Foreign keys weren't enforced before, so deleting a row left the rows referencing it
+-- behind. Tracks of a deleted album would have been deleted along with it.
+DELETE FROM tracks
+WHERE album_id NOT IN (SELECT id FROM albums);
+
+-- Tracks whose artist is gone are credited to an artist of the same name
+INSERT INTO artists (name)
+SELECT DISTINCT artist_name FROM tracks
+WHERE artist_id NOT IN (SELECT id FROM artists)
+    AND artist_name NOT IN (SELECT name FROM artists);
+
+UPDATE tracks
+SET artist_id = (SELECT MIN(id) FROM artists WHERE name = tracks.artist_name)
+WHERE artist_id NOT IN (SELECT id FROM artists);
+
+DELETE FROM playlist_tracks
+WHERE playlist_id NOT IN (SELECT id FROM playlists)
+    OR track_id NOT IN (SELECT id FROM tracks);
+
+DELETE FROM album_artists
+WHERE album_id NOT IN (SELECT id FROM albums)
+    OR artist_id NOT IN (SELECT id FROM artists);
+
+DELETE FROM track_artists
+WHERE track_id NOT IN (SELECT id FROM tracks)
+    OR artist_id NOT IN (SELECT id FROM artists);
+
+-- Entries left behind kept counting towards their playlist
+UPDATE playlists
+SET track_count = (
+    SELECT COUNT(*) FROM playlist_tracks WHERE playlist_id = playlists.id
+);
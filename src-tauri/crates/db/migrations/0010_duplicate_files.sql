@@ -0,0 +1,12 @@
+-- Files skipped as a copy of a track, stored so they aren't read again on every rescan.
+-- Once the track is gone, its copies are read again and one of them takes its place.
+CREATE TABLE duplicate_files (
+    path          TEXT    NOT NULL PRIMARY KEY,
+    track_id      INTEGER NOT NULL REFERENCES tracks(id) ON DELETE CASCADE,
+    file_size     INTEGER NOT NULL,
+    file_modified INTEGER NOT NULL,
+    file_inode    INTEGER
+);
+
+CREATE INDEX idx_duplicate_files_track_id
+ON duplicate_files (track_id);
@@ -0,0 +1,5 @@
+DELETE FROM albums
+WHERE id NOT IN (SELECT album_id FROM tracks);
+
+DELETE FROM album_artists
+WHERE album_id NOT IN (SELECT id FROM albums);
@@ -1,5 +1,5 @@
 SELECT 
-    SUM(duration),
+    COALESCE(SUM(duration), 0),
     COUNT(*)
 FROM tracks
-WHERE album_id = ?1
\ No newline at end of file
+WHERE album_id = ?1
@@ -8,7 +8,8 @@ SELECT
     a.track_count,
     a.duration,
     a.cover_path,
-    a.path
+    a.path,
+    a.compilation
 FROM albums a
 JOIN album_artists aa ON a.id = aa.album_id
 JOIN artists ar ON aa.artist_id = ar.id
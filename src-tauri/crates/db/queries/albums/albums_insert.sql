@@ -1,2 +1,3 @@
-INSERT INTO albums (name, year, type, track_count, duration, cover_path, path)
-VALUES (?2, ?3, ?4, ?5, ?6, ?7, ?8);
\ No newline at end of file
+INSERT INTO albums (name, year, type, track_count, duration, cover_path, path, compilation)
+VALUES (?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+RETURNING id;
\ No newline at end of file
@@ -0,0 +1,5 @@
+UPDATE albums
+SET
+    path = ?2
+WHERE
+    id = ?1
@@ -0,0 +1,4 @@
+DELETE FROM artists
+WHERE id NOT IN (SELECT artist_id FROM album_artists)
+    AND id NOT IN (SELECT artist_id FROM tracks)
+    AND id NOT IN (SELECT artist_id FROM track_artists)
@@ -1,2 +1,3 @@
 INSERT INTO playlists (name, description, cover_path)
-VALUES (?1, ?2, ?3)
\ No newline at end of file
+VALUES (?1, ?2, ?3)
+RETURNING id;
\ No newline at end of file
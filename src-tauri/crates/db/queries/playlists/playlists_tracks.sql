@@ -9,7 +9,23 @@ SELECT
     t.duration,
     t.cover_path,
     t.path,
-    t.hash
+    t.hash,
+    t.disc_number,
+    t.track_total,
+    t.disc_total,
+    t.genre,
+    t.composer,
+    t.release_date,
+    t.codec,
+    t.sample_rate,
+    t.bit_depth,
+    t.channels,
+    t.bitrate,
+    t.lossless,
+    t.file_size,
+    t.file_modified,
+    t.file_inode,
+    t.available
 FROM
     playlist_tracks pt
     JOIN tracks t ON pt.track_id = t.id
@@ -0,0 +1,2 @@
+SELECT path, track_id, file_size, file_modified, file_inode
+FROM duplicate_files
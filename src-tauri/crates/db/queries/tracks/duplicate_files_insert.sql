@@ -0,0 +1,2 @@
+INSERT OR REPLACE INTO duplicate_files (path, track_id, file_size, file_modified, file_inode)
+VALUES (?1, ?2, ?3, ?4, ?5)
@@ -0,0 +1 @@
+DELETE FROM track_artists WHERE track_id = ?1
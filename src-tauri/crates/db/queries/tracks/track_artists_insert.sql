@@ -0,0 +1,2 @@
+INSERT OR IGNORE INTO track_artists (track_id, artist_id, role)
+VALUES (?1, ?2, ?3);
\ No newline at end of file
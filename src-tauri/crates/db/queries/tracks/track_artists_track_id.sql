@@ -0,0 +1,2 @@
+SELECT DISTINCT artist_id FROM track_artists
+WHERE track_id = ?1
\ No newline at end of file
@@ -0,0 +1,3 @@
+SELECT * FROM tracks
+WHERE album_id = ?1
+ORDER BY disc_number ASC, number ASC
\ No newline at end of file
@@ -0,0 +1,7 @@
+SELECT DISTINCT t.* FROM tracks t
+JOIN track_artists ta ON t.id = ta.track_id
+WHERE ta.artist_id = ?1
+    AND t.album_id NOT IN (
+        SELECT album_id FROM album_artists WHERE artist_id = ?1
+    )
+ORDER BY t.album_name ASC, t.disc_number ASC, t.number ASC
\ No newline at end of file
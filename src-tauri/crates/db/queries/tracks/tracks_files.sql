@@ -0,0 +1,2 @@
+SELECT id, album_id, path, file_size, file_modified, file_inode
+FROM tracks
@@ -1,2 +1,3 @@
-INSERT INTO tracks (album_id, artist_id, album_name, artist_name, name, number, duration, cover_path, path, hash)
-VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
\ No newline at end of file
+INSERT INTO tracks (album_id, artist_id, album_name, artist_name, name, number, duration, cover_path, path, disc_number, track_total, disc_total, genre, composer, release_date, codec, sample_rate, bit_depth, channels, bitrate, lossless, file_size, file_modified, file_inode, hash)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25)
+RETURNING id;
\ No newline at end of file
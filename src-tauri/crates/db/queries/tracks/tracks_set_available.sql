@@ -0,0 +1,6 @@
+UPDATE tracks
+SET
+    available = ?1
+WHERE
+    substr(path, 1, length(?2)) = ?2
+    AND available != ?1
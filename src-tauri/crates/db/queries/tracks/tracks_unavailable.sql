@@ -0,0 +1,2 @@
+SELECT id FROM tracks
+WHERE available = 0
@@ -0,0 +1,29 @@
+UPDATE tracks
+SET
+    album_id = ?1,
+    artist_id = ?2,
+    album_name = ?3,
+    artist_name = ?4,
+    name = ?5,
+    number = ?6,
+    duration = ?7,
+    cover_path = ?8,
+    path = ?9,
+    disc_number = ?10,
+    track_total = ?11,
+    disc_total = ?12,
+    genre = ?13,
+    composer = ?14,
+    release_date = ?15,
+    codec = ?16,
+    sample_rate = ?17,
+    bit_depth = ?18,
+    channels = ?19,
+    bitrate = ?20,
+    lossless = ?21,
+    file_size = ?22,
+    file_modified = ?23,
+    file_inode = ?24,
+    hash = ?25
+WHERE
+    id = ?26
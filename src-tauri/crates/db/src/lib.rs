@@ -2,13 +2,15 @@ use common::traits::*;
 use common::*;
 use include_dir::{Dir, include_dir};
 use once_cell::sync::Lazy;
-use r2d2::Pool;
-use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::OptionalExtension;
-use std::{collections::HashMap, fs::create_dir, path::PathBuf};
+use std::{collections::HashMap, path::PathBuf};
 
 use crate::timed_connection::TimedPool;
 
+pub use options::DatabaseOptions;
+
+mod migrations;
+mod options;
 mod timed_connection;
 
 fn collect_sql_files(dir: &Dir, queries: &mut HashMap<String, String>) {
@@ -35,6 +37,9 @@ fn collect_sql_files(dir: &Dir, queries: &mut HashMap<String, String>) {
 
 static QUERY_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/queries");
 
+/// Schema changes, applied in order by [`migrations::migrate`]
+static MIGRATION_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/migrations");
+
 static QUERIES: Lazy<HashMap<String, String>> = Lazy::new(|| {
     let mut queries = HashMap::new();
 
@@ -55,6 +60,12 @@ pub enum Error {
     R2D2Error(#[from] r2d2::Error),
     #[error(transparent)]
     RusqliteError(#[from] rusqlite::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Read-only database is on schema version {version} instead of {latest}")]
+    OutdatedSchema { version: u32, latest: u32 },
+    #[error("Migrating left a row in {table} referencing a missing row in {parent}")]
+    ForeignKeyViolation { table: String, parent: String },
 }
 
 pub struct Database {
@@ -64,30 +75,18 @@ pub struct Database {
 type Result<T, U = Error> = std::result::Result<T, U>;
 
 impl Database {
-    /// Instanties a connection with the database, creates a new sqlite file if it doesn't exist
-    pub fn new(path: PathBuf) -> Self {
-        if !path.exists() {
-            create_dir(&path).expect("Error creating data directory");
-        }
-
-        let manager = SqliteConnectionManager::file(path.join("db.sqlite"));
-        let pool = Pool::new(manager).unwrap();
-        let timed_pool = TimedPool(pool);
-        let conn = timed_pool.get().unwrap();
-
-        conn.execute_batch(
-            "PRAGMA journal_mode = WAL;
-            PRAGMA journal_size_limit = 6144000;
-            PRAGMA synchronous = NORMAL;",
-        )
-        .expect("Error setting PRAGMA");
-
-        conn.execute_batch(query("schema"))
-            .expect("Error creating tables");
-
-        drop(conn);
+    /// Options to open a database with, kept in memory unless given a path
+    #[must_use]
+    pub fn options() -> DatabaseOptions {
+        DatabaseOptions::default()
+    }
 
-        Self { pool: timed_pool }
+    /// Opens `db.sqlite` in `path` with the default options, creating it if it doesn't exist
+    ///
+    /// # Errors
+    /// Returns any error from [`DatabaseOptions::build`].
+    pub fn new(path: PathBuf) -> Result<Self> {
+        Self::options().path(path).build()
     }
 
     /// Writes WAL data to database
@@ -144,59 +143,40 @@ impl Database {
         Ok(result)
     }
 
-    /// Insert value to `T` table
-    pub fn insert<T: Insertable + Hashable>(&self, data_to_pass: T) -> Result<()> {
+    /// Insert value to `T` table, returning the ID of the new row
+    pub fn insert<T: Insertable + Hashable>(&self, data_to_pass: T) -> Result<u32> {
         let mut conn = self.pool.get()?;
         let tx = conn.transaction()?;
-        let stmt_to_call = match T::table_name() {
-            "artists" => query("artists_insert"),
-            "tracks" => query("tracks_insert"),
-            "playlists" => query("playlists_insert"),
-            _ => unreachable!("Invalid table name"),
-        };
-
-        {
-            let mut stmt = tx.prepare_cached(stmt_to_call)?;
-            let mut params = data_to_pass.to_params();
-            let hash = data_to_pass.make_hash();
-
-            if T::table_name() == "tracks" {
-                params.push(&hash);
-            }
-
-            stmt.execute(rusqlite::params_from_iter(params))?;
-        }
-
+        let id = insert_row(&tx, data_to_pass)?;
         tx.commit()?;
 
-        Ok(())
+        Ok(id)
     }
 
-    /// Insert album`T` table
-    pub fn insert_album<T: Insertable + HasArtists>(&self, data_to_pass: T) -> Result<()> {
+    /// Insert album`T` table, returning the ID of the new album
+    pub fn insert_album<T: Insertable + HasArtists>(&self, data_to_pass: T) -> Result<u32> {
         let mut conn = self.pool.get()?;
         let tx = conn.transaction()?;
-        let stmt_to_call = match T::table_name() {
-            "albums" => query("albums_insert"),
-            _ => unreachable!("Invalid table name"),
-        };
-
-        let album_id = {
-            let mut stmt = tx.prepare_cached(stmt_to_call)?;
-            let params = data_to_pass.to_params();
-            stmt.execute(rusqlite::params_from_iter(params))?;
+        let id = insert_album_row(&tx, data_to_pass)?;
+        tx.commit()?;
 
-            tx.last_insert_rowid()
-        };
+        Ok(id)
+    }
 
-        {
-            let mut stmt = tx.prepare_cached(query("album_artists_insert"))?;
-            stmt.execute((album_id, data_to_pass.get_artist_id()))?;
-        }
+    /// Run `f` in a single transaction, which is committed if it returns `Ok`.
+    ///
+    /// Meant for writing many rows at once, committing every row on its own is far slower.
+    pub fn write_batch<T, E: From<Error>>(
+        &self,
+        f: impl FnOnce(&Batch<'_>) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let mut conn = self.pool.get().map_err(Error::from)?;
+        let tx = conn.transaction().map_err(Error::from)?;
 
-        tx.commit()?;
+        let result = f(&Batch { tx: &tx })?;
+        tx.commit().map_err(Error::from)?;
 
-        Ok(())
+        Ok(result)
     }
 
     /// Delete value from `T` table where id is same
@@ -353,8 +333,7 @@ impl Database {
 
     pub fn album_with_tracks(&self, album_id: &u32) -> Result<AlbumWithTracks> {
         let conn = self.pool.get()?;
-        let mut stmt =
-            conn.prepare("SELECT * FROM tracks WHERE album_id = ?1 ORDER by number ASC")?;
+        let mut stmt = conn.prepare(query("tracks_album_id"))?;
 
         let tracks = stmt
             .query_map([album_id], Tracks::from_row)?
@@ -365,6 +344,117 @@ impl Database {
         Ok(AlbumWithTracks { album, tracks })
     }
 
+    /// Credit artists on a track, credits that already exist are skipped
+    pub fn insert_track_artists(&self, track_id: u32, credits: &[(u32, ArtistRole)]) -> Result<()> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        insert_credits(&tx, track_id, credits)?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Get the file of every track, to tell which files changed since they were imported
+    pub fn track_files(&self) -> Result<Vec<TrackFile>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(query("tracks_files"))?;
+        let result = stmt
+            .query_map([], |row| {
+                let size: Option<i64> = row.get(3)?;
+                let modified: Option<i64> = row.get(4)?;
+                let inode: Option<i64> = row.get(5)?;
+
+                Ok(TrackFile {
+                    id: row.get(0)?,
+                    album_id: row.get(1)?,
+                    path: row.get(2)?,
+                    file: size.zip(modified).map(|(size, modified)| FileStamp {
+                        size,
+                        modified,
+                        inode,
+                    }),
+                })
+            })?
+            .collect::<Result<Vec<TrackFile>, rusqlite::Error>>()?;
+
+        Ok(result)
+    }
+
+    /// Get every file skipped as a copy of a track
+    pub fn duplicate_files(&self) -> Result<Vec<DuplicateFile>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(query("duplicate_files_all"))?;
+        let result = stmt
+            .query_map([], |row| {
+                Ok(DuplicateFile {
+                    path: row.get(0)?,
+                    track_id: row.get(1)?,
+                    file: FileStamp {
+                        size: row.get(2)?,
+                        modified: row.get(3)?,
+                        inode: row.get(4)?,
+                    },
+                })
+            })?
+            .collect::<Result<Vec<DuplicateFile>, rusqlite::Error>>()?;
+
+        Ok(result)
+    }
+
+    /// Mark every track under `path_prefix` as available or not, returning how many
+    /// tracks changed
+    pub fn set_tracks_available(&self, path_prefix: &str, available: bool) -> Result<usize> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(query("tracks_set_available"))?;
+        let changed = stmt.execute((available, path_prefix))?;
+
+        Ok(changed)
+    }
+
+    /// Get IDs of every track whose file can't be reached
+    pub fn unavailable_track_ids(&self) -> Result<Vec<u32>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(query("tracks_unavailable"))?;
+        let result = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<u32>, rusqlite::Error>>()?;
+
+        Ok(result)
+    }
+
+    /// Delete albums without tracks, then artists without albums, tracks or credits
+    pub fn delete_orphans(&self) -> Result<()> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute_batch(query("albums_delete_empty"))?;
+        tx.execute(query("artists_delete_unused"), [])?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Get IDs of every artist credited on a track
+    pub fn track_artist_ids(&self, track_id: u32) -> Result<Vec<u32>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare_cached(query("track_artists_track_id"))?;
+        let result = stmt
+            .query_map([track_id], |row| row.get(0))?
+            .collect::<Result<Vec<u32>, rusqlite::Error>>()?;
+
+        Ok(result)
+    }
+
+    /// Get tracks the artist is credited on from albums that aren't theirs
+    pub fn tracks_appears_on(&self, artist_id: &u32) -> Result<Vec<Tracks>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(query("tracks_appears_on"))?;
+        let result = stmt
+            .query_map([artist_id], Tracks::from_row)?
+            .collect::<Result<Vec<Tracks>, rusqlite::Error>>()?;
+
+        Ok(result)
+    }
+
     pub fn update_album_type(
         &self,
         album_id: u32,
@@ -400,9 +490,12 @@ impl Database {
             .map(|album| self.album_with_tracks(&album.id))
             .collect::<Result<Vec<AlbumWithTracks>>>()?;
 
+        let appears_on = self.tracks_appears_on(id)?;
+
         Ok(ArtistWithAlbums {
             artist,
             albums: albums_with_tracks,
+            appears_on,
         })
     }
 
@@ -494,3 +587,340 @@ impl Database {
         Ok(result)
     }
 }
+
+/// Writes made through `Database::write_batch`, which all share one transaction
+pub struct Batch<'conn> {
+    tx: &'conn rusqlite::Transaction<'conn>,
+}
+
+impl Batch<'_> {
+    /// Insert value to `T` table, returning the ID of the new row
+    pub fn insert<T: Insertable + Hashable>(&self, data_to_pass: T) -> Result<u32> {
+        insert_row(self.tx, data_to_pass)
+    }
+
+    /// Insert album`T` table, returning the ID of the new album
+    pub fn insert_album<T: Insertable + HasArtists>(&self, data_to_pass: T) -> Result<u32> {
+        insert_album_row(self.tx, data_to_pass)
+    }
+
+    /// Credit artists on a track, credits that already exist are skipped
+    pub fn insert_track_artists(&self, track_id: u32, credits: &[(u32, ArtistRole)]) -> Result<()> {
+        insert_credits(self.tx, track_id, credits)
+    }
+
+    /// Overwrite a track with what its file holds now, keeping its ID
+    pub fn update_track(&self, track_id: u32, track: NewTrack) -> Result<()> {
+        let mut stmt = self.tx.prepare_cached(query("tracks_update"))?;
+        let mut params = track.to_params();
+        let hash = track.make_hash();
+        params.push(&hash);
+        params.push(&track_id);
+
+        stmt.execute(rusqlite::params_from_iter(params))?;
+
+        Ok(())
+    }
+
+    /// Point an album at the folder its tracks are in now
+    pub fn update_album_path(&self, album_id: u32, path: &str) -> Result<()> {
+        let mut stmt = self.tx.prepare_cached(query("albums_update_path"))?;
+        stmt.execute((album_id, path))?;
+
+        Ok(())
+    }
+
+    /// Delete a track along with its credits and playlist entries
+    pub fn delete_track(&self, track_id: u32) -> Result<()> {
+        self.delete_track_artists(track_id)?;
+
+        for stmt_to_call in [
+            "DELETE FROM playlist_tracks WHERE track_id = ?1",
+            "DELETE FROM tracks WHERE id = ?1",
+        ] {
+            let mut stmt = self.tx.prepare_cached(stmt_to_call)?;
+            stmt.execute([track_id])?;
+        }
+
+        Ok(())
+    }
+
+    /// Store the stamp of a file skipped as a copy of a track, so it isn't read again
+    /// until it changes
+    pub fn insert_duplicate_file(&self, path: &str, track_id: u32, file: FileStamp) -> Result<()> {
+        let mut stmt = self.tx.prepare_cached(query("duplicate_files_insert"))?;
+        stmt.execute((path, track_id, file.size, file.modified, file.inode))?;
+
+        Ok(())
+    }
+
+    /// Forget a file skipped as a copy, because it's gone or isn't a copy anymore
+    pub fn delete_duplicate_file(&self, path: &str) -> Result<()> {
+        let mut stmt = self
+            .tx
+            .prepare_cached("DELETE FROM duplicate_files WHERE path = ?1")?;
+        stmt.execute([path])?;
+
+        Ok(())
+    }
+
+    /// Forget the files skipped as copies of a track whose tags changed, so they're read
+    /// again
+    pub fn delete_duplicates_of(&self, track_id: u32) -> Result<()> {
+        let mut stmt = self
+            .tx
+            .prepare_cached("DELETE FROM duplicate_files WHERE track_id = ?1")?;
+        stmt.execute([track_id])?;
+
+        Ok(())
+    }
+
+    /// Remove every credit of a track, so they can be credited anew
+    pub fn delete_track_artists(&self, track_id: u32) -> Result<()> {
+        let mut stmt = self.tx.prepare_cached(query("track_artists_delete"))?;
+        stmt.execute([track_id])?;
+
+        Ok(())
+    }
+
+    /// Get IDs of the tracks with the given hash, including ones inserted in this batch
+    pub fn tracks_by_hash(&self, hash: &str) -> Result<Vec<u32>> {
+        let mut stmt = self
+            .tx
+            .prepare_cached("SELECT id FROM tracks WHERE hash = ?1")?;
+        let result = stmt
+            .query_map([hash], |row| row.get(0))?
+            .collect::<Result<Vec<u32>, rusqlite::Error>>()?;
+
+        Ok(result)
+    }
+}
+
+fn insert_row<T: Insertable + Hashable>(
+    conn: &rusqlite::Connection,
+    data_to_pass: T,
+) -> Result<u32> {
+    let stmt_to_call = match T::table_name() {
+        "artists" => query("artists_insert"),
+        "tracks" => query("tracks_insert"),
+        "playlists" => query("playlists_insert"),
+        _ => unreachable!("Invalid table name"),
+    };
+
+    let mut stmt = conn.prepare_cached(stmt_to_call)?;
+    let mut params = data_to_pass.to_params();
+    let hash = data_to_pass.make_hash();
+
+    if T::table_name() == "tracks" {
+        params.push(&hash);
+    }
+
+    let id = stmt.query_row(rusqlite::params_from_iter(params), |row| row.get(0))?;
+
+    Ok(id)
+}
+
+fn insert_album_row<T: Insertable + HasArtists>(
+    conn: &rusqlite::Connection,
+    data_to_pass: T,
+) -> Result<u32> {
+    let stmt_to_call = match T::table_name() {
+        "albums" => query("albums_insert"),
+        _ => unreachable!("Invalid table name"),
+    };
+
+    let album_id: u32 = {
+        let mut stmt = conn.prepare_cached(stmt_to_call)?;
+        let params = data_to_pass.to_params();
+        stmt.query_row(rusqlite::params_from_iter(params), |row| row.get(0))?
+    };
+
+    let mut stmt = conn.prepare_cached(query("album_artists_insert"))?;
+    stmt.execute((album_id, data_to_pass.get_artist_id()))?;
+
+    Ok(album_id)
+}
+
+fn insert_credits(
+    conn: &rusqlite::Connection,
+    track_id: u32,
+    credits: &[(u32, ArtistRole)],
+) -> Result<()> {
+    let mut stmt = conn.prepare_cached(query("track_artists_insert"))?;
+    for (artist_id, role) in credits {
+        stmt.execute((track_id, artist_id, role))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_track<'a>(album_id: u32, artist_id: u32, name: &'a str, path: &'a str) -> NewTrack<'a> {
+        NewTrack {
+            duration: 200,
+            album_name: "Album",
+            album_id,
+            artist_name: "Artist",
+            artist_id,
+            name,
+            number: 1,
+            path,
+            cover_path: "",
+            disc_number: 1,
+            track_total: None,
+            disc_total: None,
+            genre: None,
+            composer: None,
+            release_date: None,
+            codec: None,
+            sample_rate: None,
+            bit_depth: None,
+            channels: None,
+            bitrate: None,
+            lossless: false,
+            file: FileStamp {
+                size: 1,
+                modified: 1,
+                inode: None,
+            },
+        }
+    }
+
+    /// A database holding an album with a single track, returning the IDs of the album,
+    /// its artist and the track
+    fn library() -> (Database, u32, u32, u32) {
+        let db = Database::options().build().unwrap();
+
+        let ids = db
+            .write_batch(|batch| {
+                let artist_id = batch.insert(NewArtist { name: "Artist" })?;
+                let album_id = batch.insert_album(NewAlbum {
+                    artist_id,
+                    artist_name: "Artist",
+                    name: "Album",
+                    cover_path: "",
+                    year: 2005,
+                    album_type: &AlbumType::Unknown,
+                    track_count: 0,
+                    duration: 0,
+                    path: "/music/Album",
+                    compilation: false,
+                })?;
+                let track_id = batch.insert(new_track(
+                    album_id,
+                    artist_id,
+                    "First",
+                    "/music/Album/01.flac",
+                ))?;
+                batch.insert_track_artists(track_id, &[(artist_id, ArtistRole::Primary)])?;
+
+                Ok::<_, Error>((album_id, artist_id, track_id))
+            })
+            .unwrap();
+
+        (db, ids.0, ids.1, ids.2)
+    }
+
+    #[test]
+    fn batch_inserts_return_their_ids() {
+        let (db, album_id, artist_id, first) = library();
+
+        let (second, same) = db
+            .write_batch(|batch| {
+                let second = batch.insert(new_track(
+                    album_id,
+                    artist_id,
+                    "Second",
+                    "/music/Album/02.flac",
+                ))?;
+                let hash = new_track(album_id, artist_id, "Second", "").make_hash();
+
+                // Rows written earlier in the same batch are seen
+                Ok::<_, Error>((second, batch.tracks_by_hash(&hash)?))
+            })
+            .unwrap();
+
+        assert_ne!(first, second);
+        assert_eq!(same, vec![second]);
+        assert_eq!(db.by_id::<Tracks>(&first).unwrap().name, "First");
+        assert_eq!(db.by_id::<Tracks>(&second).unwrap().name, "Second");
+        assert_eq!(db.by_id::<Albums>(&album_id).unwrap().artist_id, artist_id);
+    }
+
+    #[test]
+    fn failed_batch_is_rolled_back() {
+        let (db, album_id, artist_id, _) = library();
+
+        let result = db.write_batch(|batch| {
+            batch.insert(new_track(
+                album_id,
+                artist_id,
+                "Second",
+                "/music/Album/02.flac",
+            ))?;
+            batch.insert(NewArtist { name: "Other" })?;
+            Err::<(), _>(Error::from(rusqlite::Error::InvalidQuery))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(db.rows::<Tracks>().unwrap(), 1);
+        assert_eq!(db.rows::<Artists>().unwrap(), 1);
+    }
+
+    #[test]
+    fn update_track_keeps_its_id() {
+        let (db, album_id, artist_id, track_id) = library();
+        let edited = new_track(album_id, artist_id, "Edited", "/music/Album/01.flac");
+        let hash = edited.make_hash();
+
+        db.write_batch(|batch| batch.update_track(track_id, edited))
+            .unwrap();
+
+        let track = db.by_id::<Tracks>(&track_id).unwrap();
+        assert_eq!(track.name, "Edited");
+        assert_eq!(track.hash, hash);
+        assert_eq!(db.rows::<Tracks>().unwrap(), 1);
+        assert_eq!(db.track_artist_ids(track_id).unwrap(), vec![artist_id]);
+    }
+
+    #[test]
+    fn delete_track_removes_its_credits_and_playlist_entries() {
+        let (db, _, _, track_id) = library();
+        let playlist_id = db
+            .insert(NewPlaylist {
+                name: "Playlist",
+                description: "",
+                cover_path: "",
+            })
+            .unwrap();
+        db.insert_track_to_playlist(&playlist_id, &track_id)
+            .unwrap();
+        assert_eq!(db.playlist_track_count(playlist_id).unwrap(), 1);
+
+        db.write_batch(|batch| batch.delete_track(track_id))
+            .unwrap();
+
+        assert_eq!(db.rows::<Tracks>().unwrap(), 0);
+        assert!(db.track_artist_ids(track_id).unwrap().is_empty());
+        assert!(
+            db.get_playlist_with_tracks(&playlist_id)
+                .unwrap()
+                .tracks
+                .is_empty()
+        );
+        assert_eq!(db.playlist_track_count(playlist_id).unwrap(), 0);
+    }
+
+    #[test]
+    fn update_album_path() {
+        let (db, album_id, _, _) = library();
+
+        db.write_batch(|batch| batch.update_album_path(album_id, "/music/Moved"))
+            .unwrap();
+
+        assert_eq!(db.by_id::<Albums>(&album_id).unwrap().path, "/music/Moved");
+    }
+}
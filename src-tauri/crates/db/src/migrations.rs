@@ -0,0 +1,435 @@
+use std::{fs, io, path::Path};
+
+use rusqlite::Connection;
+
+use crate::{Error, MIGRATION_DIR};
+
+/// A schema change, applied to databases whose `user_version` is below its version
+struct Migration {
+    version: u32,
+    sql: &'static str,
+}
+
+/// Every migration, ordered by version. Files are named `<version>_<description>.sql`.
+fn migrations() -> Vec<Migration> {
+    let mut migrations: Vec<Migration> = MIGRATION_DIR
+        .files()
+        .filter(|file| file.path().extension().is_some_and(|e| e == "sql"))
+        .map(|file| {
+            let name = file.path().file_stem().unwrap().to_str().unwrap();
+            let (version, _) = name
+                .split_once('_')
+                .unwrap_or_else(|| panic!("Migration '{name}' has no version"));
+
+            Migration {
+                version: version
+                    .parse()
+                    .unwrap_or_else(|_| panic!("Migration '{name}' has an invalid version")),
+                sql: file.contents_utf8().unwrap(),
+            }
+        })
+        .collect();
+
+    migrations.sort_by_key(|migration| migration.version);
+    migrations
+}
+
+/// Databases from before migrations were tracked are at `user_version` 0 whatever their
+/// schema, so their version is told from the newest change they already have
+const LEGACY_VERSIONS: &[(u32, &str)] = &[
+    (
+        8,
+        "SELECT 1 FROM sqlite_master WHERE type = 'index' AND name = 'idx_tracks_hash'",
+    ),
+    (
+        7,
+        "SELECT 1 FROM pragma_table_info('tracks') WHERE name = 'available'",
+    ),
+    (
+        6,
+        "SELECT 1 FROM pragma_table_info('tracks') WHERE name = 'file_size'",
+    ),
+    (
+        5,
+        "SELECT 1 FROM pragma_table_info('tracks') WHERE name = 'lossless'",
+    ),
+    (
+        4,
+        "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'track_artists'",
+    ),
+    (
+        3,
+        "SELECT 1 FROM pragma_table_info('albums') WHERE name = 'compilation'",
+    ),
+    (
+        2,
+        "SELECT 1 FROM pragma_table_info('tracks') WHERE name = 'disc_number'",
+    ),
+    (
+        1,
+        "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'tracks'",
+    ),
+];
+
+fn user_version(conn: &Connection) -> rusqlite::Result<u32> {
+    conn.prepare("PRAGMA user_version")?
+        .query_row([], |row| row.get(0))
+}
+
+fn legacy_version(conn: &Connection) -> rusqlite::Result<u32> {
+    for (version, has_change) in LEGACY_VERSIONS {
+        if conn.prepare(has_change)?.exists([])? {
+            return Ok(*version);
+        }
+    }
+
+    Ok(0)
+}
+
+/// The version of the database's schema, and the newest one there's a migration to
+pub(crate) fn versions(conn: &Connection) -> rusqlite::Result<(u32, u32)> {
+    let latest = migrations().last().map_or(0, |migration| migration.version);
+    let version = match user_version(conn)? {
+        0 => legacy_version(conn)?,
+        tracked => tracked,
+    };
+
+    Ok((version, latest))
+}
+
+/// Bring the schema of the database up to date with the newest migration.
+///
+/// A copy of an existing `db_file` is kept next to it before it's migrated.
+/// Every pending migration runs in one transaction, so a failing one leaves the
+/// database as it was. So do migrations that leave a row referencing a missing one.
+pub(crate) fn migrate(conn: &mut Connection, db_file: Option<&Path>) -> Result<(), Error> {
+    let tracked = user_version(conn)?;
+    let (version, latest) = versions(conn)?;
+
+    if version >= latest {
+        // Start tracking the version of databases that are already up to date
+        if tracked < version {
+            conn.execute_batch(&format!("PRAGMA user_version = {version}"))?;
+        }
+
+        return Ok(());
+    }
+
+    if version > 0
+        && let Some(db_file) = db_file
+    {
+        let backup = db_file.with_extension(format!("v{version}.sqlite"));
+        logging::info!(
+            "Backing up database to {} before migrating",
+            backup.display()
+        );
+
+        // A backup from an earlier, failed attempt is replaced
+        if let Err(e) = fs::remove_file(&backup)
+            && e.kind() != io::ErrorKind::NotFound
+        {
+            return Err(e.into());
+        }
+
+        conn.execute("VACUUM INTO ?1", [backup.to_string_lossy()])?;
+    }
+
+    // Tables are rebuilt by dropping them, which would cascade to every row referencing
+    // them otherwise, and the pragma has no effect inside a transaction
+    let foreign_keys: bool = conn
+        .prepare("PRAGMA foreign_keys")?
+        .query_row([], |row| row.get(0))?;
+    conn.execute_batch("PRAGMA foreign_keys = OFF")?;
+
+    let tx = conn.transaction()?;
+    for migration in migrations().iter().filter(|m| m.version > version) {
+        logging::info!("Migrating database to version {}", migration.version);
+        tx.execute_batch(migration.sql)?;
+    }
+
+    // Foreign keys are enforced from here on, a row referencing a missing one would fail
+    // any write that touches it
+    {
+        let mut check = tx.prepare("PRAGMA foreign_key_check")?;
+        if let Some(row) = check.query([])?.next()? {
+            return Err(Error::ForeignKeyViolation {
+                table: row.get(0)?,
+                parent: row.get(2)?,
+            });
+        }
+    }
+
+    tx.pragma_update(None, "user_version", latest)?;
+    tx.commit()?;
+
+    conn.execute_batch(&format!("PRAGMA foreign_keys = {foreign_keys}"))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Database;
+    use common::Tracks;
+    use std::path::PathBuf;
+
+    /// Rows every version of the schema can hold
+    const FIXTURE: &str = "
+        INSERT INTO artists (id, name) VALUES (1, 'Artist'), (2, 'Album Artist');
+        INSERT INTO albums (id, name, year, type, track_count, duration, cover_path, path)
+        VALUES (1, 'Album', 2005, 'Single', 2, 400, '/covers/album.jpg', '/music/Album');
+        INSERT INTO album_artists (album_id, artist_id) VALUES (1, 2);
+        INSERT INTO tracks (id, album_id, artist_id, album_name, artist_name, name, number,
+            duration, cover_path, path, hash)
+        VALUES
+            (1, 1, 1, 'Album', 'Artist', 'First', 1, 200, '/covers/album.jpg',
+                '/music/Album/01.flac', 'first'),
+            (2, 1, 1, 'Album', 'Artist', 'Second', 2, 200, '/covers/album.jpg',
+                '/music/Album/02.flac', 'second');
+        INSERT INTO playlists (id, name, description, cover_path)
+        VALUES (1, 'Playlist', '', '');
+        INSERT INTO playlist_tracks (playlist_id, track_id) VALUES (1, 1), (1, 2);
+    ";
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let path = std::env::temp_dir().join(format!("db_{}_{name}", std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// A database at `version` holding the fixture rows, with `user_version` left at 0
+    /// when it's from before migrations were tracked
+    fn fixture(dir: &Path, version: u32, tracked: bool) {
+        let conn = rusqlite::Connection::open(dir.join("db.sqlite")).unwrap();
+
+        for migration in migrations().iter().filter(|m| m.version <= version) {
+            conn.execute_batch(migration.sql).unwrap();
+        }
+
+        conn.execute_batch(FIXTURE).unwrap();
+
+        if tracked {
+            conn.pragma_update(None, "user_version", version).unwrap();
+        }
+    }
+
+    /// Columns and indexes of every table, which have to match however the database got
+    /// to the newest version
+    fn schema(dir: &Path) -> Vec<String> {
+        let conn = rusqlite::Connection::open(dir.join("db.sqlite")).unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT m.name, p.name, p.type, p.[notnull], p.dflt_value, p.pk
+                FROM sqlite_master m, pragma_table_info(m.name) p
+                WHERE m.type = 'table'
+                UNION ALL
+                SELECT tbl_name, name, type, 0, NULL, 0 FROM sqlite_master
+                WHERE type IN ('index', 'trigger')
+                ORDER BY 1, 2",
+            )
+            .unwrap();
+
+        stmt.query_map([], |row| {
+            Ok(format!(
+                "{}.{} {} {} {:?} {}",
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, bool>(3)?,
+                row.get::<_, Option<String>>(4)?,
+                row.get::<_, u32>(5)?,
+            ))
+        })
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap()
+    }
+
+    fn latest() -> u32 {
+        migrations().last().unwrap().version
+    }
+
+    #[test]
+    fn versions_are_consecutive() {
+        let versions: Vec<u32> = migrations().iter().map(|m| m.version).collect();
+        assert_eq!(versions, (1..=latest()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn creates_new_database() {
+        let dir = TempDir::new("migrate_new");
+        Database::new(dir.0.clone()).unwrap();
+
+        let conn = rusqlite::Connection::open(dir.0.join("db.sqlite")).unwrap();
+        assert_eq!(user_version(&conn).unwrap(), latest());
+
+        // Nothing to back up
+        for entry in fs::read_dir(&dir.0).unwrap() {
+            let name = entry.unwrap().file_name();
+            assert!(name.to_string_lossy().starts_with("db.sqlite"), "{name:?}");
+        }
+    }
+
+    #[test]
+    fn upgrades_from_every_version() {
+        let fresh = TempDir::new("migrate_fresh");
+        Database::new(fresh.0.clone()).unwrap();
+        let expected = schema(&fresh.0);
+
+        for version in 1..=latest() {
+            for tracked in [true, false] {
+                // Only versions that changed the schema can be told apart without tracking
+                if !tracked && !LEGACY_VERSIONS.iter().any(|(legacy, _)| *legacy == version) {
+                    continue;
+                }
+
+                let dir = TempDir::new(&format!("migrate_{version}_{tracked}"));
+                fixture(&dir.0, version, tracked);
+
+                let db = Database::new(dir.0.clone()).unwrap();
+                let context = format!("from version {version}, tracked: {tracked}");
+
+                assert_eq!(schema(&dir.0), expected, "{context}");
+                assert_eq!(
+                    user_version(&rusqlite::Connection::open(dir.0.join("db.sqlite")).unwrap())
+                        .unwrap(),
+                    latest(),
+                    "{context}"
+                );
+
+                let track = db.by_id::<Tracks>(&2).unwrap();
+                assert_eq!(track.name, "Second", "{context}");
+                assert_eq!(track.disc_number, 1, "{context}");
+                assert!(track.available, "{context}");
+
+                // The fixture only credits track artists itself once it can
+                if version < 4 {
+                    assert_eq!(db.track_artist_ids(1).unwrap(), vec![1], "{context}");
+                }
+                assert_eq!(db.get_playlist_with_tracks(&1).unwrap().tracks.len(), 2);
+
+                let backup = dir.0.join(format!("db.v{version}.sqlite"));
+                assert_eq!(backup.exists(), version < latest(), "{context}");
+            }
+        }
+    }
+
+    #[test]
+    fn failed_migration_leaves_database_untouched() {
+        let dir = TempDir::new("migrate_failed");
+        fixture(&dir.0, 1, true);
+
+        let conn = rusqlite::Connection::open(dir.0.join("db.sqlite")).unwrap();
+        // Makes the migration that adds it fail halfway through
+        conn.execute_batch("ALTER TABLE tracks ADD COLUMN codec TEXT")
+            .unwrap();
+        drop(conn);
+
+        let mut conn = rusqlite::Connection::open(dir.0.join("db.sqlite")).unwrap();
+        assert!(migrate(&mut conn, Some(&dir.0.join("db.sqlite"))).is_err());
+
+        assert_eq!(user_version(&conn).unwrap(), 1);
+        assert!(
+            !conn
+                .prepare("SELECT 1 FROM pragma_table_info('tracks') WHERE name = 'genre'")
+                .unwrap()
+                .exists([])
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn removes_orphan_rows() {
+        let dir = TempDir::new("migrate_orphans");
+        fixture(&dir.0, 8, true);
+
+        // Left behind while foreign keys weren't enforced
+        let conn = rusqlite::Connection::open(dir.0.join("db.sqlite")).unwrap();
+        conn.execute_batch(
+            "PRAGMA foreign_keys = OFF;
+            INSERT INTO tracks (id, album_id, artist_id, album_name, artist_name, name, number,
+                duration, cover_path, path, hash)
+            VALUES
+                (3, 9, 1, 'Gone', 'Artist', 'Albumless', 1, 200, '', '/music/Gone/01.flac',
+                    'albumless'),
+                (4, 1, 9, 'Album', 'Gone Artist', 'Third', 3, 200, '', '/music/Album/03.flac',
+                    'third');
+            INSERT INTO playlist_tracks (playlist_id, track_id) VALUES (1, 99), (9, 1), (1, 3);
+            INSERT INTO track_artists (track_id, artist_id, role)
+            VALUES (1, 1, 'primary'), (99, 1, 'primary'), (1, 99, 'featured');
+            INSERT INTO album_artists (album_id, artist_id) VALUES (9, 1), (1, 99);",
+        )
+        .unwrap();
+        assert_eq!(
+            conn.query_row("SELECT track_count FROM playlists", [], |row| row
+                .get::<_, u32>(0))
+                .unwrap(),
+            4
+        );
+
+        let mut conn = rusqlite::Connection::open(dir.0.join("db.sqlite")).unwrap();
+        migrate(&mut conn, Some(&dir.0.join("db.sqlite"))).unwrap();
+
+        let count = |sql: &str| conn.query_row(sql, [], |row| row.get::<_, u32>(0)).unwrap();
+        assert_eq!(count("SELECT COUNT(*) FROM pragma_foreign_key_check"), 0);
+        assert_eq!(count("SELECT track_count FROM playlists"), 2);
+        assert_eq!(count("SELECT COUNT(*) FROM playlist_tracks"), 2);
+        assert_eq!(count("SELECT COUNT(*) FROM tracks WHERE id = 3"), 0);
+        assert_eq!(count("SELECT COUNT(*) FROM track_artists"), 1);
+        assert_eq!(count("SELECT COUNT(*) FROM album_artists"), 1);
+
+        // Credited to a recreated artist instead of removed
+        assert_eq!(
+            count(
+                "SELECT COUNT(*) FROM tracks t JOIN artists a ON a.id = t.artist_id
+                WHERE t.id = 4 AND a.name = 'Gone Artist'"
+            ),
+            1
+        );
+    }
+
+    #[test]
+    fn migration_leaving_orphans_is_rolled_back() {
+        let dir = TempDir::new("migrate_orphaned");
+        fixture(&dir.0, 8, true);
+
+        let conn = rusqlite::Connection::open(dir.0.join("db.sqlite")).unwrap();
+        // Leaves a row behind while migrating
+        conn.execute_batch(
+            "CREATE TRIGGER orphan AFTER UPDATE ON playlists
+            BEGIN
+                INSERT OR IGNORE INTO album_artists (album_id, artist_id) VALUES (99, 1);
+            END;",
+        )
+        .unwrap();
+        drop(conn);
+
+        let mut conn = rusqlite::Connection::open(dir.0.join("db.sqlite")).unwrap();
+        assert!(matches!(
+            migrate(&mut conn, Some(&dir.0.join("db.sqlite"))),
+            Err(Error::ForeignKeyViolation { table, parent })
+                if table == "album_artists" && parent == "albums"
+        ));
+
+        assert_eq!(user_version(&conn).unwrap(), 8);
+        assert!(
+            !conn
+                .prepare("SELECT 1 FROM album_artists WHERE album_id = 99")
+                .unwrap()
+                .exists([])
+                .unwrap()
+        );
+    }
+}
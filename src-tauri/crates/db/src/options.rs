@@ -0,0 +1,284 @@
+use std::{
+    fs,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use r2d2::{CustomizeConnection, ManageConnection, Pool};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{Connection, OpenFlags};
+
+use crate::{Database, Error, Result, migrations, timed_connection::TimedPool};
+
+/// Gives every in-memory database of the process its own name, so pools don't share one
+static MEMORY_DATABASES: AtomicUsize = AtomicUsize::new(0);
+
+/// Where the database lives
+enum Location {
+    /// `db.sqlite` inside the directory, which is created if it doesn't exist
+    Directory(PathBuf),
+    /// Gone once the [`Database`] is dropped
+    Memory,
+}
+
+/// How to open a [`Database`], created through [`Database::options`]
+pub struct DatabaseOptions {
+    location: Location,
+    read_only: bool,
+    pool_size: u32,
+    busy_timeout: Duration,
+}
+
+impl Default for DatabaseOptions {
+    fn default() -> Self {
+        Self {
+            location: Location::Memory,
+            read_only: false,
+            pool_size: 10,
+            busy_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl DatabaseOptions {
+    /// Keep the database as `db.sqlite` in `dir`
+    #[must_use]
+    pub fn path(mut self, dir: PathBuf) -> Self {
+        self.location = Location::Directory(dir);
+        self
+    }
+
+    /// Keep the database in memory, which is the default
+    #[must_use]
+    pub fn in_memory(mut self) -> Self {
+        self.location = Location::Memory;
+        self
+    }
+
+    /// Open the database without writing to it, nor creating or migrating it
+    #[must_use]
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Most connections the pool keeps open at once, 10 by default
+    #[must_use]
+    pub fn pool_size(mut self, pool_size: u32) -> Self {
+        self.pool_size = pool_size;
+        self
+    }
+
+    /// How long a connection waits on another one's lock before giving up, 5 seconds by default
+    #[must_use]
+    pub fn busy_timeout(mut self, busy_timeout: Duration) -> Self {
+        self.busy_timeout = busy_timeout;
+        self
+    }
+
+    /// Consume `DatabaseOptions`, opening the database and migrating it to the newest schema.
+    ///
+    /// # Errors
+    /// Returns [`Error::OutdatedSchema`] if a read-only database isn't on the newest schema,
+    /// or any error from creating, opening or migrating the database.
+    pub fn build(self) -> Result<Database> {
+        let mut flags = OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        flags |= if self.read_only {
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+        } else {
+            OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE
+        };
+
+        let (manager, db_file) = match &self.location {
+            Location::Directory(dir) => {
+                if !self.read_only {
+                    fs::create_dir_all(dir)?;
+                }
+
+                let db_file = dir.join("db.sqlite");
+                (SqliteConnectionManager::file(&db_file), Some(db_file))
+            }
+            Location::Memory => {
+                // Connections only see the same in-memory database through a shared cache
+                let id = MEMORY_DATABASES.fetch_add(1, Ordering::Relaxed);
+                let uri = format!(
+                    "file:veil-{}-{id}?mode=memory&cache=shared",
+                    std::process::id()
+                );
+                (SqliteConnectionManager::file(uri), None)
+            }
+        };
+        let manager = manager.with_flags(flags);
+
+        let customizer = ConnectionCustomizer {
+            read_only: self.read_only,
+            in_memory: db_file.is_none(),
+            busy_timeout: self.busy_timeout,
+        };
+
+        // Opened outside of the pool, which would retry until timing out on a database
+        // that can't be opened. It also keeps an in-memory database alive until the pool
+        // holds connections to it.
+        let mut conn = manager.connect()?;
+        customizer.on_acquire(&mut conn)?;
+
+        if self.read_only {
+            let (version, latest) = migrations::versions(&conn)?;
+            if version < latest {
+                return Err(Error::OutdatedSchema { version, latest });
+            }
+        } else {
+            migrations::migrate(&mut conn, db_file.as_deref())?;
+        }
+
+        let mut builder = Pool::builder()
+            .max_size(self.pool_size)
+            .connection_customizer(Box::new(customizer));
+
+        // An in-memory database is gone once its last connection closes
+        if db_file.is_none() {
+            builder = builder.idle_timeout(None).max_lifetime(None);
+        }
+
+        let pool = builder.build(manager)?;
+        drop(conn);
+
+        Ok(Database {
+            pool: TimedPool(pool),
+        })
+    }
+}
+
+/// Sets up every connection of the pool, as PRAGMAs only last for the connection they're
+/// run on
+#[derive(Debug)]
+struct ConnectionCustomizer {
+    read_only: bool,
+    in_memory: bool,
+    busy_timeout: Duration,
+}
+
+impl CustomizeConnection<Connection, rusqlite::Error> for ConnectionCustomizer {
+    fn on_acquire(&self, conn: &mut Connection) -> Result<(), rusqlite::Error> {
+        conn.busy_timeout(self.busy_timeout)?;
+        conn.execute_batch(
+            "PRAGMA foreign_keys = ON;
+            PRAGMA synchronous = NORMAL;",
+        )?;
+
+        // Changing the journal writes to the database file
+        if !self.read_only && !self.in_memory {
+            conn.execute_batch(
+                "PRAGMA journal_mode = WAL;
+                PRAGMA journal_size_limit = 6144000;",
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn in_memory_is_shared_by_the_pool() {
+        let db = Database::options().pool_size(2).build().unwrap();
+
+        let first = db.pool.get().unwrap();
+        let second = db.pool.get().unwrap();
+        first
+            .execute("INSERT INTO artists (id, name) VALUES (1, 'Artist')", [])
+            .unwrap();
+
+        assert!(
+            second
+                .prepare("SELECT 1 FROM artists WHERE id = 1")
+                .unwrap()
+                .exists([])
+                .unwrap()
+        );
+
+        // Other in-memory databases are separate
+        let other = Database::options().build().unwrap();
+        let conn = other.pool.get().unwrap();
+        assert!(
+            !conn
+                .prepare("SELECT 1 FROM artists")
+                .unwrap()
+                .exists([])
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn every_connection_enforces_foreign_keys() {
+        let db = Database::options().pool_size(3).build().unwrap();
+
+        let conns: Vec<_> = (0..3).map(|_| db.pool.get().unwrap()).collect();
+        for conn in &conns {
+            let foreign_keys: bool = conn
+                .prepare("PRAGMA foreign_keys")
+                .unwrap()
+                .query_row([], |row| row.get(0))
+                .unwrap();
+            assert!(foreign_keys);
+
+            assert!(
+                conn.execute(
+                    "INSERT INTO album_artists (album_id, artist_id) VALUES (1, 1)",
+                    []
+                )
+                .is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn read_only() {
+        let dir = std::env::temp_dir().join(format!("db_{}_read_only", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        // Isn't created
+        assert!(
+            Database::options()
+                .path(dir.clone())
+                .read_only(true)
+                .build()
+                .is_err()
+        );
+        assert!(!dir.exists());
+
+        Database::options().path(dir.clone()).build().unwrap();
+
+        let db = Database::options()
+            .path(dir.clone())
+            .read_only(true)
+            .build()
+            .unwrap();
+        let conn = db.pool.get().unwrap();
+        assert!(
+            conn.execute("INSERT INTO artists (id, name) VALUES (1, 'Artist')", [])
+                .is_err()
+        );
+        drop(conn);
+        drop(db);
+
+        // Isn't migrated
+        let conn = Connection::open(dir.join("db.sqlite")).unwrap();
+        conn.pragma_update(None, "user_version", 1).unwrap();
+        drop(conn);
+
+        assert!(matches!(
+            Database::options()
+                .path(dir.clone())
+                .read_only(true)
+                .build(),
+            Err(Error::OutdatedSchema { version: 1, .. })
+        ));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -480,6 +480,19 @@ mod tests {
             cover_path: "a".into(),
             path: "a".into(),
             hash: "a".into(),
+            disc_number: 1,
+            track_total: None,
+            disc_total: None,
+            genre: None,
+            composer: None,
+            release_date: None,
+            codec: None,
+            sample_rate: None,
+            bit_depth: None,
+            channels: None,
+            bitrate: None,
+            lossless: false,
+            available: true,
         }
     }
 
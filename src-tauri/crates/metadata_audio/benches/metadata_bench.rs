@@ -12,13 +12,7 @@ fn bench_read_flac_from_bytes(c: &mut Criterion) {
     group.bench_function("no_skip", |b| {
         b.iter(|| {
             buffer.clear();
-            let _ = Metadata::from_bytes(
-                &mut buffer,
-                &data,
-                metadata_audio::SupportedFormats::Flac,
-                false,
-            )
-            .unwrap();
+            let _ = Metadata::from_bytes(&mut buffer, &data, false).unwrap();
         });
     });
 
@@ -26,13 +20,7 @@ fn bench_read_flac_from_bytes(c: &mut Criterion) {
     group.bench_function("skip", |b| {
         b.iter(|| {
             buffer.clear();
-            let _ = Metadata::from_bytes(
-                &mut buffer,
-                &data,
-                metadata_audio::SupportedFormats::Flac,
-                true,
-            )
-            .unwrap();
+            let _ = Metadata::from_bytes(&mut buffer, &data, true).unwrap();
         });
     });
 
@@ -49,13 +37,7 @@ fn bench_read_id3_from_bytes(c: &mut Criterion) {
     group.bench_function("no_skip", |b| {
         b.iter(|| {
             buffer.clear();
-            let _ = Metadata::from_bytes(
-                &mut buffer,
-                &data,
-                metadata_audio::SupportedFormats::ID3,
-                false,
-            )
-            .unwrap();
+            let _ = Metadata::from_bytes(&mut buffer, &data, false).unwrap();
         });
     });
 
@@ -73,13 +55,7 @@ fn bench_read_flac_from_bytes_mass(c: &mut Criterion) {
         b.iter(|| {
             for _ in 0..100 {
                 buffer.clear();
-                let _ = Metadata::from_bytes(
-                    &mut buffer,
-                    &data,
-                    metadata_audio::SupportedFormats::Flac,
-                    false,
-                )
-                .unwrap();
+                let _ = Metadata::from_bytes(&mut buffer, &data, false).unwrap();
             }
         });
     });
@@ -89,13 +65,7 @@ fn bench_read_flac_from_bytes_mass(c: &mut Criterion) {
         b.iter(|| {
             for _ in 0..100 {
                 buffer.clear();
-                let _ = Metadata::from_bytes(
-                    &mut buffer,
-                    &data,
-                    metadata_audio::SupportedFormats::Flac,
-                    true,
-                )
-                .unwrap();
+                let _ = Metadata::from_bytes(&mut buffer, &data, true).unwrap();
             }
         });
     });
@@ -105,13 +75,7 @@ fn bench_read_flac_from_bytes_mass(c: &mut Criterion) {
         b.iter(|| {
             for _ in 0..100 {
                 let mut buffer = Vec::with_capacity(1024 * 32);
-                let _ = Metadata::from_bytes(
-                    &mut buffer,
-                    &data,
-                    metadata_audio::SupportedFormats::Flac,
-                    false,
-                )
-                .unwrap();
+                let _ = Metadata::from_bytes(&mut buffer, &data, false).unwrap();
             }
         });
     });
@@ -121,13 +85,7 @@ fn bench_read_flac_from_bytes_mass(c: &mut Criterion) {
         b.iter(|| {
             for _ in 0..100 {
                 let mut buffer = Vec::with_capacity(1024 * 32);
-                let _ = Metadata::from_bytes(
-                    &mut buffer,
-                    &data,
-                    metadata_audio::SupportedFormats::Flac,
-                    true,
-                )
-                .unwrap();
+                let _ = Metadata::from_bytes(&mut buffer, &data, true).unwrap();
             }
         });
     });
@@ -0,0 +1,22 @@
+//! Every input has to come back as either metadata, tags or an error, never a panic.
+//!
+//! `cargo +nightly fuzz run from_bytes fuzz/corpus/from_bytes fuzz/seeds/from_bytes`
+//!
+//! New inputs are written to the first directory, the seeds only hold a small valid
+//! file of every supported format.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use metadata_audio::{Metadata, TagMap};
+
+fuzz_target!(|data: &[u8]| {
+    let mut buffer = Vec::new();
+    let _ = Metadata::from_bytes(&mut buffer, data, false);
+
+    // Skipping pictures takes different paths through FLAC, MP4 & Ogg
+    buffer.clear();
+    let _ = Metadata::from_bytes(&mut buffer, data, true);
+
+    buffer.clear();
+    let _ = TagMap::from_bytes(&mut buffer, data);
+});
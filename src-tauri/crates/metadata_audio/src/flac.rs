@@ -1,10 +1,13 @@
 use std::io::{Read, Seek};
 
 use crate::{
-    Error, Result, read_into_buffer_unchecked, read_n_bits_u32, read_n_bits_u64, u32_from_bytes_be,
-    u32_from_bytes_le,
+    Error, Result, parse_position, picture::Picture, read_into_buffer_unchecked, read_n_bits_u32,
+    read_n_bits_u64, slice_at, u32_from_bytes_le,
 };
 
+/// STREAMINFO always has the same size, no matter the stream
+const STREAM_INFO_SIZE: usize = 34;
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[repr(u8)]
 pub enum BlockType {
@@ -44,9 +47,9 @@ pub struct BlockHeader {
 impl<'a> Block<'a> {
     pub fn parse_by_block_type(block_type: BlockType, data: &'a [u8]) -> Result<Block<'a>> {
         match block_type {
-            BlockType::StreamInfo => Ok(Block::StreamInfo(StreamInfo::from_bytes(data))),
+            BlockType::StreamInfo => Ok(Block::StreamInfo(StreamInfo::from_bytes(data)?)),
             BlockType::VorbisComment => Ok(Block::VorbisComment(VorbisComment::from_bytes(data)?)),
-            BlockType::Picture => Ok(Block::Picture(Picture::from_bytes(data))),
+            BlockType::Picture => Ok(Block::Picture(Picture::from_flac_bytes(data)?)),
             BlockType::Unknown => Ok(Block::Unknown),
         }
     }
@@ -77,6 +80,8 @@ impl<'a> Block<'a> {
 pub struct StreamInfo {
     pub duration: f32,
     pub sample_rate: u32,
+    pub channels: u8,
+    pub bits_per_sample: u8,
     pub total_samples: u64,
 }
 
@@ -91,11 +96,17 @@ impl StreamInfo {
         StreamInfo {
             duration: -1.0,
             sample_rate: 0,
+            channels: 0,
+            bits_per_sample: 0,
             total_samples: 0,
         }
     }
 
-    pub fn from_bytes(bytes: &[u8]) -> StreamInfo {
+    pub fn from_bytes(bytes: &[u8]) -> Result<StreamInfo> {
+        if bytes.len() < STREAM_INFO_SIZE {
+            return Err(Error::TruncatedBlock);
+        }
+
         let mut stream_info = StreamInfo::new();
         let mut i = 0;
 
@@ -115,18 +126,22 @@ impl StreamInfo {
         stream_info.sample_rate = read_n_bits_u32(&bytes[i..i + 3], 0, 20);
         i += 2; // But only increment by 2 bytes
 
-        // We need to skip the first 4 bits to reach where sample rate ended
-        // stream_info.channels = read_n_bits(&bytes[i..i + 1], 4, 3);
-        // stream_info.bits_per_sample = read_n_bits(&bytes[i..i + 2], 7, 5);
+        // We need to skip the first 4 bits to reach where sample rate ended,
+        // both are stored as one less than their value
+        stream_info.channels = read_n_bits_u32(&bytes[i..i + 1], 4, 3) as u8 + 1;
+        stream_info.bits_per_sample = read_n_bits_u32(&bytes[i..i + 2], 7, 5) as u8 + 1;
         i += 1;
 
         // from last one we're at bit 24, but bits_per_sample reached until 28 so we need to skip 4 bits
         // total_samples is 36 bits long which is 4.5 bytes, so we need to read 5 bytes
         stream_info.total_samples = read_n_bits_u64(&bytes[i..i + 5], 4, 36);
 
-        stream_info.duration = stream_info.total_samples as f32 / stream_info.sample_rate as f32;
+        if stream_info.sample_rate > 0 {
+            stream_info.duration =
+                stream_info.total_samples as f32 / stream_info.sample_rate as f32;
+        }
 
-        stream_info
+        Ok(stream_info)
     }
 }
 
@@ -138,50 +153,108 @@ pub struct VorbisComment<'a> {
 
     pub album_artist: Option<&'a str>,
 
+    /// Track artists, the first one is the main artist and any others are featured
+    pub artists: Vec<&'a str>,
+
     pub title: Option<&'a str>,
 
     pub year: Option<u16>,
 
+    /// `DATE`, which is usually a full `yyyy-MM-dd` date
+    pub date: Option<&'a str>,
+
     pub track_number: Option<u32>,
+
+    pub track_total: Option<u32>,
+
+    pub disc_number: Option<u32>,
+
+    pub disc_total: Option<u32>,
+
+    pub genre: Option<&'a str>,
+
+    pub composers: Vec<&'a str>,
+
+    pub remixers: Vec<&'a str>,
+
+    /// `COMPILATION=1`, as written by most taggers for "Various Artists" releases
+    pub compilation: bool,
 }
 
+/// The key & value of every field in a Vorbis comment
+type VorbisFields<'a> = Vec<(&'a [u8], &'a str)>;
+
 impl<'a> VorbisComment<'a> {
     pub fn from_bytes(bytes: &'a [u8]) -> Result<VorbisComment<'a>> {
         let mut vorbis = VorbisComment::default();
-        let mut i = 0;
-
-        let vendor_length = u32_from_bytes_le(bytes, &mut i) as usize;
 
-        let vendor_string = std::str::from_utf8(&bytes[i..i + vendor_length])?;
+        let (vendor_string, fields) = Self::fields(bytes)?;
         vorbis.vendor_string = Some(vendor_string);
 
+        for (key, value_raw) in fields {
+            let value = value_raw.trim();
+
+            // Field names are case-insensitive, and fields may repeat to hold multiple values
+            match key.to_ascii_uppercase().as_slice() {
+                b"ALBUM" => vorbis.album = Some(value),
+                b"ALBUMARTIST" => vorbis.album_artist = Some(value),
+                b"ARTIST" => vorbis.artists.push(value),
+                b"COMPILATION" => vorbis.compilation = value == "1",
+                b"TITLE" => vorbis.title = Some(value),
+                b"YEAR" => vorbis.year = Self::parse_u16_ascii(value_raw.as_bytes()),
+                b"DATE" => vorbis.date = Some(value),
+                b"GENRE" => vorbis.genre = Some(value),
+                b"COMPOSER" => vorbis.composers.push(value),
+                b"REMIXER" => vorbis.remixers.push(value),
+                // Some taggers write the total into the number, as in "3/12"
+                b"TRACKNUMBER" => {
+                    let (number, total) = parse_position(value);
+                    vorbis.track_number = number;
+                    vorbis.track_total = vorbis.track_total.or(total);
+                }
+                b"DISCNUMBER" => {
+                    let (number, total) = parse_position(value);
+                    vorbis.disc_number = number;
+                    vorbis.disc_total = vorbis.disc_total.or(total);
+                }
+                b"TRACKTOTAL" | b"TOTALTRACKS" => {
+                    vorbis.track_total = Self::parse_u32_ascii(value.as_bytes());
+                }
+                b"DISCTOTAL" | b"TOTALDISCS" => {
+                    vorbis.disc_total = Self::parse_u32_ascii(value.as_bytes());
+                }
+                _ => {}
+            }
+        }
+
+        Ok(vorbis)
+    }
+
+    /// The vendor string and every `KEY=value` field in the order they're stored,
+    /// fields without a `=` are left out
+    pub(crate) fn fields(bytes: &'a [u8]) -> Result<(&'a str, VorbisFields<'a>)> {
+        let mut i = 0;
+
+        let vendor_length = u32_from_bytes_le(bytes, &mut i)? as usize;
+        let vendor_string = std::str::from_utf8(slice_at(bytes, i, vendor_length)?)?;
         i += vendor_length;
 
-        let num_comments = u32_from_bytes_le(bytes, &mut i);
+        let num_comments = u32_from_bytes_le(bytes, &mut i)?;
+
+        let mut fields = Vec::new();
         for _ in 0..num_comments {
-            let comment_length = u32_from_bytes_le(bytes, &mut i) as usize;
-            let comment_slice = &bytes[i..i + comment_length];
+            let comment_length = u32_from_bytes_le(bytes, &mut i)? as usize;
+            let comment_slice = slice_at(bytes, i, comment_length)?;
 
             if let Some(eq_pos) = comment_slice.iter().position(|&b| b == b'=') {
                 let (key, value) = comment_slice.split_at(eq_pos);
-
-                let value_raw = &value[1..];
-                let value = std::str::from_utf8(value_raw)?.trim();
-
-                match key {
-                    b"ALBUM" => vorbis.album = Some(value),
-                    b"ALBUMARTIST" => vorbis.album_artist = Some(value),
-                    b"TITLE" => vorbis.title = Some(value),
-                    b"YEAR" => vorbis.year = Self::parse_u16_ascii(value_raw),
-                    b"TRACKNUMBER" => vorbis.track_number = Self::parse_u32_ascii(value_raw),
-                    _ => {}
-                }
+                fields.push((key, std::str::from_utf8(&value[1..])?));
             }
 
             i += comment_length;
         }
 
-        Ok(vorbis)
+        Ok((vendor_string, fields))
     }
 
     #[inline(always)]
@@ -191,7 +264,7 @@ impl<'a> VorbisComment<'a> {
             if !b.is_ascii_digit() {
                 return None;
             }
-            n = n * 10 + (b - b'0') as u16;
+            n = n.checked_mul(10)?.checked_add(u16::from(b - b'0'))?;
         }
         Some(n)
     }
@@ -207,64 +280,16 @@ impl<'a> VorbisComment<'a> {
             if !b.is_ascii_digit() {
                 return None;
             }
-            n = n * 10 + (b - b'0') as u32;
+            n = n.checked_mul(10)?.checked_add(u32::from(b - b'0'))?;
         }
         Some(n)
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct Picture<'a> {
-    // pub picture_type: u32,
-    // pub mime_type: String,
-    // pub description: String,
-    // pub width: u32,
-    // pub height: u32,
-    // pub color_depth: u32,
-    // pub indexed_color: u32,
-    pub data: &'a [u8],
-}
-
-impl<'a> Picture<'a> {
-    pub fn from_bytes(bytes: &'a [u8]) -> Picture<'a> {
-        let mut i = 0;
-
-        // picture.picture_type = u32_from_bytes(Endian::Big, &bytes, &mut i);
-        i += 4;
-
-        let mime_length = u32_from_bytes_be(bytes, &mut i) as usize;
-
-        // picture.mime_type = String::from_utf8_lossy(&bytes[i..i + mime_length]).to_string();
-        i += mime_length;
-
-        let description_length = u32_from_bytes_be(bytes, &mut i) as usize;
-
-        // picture.description =
-        //     String::from_utf8_lossy(&bytes[i..i + description_length]).to_string();
-        i += description_length;
-
-        // picture.width = u32_from_bytes(Endian::Big, &bytes, &mut i);
-
-        // picture.height = u32_from_bytes(Endian::Big, &bytes, &mut i);
-
-        // picture.color_depth = u32_from_bytes(Endian::Big, &bytes, &mut i);
-
-        // picture.indexed_color = u32_from_bytes(Endian::Big, &bytes, &mut i);
-
-        i += 16;
-
-        let picture_length = u32_from_bytes_be(bytes, &mut i) as usize;
-
-        Picture {
-            data: &bytes[i..i + picture_length],
-        }
-    }
-}
-
 #[derive(Debug, Clone)]
 pub struct Flac<'a> {
     pub stream_info: StreamInfo,
-    pub picture: Option<Picture<'a>>,
+    pub pictures: Vec<Picture<'a>>,
     pub vorbis_comment: VorbisComment<'a>,
 }
 
@@ -281,10 +306,6 @@ impl<'a> Flac<'a> {
             return Err(Error::InvalidFlacSignature);
         }
 
-        let mut stream_info_found = false;
-        let mut vorbis_comment_found = false;
-        let mut picture_found = false;
-
         let mut headers = Vec::with_capacity(3);
         loop {
             let block_header = Block::parse_block_header(reader)?;
@@ -303,20 +324,12 @@ impl<'a> Flac<'a> {
                     block_type: block_header.block_type,
                     is_last,
                 });
-
-                match block_header.block_type {
-                    BlockType::StreamInfo => stream_info_found = true,
-                    BlockType::VorbisComment => vorbis_comment_found = true,
-                    BlockType::Picture => picture_found = true,
-                    BlockType::Unknown => (),
-                }
             } else {
                 reader.seek_relative(block_header.length as i64)?;
             }
 
-            if is_last
-                || (stream_info_found && vorbis_comment_found && (picture_found || skip_picture))
-            {
+            // Blocks are walked until the last one, which leaves the reader at the audio data
+            if is_last {
                 break;
             }
         }
@@ -0,0 +1,461 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek},
+    path::Path,
+};
+
+use crate::{Error, Result, replace_head, write_in_place};
+
+/// Padding left behind after a full rewrite, so the next edit can happen in place
+const DEFAULT_PADDING: u32 = 8 * 1024;
+
+/// Block lengths are stored in 24 bits
+const MAX_BLOCK_LEN: usize = (1 << 24) - 1;
+
+const STREAMINFO: u8 = 0;
+const PADDING: u8 = 1;
+const VORBIS_COMMENT: u8 = 4;
+const PICTURE: u8 = 6;
+
+/// An owned Vorbis comment that keeps every field, including ones VEIL doesn't know about
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VorbisCommentBlock {
+    pub vendor: String,
+    pub comments: Vec<(String, String)>,
+}
+
+impl VorbisCommentBlock {
+    pub fn from_bytes(bytes: &[u8]) -> Result<VorbisCommentBlock> {
+        let mut i = 0;
+
+        let vendor = read_string(bytes, &mut i)?.to_owned();
+        let count = read_u32_le(bytes, &mut i)?;
+
+        let mut comments = Vec::with_capacity(count.min(64) as usize);
+        for _ in 0..count {
+            let comment = read_string(bytes, &mut i)?;
+
+            if let Some((key, value)) = comment.split_once('=') {
+                comments.push((key.to_owned(), value.to_owned()));
+            }
+        }
+
+        Ok(VorbisCommentBlock { vendor, comments })
+    }
+
+    /// First value of `key`, keys are case-insensitive
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.comments
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Replace every value of `key` with `value`
+    pub fn set(&mut self, key: &str, value: impl Into<String>) {
+        self.remove(key);
+        self.comments.push((key.to_ascii_uppercase(), value.into()));
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.comments.retain(|(k, _)| !k.eq_ignore_ascii_case(key));
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(64 + self.comments.len() * 32);
+
+        bytes.extend_from_slice(&(self.vendor.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(self.vendor.as_bytes());
+        bytes.extend_from_slice(&(self.comments.len() as u32).to_le_bytes());
+
+        for (key, value) in &self.comments {
+            bytes.extend_from_slice(&((key.len() + 1 + value.len()) as u32).to_le_bytes());
+            bytes.extend_from_slice(key.as_bytes());
+            bytes.push(b'=');
+            bytes.extend_from_slice(value.as_bytes());
+        }
+
+        bytes
+    }
+}
+
+/// An owned FLAC picture block
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PictureBlock {
+    /// `ID3v2` `APIC` picture type, 3 is the front cover
+    pub picture_type: u32,
+    pub mime_type: String,
+    pub description: String,
+    pub width: u32,
+    pub height: u32,
+    pub color_depth: u32,
+    pub indexed_colors: u32,
+    pub data: Vec<u8>,
+}
+
+impl PictureBlock {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(32 + self.mime_type.len() + self.data.len());
+
+        bytes.extend_from_slice(&self.picture_type.to_be_bytes());
+        bytes.extend_from_slice(&(self.mime_type.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(self.mime_type.as_bytes());
+        bytes.extend_from_slice(&(self.description.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(self.description.as_bytes());
+
+        for n in [
+            self.width,
+            self.height,
+            self.color_depth,
+            self.indexed_colors,
+        ] {
+            bytes.extend_from_slice(&n.to_be_bytes());
+        }
+
+        bytes.extend_from_slice(&(self.data.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&self.data);
+
+        bytes
+    }
+}
+
+/// A metadata block as it's stored in the file
+struct RawBlock {
+    block_type: u8,
+    data: Vec<u8>,
+}
+
+pub struct FlacWriter {}
+
+impl FlacWriter {
+    /// Read the Vorbis comment of a file, to be edited and passed back to [`FlacWriter::write`]
+    pub fn read_comment(path: &Path) -> Result<VorbisCommentBlock> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let (blocks, _) = Self::read_blocks(&mut reader)?;
+
+        blocks
+            .iter()
+            .find(|block| block.block_type == VORBIS_COMMENT)
+            .map_or_else(
+                || Ok(VorbisCommentBlock::default()),
+                |block| VorbisCommentBlock::from_bytes(&block.data),
+            )
+    }
+
+    /// Replace the Vorbis comment, and the pictures if `pictures` is `Some`.
+    ///
+    /// Every other block and the audio frames are kept as they are. When the new
+    /// blocks fit in the space taken by the old ones and their padding the file is
+    /// edited in place, otherwise it's rewritten through a temporary file.
+    pub fn write(
+        path: &Path,
+        comment: &VorbisCommentBlock,
+        pictures: Option<&[PictureBlock]>,
+    ) -> Result<()> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let (blocks, audio_start) = Self::read_blocks(&mut reader)?;
+
+        let mut new_blocks: Vec<RawBlock> = blocks
+            .into_iter()
+            .filter(|block| match block.block_type {
+                PADDING | VORBIS_COMMENT => false,
+                PICTURE => pictures.is_none(),
+                _ => true,
+            })
+            .collect();
+
+        // STREAMINFO has to stay first, the comment goes right after it
+        let comment_position = new_blocks
+            .iter()
+            .position(|block| block.block_type == STREAMINFO)
+            .map_or(0, |i| i + 1);
+
+        new_blocks.insert(
+            comment_position,
+            RawBlock {
+                block_type: VORBIS_COMMENT,
+                data: comment.to_bytes(),
+            },
+        );
+
+        for picture in pictures.unwrap_or_default() {
+            new_blocks.push(RawBlock {
+                block_type: PICTURE,
+                data: picture.to_bytes(),
+            });
+        }
+
+        if new_blocks
+            .iter()
+            .any(|block| block.data.len() > MAX_BLOCK_LEN)
+        {
+            return Err(Error::BlockTooLarge);
+        }
+
+        let available = audio_start - 4;
+        let needed: u64 = new_blocks.iter().map(|b| 4 + b.data.len() as u64).sum();
+
+        // The leftover space either fits exactly or has room for a padding block header
+        let in_place_padding = match available.checked_sub(needed) {
+            Some(0) => Some(None),
+            Some(left) if left >= 4 && left - 4 <= MAX_BLOCK_LEN as u64 => Some(Some(left - 4)),
+            _ => None,
+        };
+
+        drop(reader);
+
+        if let Some(padding) = in_place_padding {
+            return write_in_place(path, 4, &Self::encode_blocks(&new_blocks, padding));
+        }
+
+        let mut head = b"fLaC".to_vec();
+        head.extend(Self::encode_blocks(
+            &new_blocks,
+            Some(u64::from(DEFAULT_PADDING)),
+        ));
+
+        replace_head(path, &head, audio_start)
+    }
+
+    fn encode_blocks(blocks: &[RawBlock], padding: Option<u64>) -> Vec<u8> {
+        let total = blocks.iter().map(|b| 4 + b.data.len()).sum::<usize>()
+            + padding.map_or(0, |p| 4 + p as usize);
+        let mut bytes = Vec::with_capacity(total);
+
+        for (i, block) in blocks.iter().enumerate() {
+            let is_last = padding.is_none() && i == blocks.len() - 1;
+            write_block_header(&mut bytes, block.block_type, is_last, block.data.len());
+            bytes.extend_from_slice(&block.data);
+        }
+
+        if let Some(padding) = padding {
+            write_block_header(&mut bytes, PADDING, true, padding as usize);
+            bytes.resize(bytes.len() + padding as usize, 0);
+        }
+
+        bytes
+    }
+
+    /// Read every metadata block, returning them along with where the audio frames start
+    fn read_blocks<R: Read + Seek>(reader: &mut R) -> Result<(Vec<RawBlock>, u64)> {
+        let mut signature = [0u8; 4];
+        reader.read_exact(&mut signature)?;
+        if &signature != b"fLaC" {
+            return Err(Error::InvalidFlacSignature);
+        }
+
+        let mut blocks = Vec::with_capacity(4);
+        loop {
+            let mut header = [0u8; 4];
+            reader.read_exact(&mut header)?;
+
+            let is_last = header[0] & 0x80 != 0;
+            let length = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+
+            let mut data = vec![0u8; length];
+            reader.read_exact(&mut data)?;
+
+            blocks.push(RawBlock {
+                block_type: header[0] & 0x7F,
+                data,
+            });
+
+            if is_last {
+                break;
+            }
+        }
+
+        Ok((blocks, reader.stream_position()?))
+    }
+}
+
+fn write_block_header(bytes: &mut Vec<u8>, block_type: u8, is_last: bool, length: usize) {
+    let length = (length as u32).to_be_bytes();
+    bytes.push(block_type | if is_last { 0x80 } else { 0 });
+    bytes.extend_from_slice(&length[1..]);
+}
+
+fn read_u32_le(bytes: &[u8], i: &mut usize) -> Result<u32> {
+    let Some(&[a, b, c, d]) = bytes.get(*i..*i + 4) else {
+        return Err(Error::InvalidVorbisComment);
+    };
+
+    *i += 4;
+    Ok(u32::from_le_bytes([a, b, c, d]))
+}
+
+fn read_string<'a>(bytes: &'a [u8], i: &mut usize) -> Result<&'a str> {
+    let length = read_u32_le(bytes, i)? as usize;
+    let string = bytes
+        .get(*i..*i + length)
+        .ok_or(Error::InvalidVorbisComment)?;
+
+    *i += length;
+    Ok(std::str::from_utf8(string)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io, path::PathBuf};
+
+    use super::*;
+    use crate::{Metadata, temp_path};
+
+    const AUDIO: &[u8] = b"\xFF\xF8audio frames that must survive";
+
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, data: &[u8]) -> TempFile {
+            let path = std::env::temp_dir()
+                .join(format!("metadata_audio_{}_{name}.flac", std::process::id()));
+            fs::write(&path, data).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn stream_info() -> Vec<u8> {
+        // 44.1 kHz, 2 channels, 16 bits, 441000 samples
+        let mut data = vec![0u8; 34];
+        data[10..18].copy_from_slice(&[0x0A, 0xC4, 0x42, 0xF0, 0x00, 0x06, 0xBA, 0xA8]);
+        data
+    }
+
+    fn flac(blocks: &[(u8, Vec<u8>)]) -> Vec<u8> {
+        let raw: Vec<RawBlock> = blocks
+            .iter()
+            .map(|(block_type, data)| RawBlock {
+                block_type: *block_type,
+                data: data.clone(),
+            })
+            .collect();
+
+        let mut file = b"fLaC".to_vec();
+        file.extend(FlacWriter::encode_blocks(&raw, None));
+        file.extend_from_slice(AUDIO);
+        file
+    }
+
+    fn comment(title: &str) -> VorbisCommentBlock {
+        let mut comment = VorbisCommentBlock {
+            vendor: "reference libFLAC 1.4.3".into(),
+            comments: vec![("CUSTOM".into(), "kept".into())],
+        };
+        comment.set("TITLE", title);
+        comment
+    }
+
+    #[test]
+    fn comment_round_trips() {
+        let comment = comment("Title");
+        let parsed = VorbisCommentBlock::from_bytes(&comment.to_bytes()).unwrap();
+
+        assert_eq!(parsed, comment);
+        assert_eq!(parsed.get("title"), Some("Title"));
+    }
+
+    #[test]
+    fn padding_is_reused_in_place() {
+        let file = TempFile::new(
+            "in_place",
+            &flac(&[
+                (STREAMINFO, stream_info()),
+                (VORBIS_COMMENT, comment("Old").to_bytes()),
+                (2, b"application block".to_vec()),
+                (PADDING, vec![0; 1024]),
+            ]),
+        );
+        let original_len = fs::metadata(&file.0).unwrap().len();
+
+        FlacWriter::write(&file.0, &comment("A much longer title than before"), None).unwrap();
+
+        let data = fs::read(&file.0).unwrap();
+        assert_eq!(data.len() as u64, original_len);
+        assert!(data.ends_with(AUDIO));
+
+        let comment = FlacWriter::read_comment(&file.0).unwrap();
+        assert_eq!(
+            comment.get("TITLE"),
+            Some("A much longer title than before")
+        );
+        assert_eq!(comment.get("CUSTOM"), Some("kept"));
+
+        let (blocks, _) = FlacWriter::read_blocks(&mut io::Cursor::new(&data)).unwrap();
+        let types: Vec<u8> = blocks.iter().map(|b| b.block_type).collect();
+        assert_eq!(types, [STREAMINFO, VORBIS_COMMENT, 2, PADDING]);
+        assert_eq!(blocks[2].data, b"application block");
+    }
+
+    #[test]
+    fn file_is_rewritten_when_padding_is_too_small() {
+        let file = TempFile::new(
+            "rewrite",
+            &flac(&[
+                (STREAMINFO, stream_info()),
+                (VORBIS_COMMENT, comment("Old").to_bytes()),
+            ]),
+        );
+
+        let picture = PictureBlock {
+            picture_type: 3,
+            mime_type: "image/png".into(),
+            data: vec![0x89, b'P', b'N', b'G'],
+            ..Default::default()
+        };
+
+        FlacWriter::write(&file.0, &comment("New"), Some(&[picture])).unwrap();
+
+        let data = fs::read(&file.0).unwrap();
+        assert!(data.ends_with(AUDIO));
+        assert!(!temp_path(&file.0).exists());
+
+        let mut buffer = Vec::new();
+        let meta = Metadata::from_file(&mut buffer, &file.0, false).unwrap();
+
+        assert_eq!(meta.name.as_deref(), Some("New"));
+        assert_eq!(meta.pictures[0].data, &[0x89, b'P', b'N', b'G']);
+        assert!((meta.duration - 10.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn pictures_are_kept_unless_replaced() {
+        let picture = PictureBlock {
+            picture_type: 3,
+            mime_type: "image/jpeg".into(),
+            data: vec![0xFF, 0xD8],
+            ..Default::default()
+        };
+
+        let file = TempFile::new(
+            "keep_picture",
+            &flac(&[
+                (STREAMINFO, stream_info()),
+                (PICTURE, picture.to_bytes()),
+                (PADDING, vec![0; 512]),
+            ]),
+        );
+
+        FlacWriter::write(&file.0, &comment("Title"), None).unwrap();
+
+        let data = fs::read(&file.0).unwrap();
+        let (blocks, _) = FlacWriter::read_blocks(&mut io::Cursor::new(&data)).unwrap();
+
+        assert_eq!(blocks[1].block_type, VORBIS_COMMENT);
+        assert_eq!(blocks[2].data, picture.to_bytes());
+    }
+
+    #[test]
+    fn truncated_comment_returns_error() {
+        let mut bytes = comment("Title").to_bytes();
+        bytes.truncate(bytes.len() - 2);
+
+        let err = VorbisCommentBlock::from_bytes(&bytes).unwrap_err();
+        assert!(matches!(err, Error::InvalidVorbisComment));
+    }
+}
@@ -1,118 +1,468 @@
-use std::io::{Read, Seek};
+use std::{
+    borrow::Cow,
+    io::{Cursor, Read, Seek, SeekFrom},
+};
 
-use crate::{Error, Result, read_into_buffer_unchecked};
+use crate::{
+    Error, Result,
+    id3v1::GENRES,
+    picture::{Picture, PictureType},
+    read_into_buffer_unchecked, split_values,
+    tag_map::{TagMap, TagValue},
+};
+
+/// How many tags linked through SEEK frames are followed before giving up.
+const MAX_LINKED_TAGS: usize = 4;
 
 pub struct Id3 {}
 
+/// Which frames of a tag are read
+#[derive(Clone, Copy, PartialEq)]
+enum Frames {
+    /// The frames `Metadata` uses, until the ones a track needs have been found
+    Required { skip_picture: bool },
+    /// Every frame, including the ones `Metadata` doesn't know about
+    Every,
+}
+
+#[derive(Debug)]
+pub struct Id3Tag {
+    pub frames: Vec<FrameHeader>,
+
+    /// Where the audio data starts, which is right after a prepended tag
+    pub audio_start: u64,
+}
+
 impl Id3 {
+    /// Reads the frames `Metadata` uses, stopping once the title, artist, album, track
+    /// number and picture have been found. Pictures aren't read if `skip_picture` is set.
     pub fn read_all_frames<R: Read + Seek>(
         block_buffer: &mut Vec<u8>,
         reader: &mut R,
-    ) -> Result<Vec<FrameHeader>> {
-        let _header = Id3Header::parse(reader)?;
+        skip_picture: bool,
+    ) -> Result<Id3Tag> {
+        Self::read(block_buffer, reader, Frames::Required { skip_picture })
+    }
+
+    /// Reads every frame of the tag, including the ones `Metadata` doesn't know about
+    pub fn read_every_frame<R: Read + Seek>(
+        block_buffer: &mut Vec<u8>,
+        reader: &mut R,
+    ) -> Result<Id3Tag> {
+        Self::read(block_buffer, reader, Frames::Every)
+    }
+
+    fn read<R: Read + Seek>(
+        block_buffer: &mut Vec<u8>,
+        reader: &mut R,
+        frames: Frames,
+    ) -> Result<Id3Tag> {
+        let mut tag_start = reader.stream_position()?;
+        let stream_start = tag_start;
 
-        // TODO: Depending on major & minor versions, handle different methods of parsing IF there are
-        // changes to the metadata parsing, still have to research that.
+        let mut header = match Id3Header::parse(reader) {
+            Ok(header) => header,
+            Err(Error::InvalidId3Signature) => {
+                // ID3v2.4 allows the tag to be appended to the end of the file instead
+                tag_start = Self::locate_appended_tag(reader)?;
+                Id3Header::parse(reader)?
+            }
+            Err(e) => return Err(e),
+        };
+
+        let audio_start = if tag_start == stream_start {
+            stream_start + header.total_size()
+        } else {
+            stream_start
+        };
 
         let mut frame_headers = Vec::with_capacity(6);
         let mut found = FoundFrames::default();
-        while let Some(mut header) = FrameHeader::parse(reader)? {
+
+        for _ in 0..MAX_LINKED_TAGS {
+            let seek_offset = Self::read_tag(
+                &header,
+                block_buffer,
+                reader,
+                &mut frame_headers,
+                &mut found,
+                frames,
+            )?;
+
+            let Some(offset) = seek_offset else {
+                break;
+            };
+
+            // The SEEK frame offset is counted from the end of the current tag
+            let next_tag = tag_start + header.total_size() + u64::from(offset);
+            reader.seek(SeekFrom::Start(next_tag))?;
+
+            tag_start = next_tag;
+            header = Id3Header::parse(reader)?;
+        }
+
+        Ok(Id3Tag {
+            frames: frame_headers,
+            audio_start,
+        })
+    }
+
+    /// Reads the frames of a single tag, returning the SEEK frame offset if the tag has one.
+    fn read_tag<R: Read + Seek>(
+        header: &Id3Header,
+        block_buffer: &mut Vec<u8>,
+        reader: &mut R,
+        frame_headers: &mut Vec<FrameHeader>,
+        found: &mut FoundFrames,
+        frames: Frames,
+    ) -> Result<Option<u32>> {
+        let frames_len = header.frames_len()?;
+
+        // ID3v2.2 & ID3v2.3 unsynchronise the whole tag at once, so it has to be undone
+        // before any frame headers can be read.
+        if header.major_version <= 3 && header.unsynchronisation() {
+            let mut tag = Vec::with_capacity(frames_len as usize);
+            read_into_buffer_unchecked(reader, &mut tag, frames_len as usize)?;
+
+            let len = resynchronise(&mut tag);
+            tag.truncate(len);
+
+            let tag_len = tag.len() as u64;
+            let mut tag_reader = Cursor::new(tag);
+            return Self::read_frames(
+                header,
+                block_buffer,
+                &mut tag_reader,
+                tag_len,
+                frame_headers,
+                found,
+                frames,
+            );
+        }
+
+        let frames_end = reader.stream_position()? + frames_len as u64;
+        Self::read_frames(
+            header,
+            block_buffer,
+            reader,
+            frames_end,
+            frame_headers,
+            found,
+            frames,
+        )
+    }
+
+    fn read_frames<R: Read + Seek>(
+        header: &Id3Header,
+        block_buffer: &mut Vec<u8>,
+        reader: &mut R,
+        frames_end: u64,
+        frame_headers: &mut Vec<FrameHeader>,
+        found: &mut FoundFrames,
+        frames: Frames,
+    ) -> Result<Option<u32>> {
+        let mut seek_offset = None;
+
+        let frame_header_size = FrameHeader::size(header.major_version);
+
+        while reader.stream_position()? + frame_header_size <= frames_end {
+            let Some(mut frame) = FrameHeader::parse(reader, header.major_version)? else {
+                break;
+            };
+
+            if reader.stream_position()? + frame.length as u64 > frames_end {
+                return Err(Error::InvalidFrameSize);
+            }
+
+            if frame.frame_id == FrameId::Seek {
+                if frame.length < 4 {
+                    return Err(Error::InvalidFrameSize);
+                }
+
+                let mut offset = [0u8; 4];
+                reader.read_exact(&mut offset)?;
+                reader.seek_relative(frame.length as i64 - 4)?;
+
+                seek_offset = Some(u32::from_be_bytes(offset));
+                continue;
+            }
+
+            let is_picture = matches!(frame.frame_id, FrameId::Apic | FrameId::Pic);
+            let is_skipped = match frames {
+                Frames::Required { skip_picture } => {
+                    frame.frame_id == FrameId::Unknown || (skip_picture && is_picture)
+                }
+                Frames::Every => false,
+            };
+
+            if is_skipped {
+                reader.seek_relative(frame.length as i64)?;
+                continue;
+            }
+
+            if let Err(e) = frame.flags.check_supported() {
+                logging::warn!("Skipping {:?} frame: {e}", frame.frame_id);
+                reader.seek_relative(frame.length as i64)?;
+                continue;
+            }
+
             let start = block_buffer.len();
-            read_into_buffer_unchecked(reader, block_buffer, header.length as usize)?;
+            read_into_buffer_unchecked(reader, block_buffer, frame.length as usize)?;
 
-            header.data_start = start as u32;
+            let data_start = start + frame.flags.prefix_len();
+            if data_start > block_buffer.len() {
+                return Err(Error::InvalidFrameSize);
+            }
+
+            if frame.flags.unsynchronisation || header.frames_unsynchronised() {
+                let len = resynchronise(&mut block_buffer[data_start..]);
+                block_buffer.truncate(data_start + len);
+            }
 
-            match header.frame_id {
+            frame.data_start = data_start as u32;
+            frame.length = (block_buffer.len() - data_start) as u32;
+
+            match frame.frame_id {
                 FrameId::Tit2 => found.title = true,
                 FrameId::Tpe1 => found.artist = true,
                 FrameId::Talb => found.album = true,
-                FrameId::Tyer => found.year = true,
-                FrameId::Time => found.duration = true,
-                FrameId::Apic => found.picture = true,
-                FrameId::Unknown => {}
-            }
-            if header.frame_id != FrameId::Unknown {
-                frame_headers.push(header);
+                FrameId::Trck => found.track = true,
+                FrameId::Apic | FrameId::Pic => found.picture = true,
+                _ => {}
             }
 
-            if found.all() {
+            frame_headers.push(frame);
+
+            // Frames after these, most of all large pictures, aren't worth buffering
+            if let Frames::Required { skip_picture } = frames
+                && found.required(skip_picture)
+            {
                 break;
             }
         }
 
-        Ok(frame_headers)
+        Ok(seek_offset)
+    }
+
+    /// Looks for an ID3v2.4 footer at the end of the stream, optionally in front of an
+    /// `ID3v1` trailer, and positions the reader at the start of the tag it belongs to.
+    fn locate_appended_tag<R: Read + Seek>(reader: &mut R) -> Result<u64> {
+        let stream_len = reader.seek(SeekFrom::End(0))?;
+
+        for trailer_len in [0u64, 128] {
+            let Some(footer_start) = stream_len.checked_sub(trailer_len + 10) else {
+                continue;
+            };
+
+            reader.seek(SeekFrom::Start(footer_start))?;
+
+            let mut footer = [0u8; 10];
+            reader.read_exact(&mut footer)?;
+
+            if &footer[0..3] != b"3DI" {
+                continue;
+            }
+
+            let tag_size = synchsafe_u32(&footer[6..10]) as u64;
+            let tag_start = footer_start
+                .checked_sub(tag_size + 10)
+                .ok_or(Error::InvalidId3Signature)?;
+
+            reader.seek(SeekFrom::Start(tag_start))?;
+            return Ok(tag_start);
+        }
+
+        Err(Error::InvalidId3Signature)
     }
 }
 
+/// The frames a track needs to be imported, once they've all been found the rest of the
+/// tag isn't read
 #[derive(Default)]
 struct FoundFrames {
     title: bool,
     artist: bool,
     album: bool,
-    year: bool,
-    duration: bool,
+    track: bool,
     picture: bool,
 }
 
 impl FoundFrames {
-    fn all(&self) -> bool {
-        self.title && self.artist && self.album && self.year && self.duration && self.picture
+    fn required(&self, skip_picture: bool) -> bool {
+        self.title && self.artist && self.album && self.track && (self.picture || skip_picture)
     }
 }
 
 #[derive(Debug)]
-struct Id3Header {
-    major_version: u8,
-    minor_version: u8,
+pub(crate) struct Id3Header {
+    pub(crate) major_version: u8,
+    flags: u8,
     tag_size: u32,
+
+    /// Bytes taken up by the extended header, which are counted in `tag_size`
+    extended_size: u32,
 }
 
 impl Id3Header {
+    const UNSYNCHRONISATION: u8 = 0x80;
+    const EXTENDED_HEADER: u8 = 0x40;
+    const FOOTER: u8 = 0x10;
+
+    /// ID3v2.2 uses the bit of the extended header for compression instead
+    const V22_COMPRESSION: u8 = 0x40;
+
     pub fn parse<R: Read + Seek>(reader: &mut R) -> Result<Self> {
         let mut header_bytes = [0u8; 10];
         reader.read_exact(&mut header_bytes)?;
 
-        let mut header = Self {
-            major_version: 0,
-            minor_version: 0,
-            tag_size: 0,
-        };
-
         if &header_bytes[0..3] != b"ID3" {
             return Err(Error::InvalidId3Signature);
         };
 
-        header.major_version = header_bytes[3];
-        header.minor_version = header_bytes[4];
+        let mut header = Self {
+            major_version: header_bytes[3],
+            flags: header_bytes[5],
+            tag_size: synchsafe_u32(&header_bytes[6..10]),
+            extended_size: 0,
+        };
 
-        let size_bytes: &[u8] = &header_bytes[6..10];
+        if !matches!(header.major_version, 2..=4) {
+            return Err(Error::UnsupportedId3Version);
+        }
 
-        header.tag_size = size_bytes
-            .iter()
-            .fold(0u32, |acc, &b| (acc << 7) | u32::from(b & 0x7F));
+        // No compression scheme was ever defined for ID3v2.2, so these tags can't be read
+        if header.major_version == 2 && header.flags & Self::V22_COMPRESSION != 0 {
+            return Err(Error::CompressedId3Frame);
+        }
 
-        // Skip past the extended header for simplicity.
-        let has_extended_header = &header_bytes[5] & 0x40 != 0;
-        if has_extended_header {
+        // Skip past the extended header as nothing in it is needed for reading frames.
+        if header.major_version >= 3 && header.flags & Self::EXTENDED_HEADER != 0 {
             let mut size_bytes = [0u8; 4];
             reader.read_exact(&mut size_bytes)?;
-            let ext_size = u32::from_be_bytes(size_bytes);
 
-            reader.seek_relative((ext_size - 4) as i64)?;
+            // ID3v2.3 excludes the size field itself, ID3v2.4 includes it and is synchsafe
+            let remaining = if header.major_version == 3 {
+                u32::from_be_bytes(size_bytes)
+            } else {
+                synchsafe_u32(&size_bytes)
+                    .checked_sub(4)
+                    .ok_or(Error::InvalidId3TagSize)?
+            };
+
+            reader.seek_relative(remaining as i64)?;
+            header.extended_size = remaining.checked_add(4).ok_or(Error::InvalidId3TagSize)?;
         }
 
         Ok(header)
     }
+
+    /// Length of the frames & padding following the headers
+    pub(crate) fn frames_len(&self) -> Result<u32> {
+        self.tag_size
+            .checked_sub(self.extended_size)
+            .ok_or(Error::InvalidId3TagSize)
+    }
+
+    /// Size of the whole tag, including the header and an optional footer
+    pub(crate) fn total_size(&self) -> u64 {
+        let footer = if self.flags & Self::FOOTER != 0 {
+            10
+        } else {
+            0
+        };
+        10 + u64::from(self.tag_size) + footer
+    }
+
+    pub(crate) fn unsynchronisation(&self) -> bool {
+        self.flags & Self::UNSYNCHRONISATION != 0
+    }
+
+    /// In ID3v2.4 the tag flag states that every frame has been unsynchronised
+    fn frames_unsynchronised(&self) -> bool {
+        self.major_version == 4 && self.unsynchronisation()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FrameFlags {
+    pub grouping: bool,
+    pub compression: bool,
+    pub encryption: bool,
+    pub unsynchronisation: bool,
+    pub data_length_indicator: bool,
+}
+
+impl FrameFlags {
+    fn from_bytes(major_version: u8, bytes: [u8; 2]) -> Self {
+        let format = bytes[1];
+
+        if major_version == 3 {
+            Self {
+                compression: format & 0x80 != 0,
+                encryption: format & 0x40 != 0,
+                grouping: format & 0x20 != 0,
+                // ID3v2.3 compressed frames carry the decompressed size, like v2.4's indicator
+                data_length_indicator: format & 0x80 != 0,
+                unsynchronisation: false,
+            }
+        } else {
+            Self {
+                grouping: format & 0x40 != 0,
+                compression: format & 0x08 != 0,
+                encryption: format & 0x04 != 0,
+                unsynchronisation: format & 0x02 != 0,
+                data_length_indicator: format & 0x01 != 0,
+            }
+        }
+    }
+
+    fn check_supported(&self) -> Result<()> {
+        if self.compression {
+            Err(Error::CompressedId3Frame)
+        } else if self.encryption {
+            Err(Error::EncryptedId3Frame)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Amount of bytes in front of the frame data added by the flags
+    fn prefix_len(&self) -> usize {
+        let mut len = 0;
+
+        if self.grouping {
+            len += 1;
+        }
+
+        if self.data_length_indicator {
+            len += 4;
+        }
+
+        len
+    }
 }
 
+#[derive(Debug)]
 pub struct FrameHeader {
     pub frame_id: FrameId,
+    /// The frame ID as stored, ID3v2.2 IDs are padded with a null byte
+    pub id: [u8; 4],
     pub data_start: u32,
     pub length: u32,
+    pub flags: FrameFlags,
 }
 
 impl FrameHeader {
-    pub fn parse<R: Read + Seek>(reader: &mut R) -> Result<Option<Self>> {
+    /// ID3v2.2 frame headers have 3 character IDs, 3 byte sizes and no flags
+    fn size(major_version: u8) -> u64 {
+        if major_version == 2 { 6 } else { 10 }
+    }
+
+    pub fn parse<R: Read + Seek>(reader: &mut R, major_version: u8) -> Result<Option<Self>> {
+        if major_version == 2 {
+            return Self::parse_v22(reader);
+        }
+
         let mut header_bytes = [0u8; 10];
         reader.read_exact(&mut header_bytes)?;
         let start = reader.stream_position()?;
@@ -122,26 +472,71 @@ impl FrameHeader {
             return Ok(None);
         }
 
+        // Anything that isn't a valid frame ID means we've run past the frames
+        if !header_bytes[0..4]
+            .iter()
+            .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+        {
+            return Ok(None);
+        }
+
         let frame_id = FrameId::from_bytes(&header_bytes[0..4]);
 
-        // seems to be only for ID3v2.3
-        let frame_size = u32::from_be_bytes(
-            header_bytes[4..8]
-                .try_into()
-                .expect("slice should have length 4"),
-        );
+        let frame_size = if major_version == 4 {
+            synchsafe_u32(&header_bytes[4..8])
+        } else {
+            u32::from_be_bytes([
+                header_bytes[4],
+                header_bytes[5],
+                header_bytes[6],
+                header_bytes[7],
+            ])
+        };
+
+        let flags = FrameFlags::from_bytes(major_version, [header_bytes[8], header_bytes[9]]);
+
+        Ok(Some(Self {
+            data_start: start as u32,
+            frame_id,
+            id: [
+                header_bytes[0],
+                header_bytes[1],
+                header_bytes[2],
+                header_bytes[3],
+            ],
+            length: frame_size,
+            flags,
+        }))
+    }
+
+    fn parse_v22<R: Read + Seek>(reader: &mut R) -> Result<Option<Self>> {
+        let mut header_bytes = [0u8; 6];
+        reader.read_exact(&mut header_bytes)?;
+        let start = reader.stream_position()?;
+
+        if !header_bytes[0..3]
+            .iter()
+            .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+        {
+            return Ok(None);
+        }
+
+        let frame_id = FrameId::from_v22_bytes(&header_bytes[0..3]);
+        let frame_size = u32::from_be_bytes([0, header_bytes[3], header_bytes[4], header_bytes[5]]);
 
         Ok(Some(Self {
             data_start: start as u32,
             frame_id,
+            id: [header_bytes[0], header_bytes[1], header_bytes[2], 0],
             length: frame_size,
+            flags: FrameFlags::default(),
         }))
     }
 }
 
 pub enum Frame<'a> {
-    Picture(&'a [u8]),
-    Text((FrameId, &'a str)),
+    Picture(Picture<'a>),
+    Text((FrameId, Cow<'a, str>)),
     Duration(f32),
     Year(u16),
     Unknown,
@@ -150,41 +545,361 @@ pub enum Frame<'a> {
 impl<'a> Frame<'a> {
     pub fn parse_by_id(frame_id: FrameId, data: &'a [u8]) -> Result<Self> {
         let parsed_frame = match frame_id {
-            FrameId::Tit2 | FrameId::Tpe1 | FrameId::Talb => {
-                let text_data = str::from_utf8(&data[1..])?.trim();
-                Self::Text((frame_id, text_data))
-            }
-            FrameId::Time => {
-                let bytes = &data[1..5]; // HHMM
-                let hours = (bytes[0] - b'0') as u32 * 10 + (bytes[1] - b'0') as u32;
-                let minutes = (bytes[2] - b'0') as u32 * 10 + (bytes[3] - b'0') as u32;
-                Self::Duration((hours * 3600 + minutes * 60) as f32)
+            FrameId::Tit2
+            | FrameId::Tpe1
+            | FrameId::Tpe2
+            | FrameId::Talb
+            | FrameId::Trck
+            | FrameId::Tpos
+            | FrameId::Tcom
+            | FrameId::Tcmp
+            | FrameId::Tpe4
+            | FrameId::Tdrc => Self::Text((frame_id, decode_text(data)?)),
+            FrameId::Tcon => Self::Text((frame_id, resolve_genre(decode_text(data)?))),
+            // TLEN is the length of the audio in milliseconds
+            FrameId::Tlen => {
+                let text = decode_text(data)?;
+                match text.parse::<u32>() {
+                    Ok(ms) if ms > 0 => Self::Duration(ms as f32 / 1000.0),
+                    _ => Self::Unknown,
+                }
             }
             FrameId::Tyer => {
-                let bytes = &data[1..5]; // YYYY
-                let year = (bytes[0] - b'0') as u16 * 1000
-                    + (bytes[1] - b'0') as u16 * 100
-                    + (bytes[2] - b'0') as u16 * 10
-                    + (bytes[3] - b'0') as u16;
-                Self::Year(year)
-            }
-            FrameId::Apic => Self::Picture(&data[1..]),
-            FrameId::Unknown => Self::Unknown,
+                let text = decode_text(data)?;
+                match text.get(0..4).and_then(|year| year.parse().ok()) {
+                    Some(year) => Self::Year(year),
+                    None => Self::Unknown,
+                }
+            }
+            FrameId::Apic | FrameId::Pic => Self::Picture(parse_picture(frame_id, data)?),
+            FrameId::Seek | FrameId::Unknown => Self::Unknown,
         };
 
         Ok(parsed_frame)
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum TextEncoding {
+    Latin1 = 0,
+    Utf16 = 1,
+    Utf16Be = 2,
+    Utf8 = 3,
+}
+
+impl TextEncoding {
+    fn from_u8(byte: u8) -> Result<TextEncoding> {
+        match byte {
+            0 => Ok(TextEncoding::Latin1),
+            1 => Ok(TextEncoding::Utf16),
+            2 => Ok(TextEncoding::Utf16Be),
+            3 => Ok(TextEncoding::Utf8),
+            _ => Err(Error::InvalidTextEncoding),
+        }
+    }
+
+    /// Decode the bytes of a text field, only allocating when the text isn't valid UTF-8 as is.
+    pub fn decode<'a>(self, bytes: &'a [u8]) -> Result<Cow<'a, str>> {
+        let text = match self {
+            TextEncoding::Latin1 => {
+                if bytes.is_ascii() {
+                    Cow::Borrowed(str::from_utf8(bytes)?)
+                } else {
+                    // Every ISO-8859-1 byte maps directly to the same Unicode code point
+                    Cow::Owned(bytes.iter().map(|&b| b as char).collect())
+                }
+            }
+            TextEncoding::Utf16 => Cow::Owned(decode_utf16_values(bytes, true)?),
+            TextEncoding::Utf16Be => Cow::Owned(decode_utf16_values(bytes, false)?),
+            TextEncoding::Utf8 => Cow::Borrowed(str::from_utf8(bytes)?),
+        };
+
+        Ok(trim_text(text))
+    }
+}
+
+/// Decode a text frame, where the first byte states the encoding of the rest.
+pub fn decode_text(data: &[u8]) -> Result<Cow<'_, str>> {
+    match data.split_first() {
+        Some((&encoding, text)) => TextEncoding::from_u8(encoding)?.decode(text),
+        None => Ok(Cow::Borrowed("")),
+    }
+}
+
+/// `APIC` holds the encoding, a null terminated MIME type, the picture type, a description
+/// in the given encoding and the image. `PIC` has a 3 character image format in place of
+/// the MIME type.
+fn parse_picture(frame_id: FrameId, data: &[u8]) -> Result<Picture<'_>> {
+    let (&encoding, rest) = data.split_first().ok_or(Error::InvalidFrameSize)?;
+    let encoding = TextEncoding::from_u8(encoding)?;
+
+    let (mime_type, rest) = if frame_id == FrameId::Pic {
+        let (format, rest) = rest.split_at_checked(3).ok_or(Error::InvalidFrameSize)?;
+        let mime_type = match format.to_ascii_uppercase().as_slice() {
+            b"JPG" => "image/jpeg",
+            b"PNG" => "image/png",
+            _ => "",
+        };
+
+        (Cow::Borrowed(mime_type), rest)
+    } else {
+        let end = rest
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or(Error::InvalidFrameSize)?;
+        (TextEncoding::Latin1.decode(&rest[..end])?, &rest[end + 1..])
+    };
+
+    let (&picture_type, rest) = rest.split_first().ok_or(Error::InvalidFrameSize)?;
+
+    let (description, image) = split_terminated(encoding, rest).ok_or(Error::InvalidFrameSize)?;
+
+    let mut picture = Picture {
+        picture_type: PictureType::from_u32(u32::from(picture_type)),
+        mime_type,
+        description: encoding.decode(description)?,
+        data: image,
+        ..Default::default()
+    };
+    picture.fill_from_image();
+
+    Ok(picture)
+}
+
+/// Split off a null terminated string, which in UTF-16 ends with two null bytes.
+fn split_terminated(encoding: TextEncoding, bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    match encoding {
+        TextEncoding::Utf16 | TextEncoding::Utf16Be => (0..bytes.len().saturating_sub(1))
+            .step_by(2)
+            .find(|&i| bytes[i] == 0 && bytes[i + 1] == 0)
+            .map(|i| (&bytes[..i], &bytes[i + 2..])),
+        TextEncoding::Latin1 | TextEncoding::Utf8 => bytes
+            .iter()
+            .position(|&b| b == 0)
+            .map(|i| (&bytes[..i], &bytes[i + 1..])),
+    }
+}
+
+/// Add a frame to a `TagMap`, under the Vorbis comment name of its field when it has one.
+///
+/// `TXXX` frames are keyed by their description, and frames that aren't text or can't be
+/// decoded are kept as binary.
+pub(crate) fn push_frame<'a>(tags: &mut TagMap<'a>, header: &FrameHeader, data: &'a [u8]) {
+    let id = header.id.strip_suffix(&[0]).unwrap_or(&header.id);
+    let key: Cow<'a, str> = match tag_key(id) {
+        Some(key) => Cow::Borrowed(key),
+        None => Cow::Owned(String::from_utf8_lossy(id).into_owned()),
+    };
+
+    let entries = match id {
+        b"TXXX" | b"TXX" => parse_user_text(data),
+        b"COMM" | b"COM" | b"USLT" | b"ULT" => parse_comment(data).map(|text| vec![(None, text)]),
+        b"WXXX" | b"WXX" => parse_user_url(data).map(|url| vec![(None, url)]),
+        [b'W', ..] => TextEncoding::Latin1
+            .decode(data)
+            .map(|url| vec![(None, url)]),
+        [b'T', ..] => decode_text(data).map(|text| {
+            let text = if matches!(id, b"TCON" | b"TCO") {
+                resolve_genre(text)
+            } else {
+                text
+            };
+
+            split_values(text)
+                .into_iter()
+                .map(|value| (None, value))
+                .collect()
+        }),
+        b"APIC" | b"PIC" => {
+            let value = parse_picture(header.frame_id, data)
+                .map_or(TagValue::Binary(data), TagValue::Picture);
+            tags.push(key, value);
+            return;
+        }
+        _ => {
+            tags.push(key, TagValue::Binary(data));
+            return;
+        }
+    };
+
+    let Ok(entries) = entries else {
+        tags.push(key, TagValue::Binary(data));
+        return;
+    };
+
+    for (description, value) in entries {
+        tags.push(
+            description.unwrap_or_else(|| key.clone()),
+            TagValue::Text(value),
+        );
+    }
+}
+
+type FrameEntries<'a> = Vec<(Option<Cow<'a, str>>, Cow<'a, str>)>;
+
+/// `TXXX` holds the encoding, a description and then its values.
+fn parse_user_text(data: &[u8]) -> Result<FrameEntries<'_>> {
+    let (&encoding, rest) = data.split_first().ok_or(Error::InvalidFrameSize)?;
+    let encoding = TextEncoding::from_u8(encoding)?;
+
+    let (description, text) = split_terminated(encoding, rest).unwrap_or((rest, &[]));
+    let description = encoding.decode(description)?;
+
+    Ok(split_values(encoding.decode(text)?)
+        .into_iter()
+        .map(|value| (Some(description.clone()), value))
+        .collect())
+}
+
+/// `COMM` and `USLT` hold the encoding, a 3 character language, a description and the text.
+fn parse_comment(data: &[u8]) -> Result<Cow<'_, str>> {
+    let (&encoding, rest) = data.split_first().ok_or(Error::InvalidFrameSize)?;
+    let encoding = TextEncoding::from_u8(encoding)?;
+
+    let (_language, rest) = rest.split_at_checked(3).ok_or(Error::InvalidFrameSize)?;
+    let (_description, text) = split_terminated(encoding, rest).unwrap_or((&[], rest));
+
+    encoding.decode(text)
+}
+
+/// `WXXX` holds the encoding and a description, followed by a Latin-1 URL.
+fn parse_user_url(data: &[u8]) -> Result<Cow<'_, str>> {
+    let (&encoding, rest) = data.split_first().ok_or(Error::InvalidFrameSize)?;
+    let encoding = TextEncoding::from_u8(encoding)?;
+
+    let (_description, url) = split_terminated(encoding, rest).unwrap_or((&[], rest));
+    TextEncoding::Latin1.decode(url)
+}
+
+/// The Vorbis comment name of a frame, for both ID3v2.2 and later frame IDs.
+fn tag_key(id: &[u8]) -> Option<&'static str> {
+    let key = match id {
+        b"TIT2" | b"TT2" => "TITLE",
+        b"TIT1" | b"TT1" => "GROUPING",
+        b"TIT3" | b"TT3" => "SUBTITLE",
+        b"TPE1" | b"TP1" => "ARTIST",
+        b"TPE2" | b"TP2" => "ALBUMARTIST",
+        b"TPE3" | b"TP3" => "CONDUCTOR",
+        b"TPE4" | b"TP4" => "REMIXER",
+        b"TALB" | b"TAL" => "ALBUM",
+        b"TRCK" | b"TRK" => "TRACKNUMBER",
+        b"TPOS" | b"TPA" => "DISCNUMBER",
+        b"TCON" | b"TCO" => "GENRE",
+        b"TCOM" | b"TCM" => "COMPOSER",
+        b"TEXT" | b"TXT" => "LYRICIST",
+        b"TCMP" | b"TCP" => "COMPILATION",
+        b"TYER" | b"TYE" => "YEAR",
+        b"TDRC" => "DATE",
+        b"TDOR" | b"TORY" | b"TOR" => "ORIGINALDATE",
+        b"TLEN" | b"TLE" => "LENGTH",
+        b"TBPM" | b"TBP" => "BPM",
+        b"TSRC" | b"TRC" => "ISRC",
+        b"TPUB" | b"TPB" => "LABEL",
+        b"TCOP" | b"TCR" => "COPYRIGHT",
+        b"TENC" | b"TEN" => "ENCODEDBY",
+        b"TSSE" | b"TSS" => "ENCODERSETTINGS",
+        b"TKEY" | b"TKE" => "KEY",
+        b"TLAN" | b"TLA" => "LANGUAGE",
+        b"TMOO" => "MOOD",
+        b"TSOA" => "ALBUMSORT",
+        b"TSOP" => "ARTISTSORT",
+        b"TSOT" => "TITLESORT",
+        b"TSO2" => "ALBUMARTISTSORT",
+        b"TSOC" => "COMPOSERSORT",
+        b"COMM" | b"COM" => "COMMENT",
+        b"USLT" | b"ULT" => "LYRICS",
+        b"APIC" | b"PIC" => "PICTURE",
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+/// ID3v2.4 separates multiple values with a null, and in UTF-16 every value has its own BOM.
+///
+/// The values are joined back together with a null, like the single byte encodings have them.
+fn decode_utf16_values(bytes: &[u8], with_bom: bool) -> Result<String> {
+    let mut values = Vec::new();
+    let mut start = 0;
+    for i in (0..bytes.len() & !1).step_by(2) {
+        if bytes[i] == 0 && bytes[i + 1] == 0 {
+            values.push(&bytes[start..i]);
+            start = i + 2;
+        }
+    }
+    values.push(&bytes[start..]);
+
+    let mut text = String::new();
+    for (i, value) in values.into_iter().enumerate() {
+        if i > 0 {
+            text.push('\0');
+        }
+
+        let decoded = match value {
+            [0xFE, 0xFF, rest @ ..] if with_bom => decode_utf16(rest, u16::from_be_bytes)?,
+            [0xFF, 0xFE, rest @ ..] if with_bom => decode_utf16(rest, u16::from_le_bytes)?,
+            // The BOM is mandatory, but a few taggers leave it out and write little-endian
+            _ if with_bom => decode_utf16(value, u16::from_le_bytes)?,
+            _ => decode_utf16(value, u16::from_be_bytes)?,
+        };
+        text.push_str(&decoded);
+    }
+
+    Ok(text)
+}
+
+fn decode_utf16(bytes: &[u8], to_u16: fn([u8; 2]) -> u16) -> Result<String> {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|pair| to_u16([pair[0], pair[1]]))
+        .take_while(|&unit| unit != 0);
+
+    Ok(char::decode_utf16(units).collect::<std::result::Result<String, _>>()?)
+}
+
+/// Strip whitespace & null terminators without giving up a borrow.
+fn trim_text(text: Cow<'_, str>) -> Cow<'_, str> {
+    let is_padding = |c: char| c == '\0' || c.is_whitespace();
+
+    match text {
+        Cow::Borrowed(s) => Cow::Borrowed(s.trim_matches(is_padding)),
+        Cow::Owned(s) => {
+            let trimmed = s.trim_matches(is_padding);
+            if trimmed.len() == s.len() {
+                Cow::Owned(s)
+            } else {
+                Cow::Owned(trimmed.to_owned())
+            }
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[repr(u8)]
 pub enum FrameId {
     Tit2,
     Tpe1,
+    /// Album artist, or "band/orchestra" as the specification calls it
+    Tpe2,
     Talb,
+    Trck,
+    /// Disc number, which like `TRCK` can hold the total
+    Tpos,
+    Tcon,
+    Tcom,
+    /// Remixer, or "interpreted, remixed, or otherwise modified by"
+    Tpe4,
+    /// iTunes compilation flag
+    Tcmp,
     Apic,
-    Time,
+    Tlen,
     Tyer,
+    Tdrc,
+    Seek,
+
+    /// ID3v2.2 picture, which has a 3 character image format in place of a MIME type
+    Pic,
     Unknown,
 }
 
@@ -193,11 +908,453 @@ impl FrameId {
         match bytes {
             b"TIT2" => FrameId::Tit2,
             b"TPE1" => FrameId::Tpe1,
+            b"TPE2" => FrameId::Tpe2,
             b"TALB" => FrameId::Talb,
+            b"TRCK" => FrameId::Trck,
+            b"TPOS" => FrameId::Tpos,
+            b"TCON" => FrameId::Tcon,
+            b"TCOM" => FrameId::Tcom,
+            b"TCMP" => FrameId::Tcmp,
+            b"TPE4" => FrameId::Tpe4,
             b"APIC" => FrameId::Apic,
             b"TYER" => FrameId::Tyer,
-            b"TIME" => FrameId::Time,
+            b"TLEN" => FrameId::Tlen,
+            b"TDRC" => FrameId::Tdrc,
+            b"SEEK" => FrameId::Seek,
             _ => FrameId::Unknown,
         }
     }
+
+    fn from_v22_bytes(bytes: &[u8]) -> FrameId {
+        match bytes {
+            b"TT2" => FrameId::Tit2,
+            b"TP1" => FrameId::Tpe1,
+            b"TP2" => FrameId::Tpe2,
+            b"TAL" => FrameId::Talb,
+            b"TRK" => FrameId::Trck,
+            b"TPA" => FrameId::Tpos,
+            b"TCO" => FrameId::Tcon,
+            b"TCM" => FrameId::Tcom,
+            b"TCP" => FrameId::Tcmp,
+            b"TP4" => FrameId::Tpe4,
+            b"PIC" => FrameId::Pic,
+            b"TYE" => FrameId::Tyer,
+            b"TLE" => FrameId::Tlen,
+            _ => FrameId::Unknown,
+        }
+    }
+}
+
+/// `TCON` can refer to an `ID3v1` genre by index, either bare as in "17" or in
+/// parentheses as in "(17)", optionally followed by a refinement like "(17)Rock & Roll".
+fn resolve_genre(text: Cow<'_, str>) -> Cow<'_, str> {
+    let (index, refinement) = match text.strip_prefix('(') {
+        Some(rest) => match rest.split_once(')') {
+            Some((index, refinement)) => (index, refinement),
+            None => return text,
+        },
+        None => (text.as_ref(), ""),
+    };
+
+    if !refinement.is_empty() {
+        return Cow::Owned(refinement.to_owned());
+    }
+
+    match index.parse::<usize>().ok().and_then(|i| GENRES.get(i)) {
+        Some(&genre) => Cow::Borrowed(genre),
+        None => text,
+    }
+}
+
+/// Decode a synchsafe integer, where only the lower 7 bits of each byte are used.
+///
+/// Some taggers write plain big-endian sizes into ID3v2.4 tags, so if any of the
+/// high bits are set the value is read as a regular `u32` instead.
+#[inline(always)]
+pub(crate) fn synchsafe_u32(bytes: &[u8]) -> u32 {
+    if bytes.iter().any(|&b| b & 0x80 != 0) {
+        return bytes.iter().fold(0u32, |acc, &b| (acc << 8) | u32::from(b));
+    }
+
+    bytes
+        .iter()
+        .fold(0u32, |acc, &b| (acc << 7) | u32::from(b & 0x7F))
+}
+
+/// Undo unsynchronisation in place by dropping every `0x00` that follows a `0xFF`.
+///
+/// Returns the new length of the data.
+pub(crate) fn resynchronise(data: &mut [u8]) -> usize {
+    let mut write = 0;
+    let mut previous = 0u8;
+
+    for read in 0..data.len() {
+        let byte = data[read];
+
+        if !(previous == 0xFF && byte == 0x00) {
+            data[write] = byte;
+            write += 1;
+        }
+
+        previous = byte;
+    }
+
+    write
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(id: &[u8; 4], size: [u8; 4], flags: [u8; 2], data: &[u8]) -> Vec<u8> {
+        let mut frame = id.to_vec();
+        frame.extend_from_slice(&size);
+        frame.extend_from_slice(&flags);
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    fn tag(major_version: u8, flags: u8, frames: &[u8]) -> Vec<u8> {
+        let size = frames.len() as u32;
+        let mut tag = b"ID3".to_vec();
+        tag.extend_from_slice(&[major_version, 0, flags]);
+        tag.extend_from_slice(&[
+            (size >> 21) as u8 & 0x7F,
+            (size >> 14) as u8 & 0x7F,
+            (size >> 7) as u8 & 0x7F,
+            size as u8 & 0x7F,
+        ]);
+        tag.extend_from_slice(frames);
+        tag
+    }
+
+    fn read(data: &[u8]) -> (Vec<u8>, Vec<FrameHeader>) {
+        let mut buffer = Vec::new();
+        let headers = Id3::read_all_frames(&mut buffer, &mut Cursor::new(data), false)
+            .unwrap()
+            .frames;
+        (buffer, headers)
+    }
+
+    fn frame_data<'a>(buffer: &'a [u8], header: &FrameHeader) -> &'a [u8] {
+        let start = header.data_start as usize;
+        &buffer[start..start + header.length as usize]
+    }
+
+    #[test]
+    fn synchsafe_sizes() {
+        assert_eq!(synchsafe_u32(&[0x00, 0x00, 0x02, 0x01]), 257);
+        assert_eq!(synchsafe_u32(&[0x7F, 0x7F, 0x7F, 0x7F]), 0x0FFF_FFFF);
+
+        // Non-synchsafe values written by broken taggers
+        assert_eq!(synchsafe_u32(&[0x00, 0x00, 0x00, 0xFF]), 255);
+    }
+
+    #[test]
+    fn resynchronise_removes_inserted_zeroes() {
+        let mut data = [0xFF, 0x00, 0xE0, 0x12, 0xFF, 0x00, 0x00];
+        let len = resynchronise(&mut data);
+
+        assert_eq!(&data[..len], &[0xFF, 0xE0, 0x12, 0xFF, 0x00]);
+    }
+
+    #[test]
+    fn v24_frame_sizes_are_synchsafe() {
+        // 200 bytes of text, which is 0x01 0x48 as a synchsafe integer
+        let mut text = vec![0x03];
+        text.extend(std::iter::repeat_n(b'a', 199));
+
+        let frames = frame(b"TIT2", [0, 0, 0x01, 0x48], [0, 0], &text);
+        let (buffer, headers) = read(&tag(4, 0, &frames));
+
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers[0].length, 200);
+        assert_eq!(frame_data(&buffer, &headers[0]), &text[..]);
+    }
+
+    #[test]
+    fn v24_frame_flags_are_applied() {
+        // Data length indicator + unsynchronisation
+        let data = [0x00, 0x00, 0x00, 0x04, 0x00, 0xFF, 0x00, 0xFE, b'a'];
+        let frames = frame(b"TALB", [0, 0, 0, data.len() as u8], [0, 0x03], &data);
+
+        let (buffer, headers) = read(&tag(4, 0, &frames));
+
+        assert_eq!(frame_data(&buffer, &headers[0]), &[0x00, 0xFF, 0xFE, b'a']);
+    }
+
+    #[test]
+    fn v23_tag_unsynchronisation_is_undone() {
+        let frames = frame(b"TPE1", [0, 0, 0, 3], [0, 0], &[0x00, 0xFF, b'a']);
+
+        // Unsynchronise the whole tag body the way a v2.3 tagger would
+        let mut unsynced = Vec::new();
+        for &b in &frames {
+            unsynced.push(b);
+            if b == 0xFF {
+                unsynced.push(0x00);
+            }
+        }
+
+        let (buffer, headers) = read(&tag(3, Id3Header::UNSYNCHRONISATION, &unsynced));
+
+        assert_eq!(headers[0].frame_id, FrameId::Tpe1);
+        assert_eq!(frame_data(&buffer, &headers[0]), &[0x00, 0xFF, b'a']);
+    }
+
+    #[test]
+    fn compressed_frames_are_skipped() {
+        let mut frames = frame(b"TIT2", [0, 0, 0, 2], [0, 0x08], &[0x00, b'a']);
+        frames.extend(frame(b"TALB", [0, 0, 0, 2], [0, 0], &[0x00, b'b']));
+
+        let (_, headers) = read(&tag(4, 0, &frames));
+
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers[0].frame_id, FrameId::Talb);
+    }
+
+    #[test]
+    fn frames_stop_at_tag_end() {
+        let mut data = tag(3, 0, &frame(b"TIT2", [0, 0, 0, 2], [0, 0], &[0x00, b'a']));
+
+        // Audio data right after the tag without any padding
+        data.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x64, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+
+        let (_, headers) = read(&data);
+        assert_eq!(headers.len(), 1);
+    }
+
+    #[test]
+    fn seek_frame_links_to_next_tag() {
+        let first = tag(4, 0, &frame(b"SEEK", [0, 0, 0, 4], [0, 0], &[0, 0, 0, 4]));
+        let second = tag(4, 0, &frame(b"TIT2", [0, 0, 0, 2], [0, 0], &[0x00, b'a']));
+
+        let mut data = first;
+        data.extend_from_slice(&[0u8; 4]);
+        data.extend_from_slice(&second);
+
+        let (buffer, headers) = read(&data);
+
+        assert_eq!(headers.len(), 1);
+        assert_eq!(frame_data(&buffer, &headers[0]), &[0x00, b'a']);
+    }
+
+    #[test]
+    fn appended_tag_is_found_by_footer() {
+        let frames = frame(b"TALB", [0, 0, 0, 2], [0, 0], &[0x00, b'b']);
+        let tag = tag(4, Id3Header::FOOTER, &frames);
+
+        let mut data = vec![0xFF, 0xFB, 0x90, 0x64];
+        data.extend_from_slice(&tag);
+        data.extend_from_slice(b"3DI");
+        data.extend_from_slice(&tag[3..10]);
+
+        let (_, headers) = read(&data);
+        assert_eq!(headers[0].frame_id, FrameId::Talb);
+    }
+
+    #[test]
+    fn latin1_text_is_decoded() {
+        let text = decode_text(b"\x00Sigur R\xf3s").unwrap();
+        assert_eq!(text, "Sigur Rós");
+
+        let text = decode_text(b"\x00ASCII only\x00").unwrap();
+        assert!(matches!(text, Cow::Borrowed("ASCII only")));
+    }
+
+    #[test]
+    fn utf16_text_is_decoded() {
+        let mut le = vec![0x01, 0xFF, 0xFE];
+        let mut be = vec![0x01, 0xFE, 0xFF];
+        let mut no_bom_be = vec![0x02];
+
+        for unit in "坂本龍一".encode_utf16() {
+            le.extend_from_slice(&unit.to_le_bytes());
+            be.extend_from_slice(&unit.to_be_bytes());
+            no_bom_be.extend_from_slice(&unit.to_be_bytes());
+        }
+        le.extend_from_slice(&[0, 0]);
+
+        assert_eq!(decode_text(&le).unwrap(), "坂本龍一");
+        assert_eq!(decode_text(&be).unwrap(), "坂本龍一");
+        assert_eq!(decode_text(&no_bom_be).unwrap(), "坂本龍一");
+    }
+
+    #[test]
+    fn utf8_text_is_borrowed() {
+        let text = decode_text("\x03Sigur Rós ".as_bytes()).unwrap();
+        assert!(matches!(text, Cow::Borrowed("Sigur Rós")));
+    }
+
+    #[test]
+    fn multiple_values_are_kept() {
+        let text = decode_text(b"\x03Artist\0Featured\0").unwrap();
+        assert_eq!(text, "Artist\0Featured");
+
+        let mut data = vec![0x01];
+        for value in ["Artist", "Featured"] {
+            data.extend_from_slice(&[0xFF, 0xFE]);
+            data.extend(value.encode_utf16().flat_map(u16::to_le_bytes));
+            data.extend_from_slice(&[0, 0]);
+        }
+        let text = decode_text(&data).unwrap();
+        assert_eq!(text, "Artist\0Featured");
+    }
+
+    #[test]
+    fn stops_once_required_frames_are_found() {
+        let picture = frame(b"APIC", [0, 0, 0, 13], [0, 0], b"\x00image/png\0\x03\0");
+        let frames = [
+            frame(b"TIT2", [0, 0, 0, 6], [0, 0], b"\x03Title"),
+            picture.clone(),
+            frame(b"TPE1", [0, 0, 0, 7], [0, 0], b"\x03Artist"),
+            frame(b"TALB", [0, 0, 0, 6], [0, 0], b"\x03Album"),
+            frame(b"TRCK", [0, 0, 0, 2], [0, 0], b"\x031"),
+            frame(b"TCON", [0, 0, 0, 5], [0, 0], b"\x03Rock"),
+            picture,
+        ]
+        .concat();
+        let data = tag(4, 0, &frames);
+
+        let read = |skip_picture| {
+            let mut buffer = Vec::new();
+            let headers = Id3::read_all_frames(&mut buffer, &mut Cursor::new(&data), skip_picture)
+                .unwrap()
+                .frames;
+            let ids: Vec<_> = headers.iter().map(|header| header.frame_id).collect();
+            (buffer.len(), ids)
+        };
+
+        let (buffered, ids) = read(false);
+        assert_eq!(
+            ids,
+            [
+                FrameId::Tit2,
+                FrameId::Apic,
+                FrameId::Tpe1,
+                FrameId::Talb,
+                FrameId::Trck,
+            ]
+        );
+        assert_eq!(buffered, 6 + 13 + 7 + 6 + 2);
+
+        // Pictures aren't buffered at all
+        let (buffered, ids) = read(true);
+        assert_eq!(
+            ids,
+            [FrameId::Tit2, FrameId::Tpe1, FrameId::Talb, FrameId::Trck]
+        );
+        assert_eq!(buffered, 6 + 7 + 6 + 2);
+    }
+
+    #[test]
+    fn every_frame_is_kept_for_tag_map() {
+        let frames = [
+            frame(b"TIT2", [0, 0, 0, 6], [0, 0], b"\x03Title"),
+            frame(b"TXXX", [0, 0, 0, 15], [0, 0], b"\x03MOOD\0Calm\0Dark"),
+            frame(b"COMM", [0, 0, 0, 10], [0, 0], b"\x00eng\0Hello"),
+            frame(b"PRIV", [0, 0, 0, 3], [0, 0], b"abc"),
+        ]
+        .concat();
+        let data = tag(4, 0, &frames);
+
+        let mut buffer = Vec::new();
+        let headers = Id3::read_every_frame(&mut buffer, &mut Cursor::new(&data))
+            .unwrap()
+            .frames;
+
+        let mut tags = TagMap::new();
+        for header in &headers {
+            push_frame(&mut tags, header, frame_data(&buffer, header));
+        }
+
+        let text = |value: &str| TagValue::Text(Cow::Owned(value.to_owned()));
+        assert_eq!(
+            tags.into_iter().collect::<Vec<_>>(),
+            [
+                (Cow::Borrowed("TITLE"), text("Title")),
+                (Cow::Borrowed("MOOD"), text("Calm")),
+                (Cow::Borrowed("MOOD"), text("Dark")),
+                (Cow::Borrowed("COMMENT"), text("Hello")),
+                (Cow::Borrowed("PRIV"), TagValue::Binary(b"abc")),
+            ]
+        );
+    }
+
+    #[test]
+    fn apic_picture_is_split_from_its_header() {
+        // UTF-16 description, so the terminator is two aligned nulls
+        let mut data = b"\x01image/png\0\x04".to_vec();
+        data.extend_from_slice(&[0xFF, 0xFE, b'B', 0, 0, 0]);
+        data.extend_from_slice(b"\x89PNG");
+
+        let Frame::Picture(picture) = Frame::parse_by_id(FrameId::Apic, &data).unwrap() else {
+            panic!("APIC wasn't parsed as a picture");
+        };
+
+        assert_eq!(picture.picture_type, PictureType::CoverBack);
+        assert_eq!(picture.mime_type, "image/png");
+        assert_eq!(picture.description, "B");
+        assert_eq!(picture.data, b"\x89PNG");
+
+        let data = b"\x00JPG\x03\0\xFF\xD8";
+        let Frame::Picture(picture) = Frame::parse_by_id(FrameId::Pic, data).unwrap() else {
+            panic!("PIC wasn't parsed as a picture");
+        };
+
+        assert_eq!(picture.picture_type, PictureType::CoverFront);
+        assert_eq!(picture.mime_type, "image/jpeg");
+        assert_eq!(picture.data, b"\xFF\xD8");
+    }
+
+    #[test]
+    fn unknown_text_encoding_returns_error() {
+        let err = decode_text(b"\x07text").unwrap_err();
+        assert!(matches!(err, Error::InvalidTextEncoding));
+    }
+
+    #[test]
+    fn genre_references_are_resolved() {
+        let genre = |text: &str| resolve_genre(Cow::Borrowed(text)).into_owned();
+
+        assert_eq!(genre("17"), "Rock");
+        assert_eq!(genre("(17)"), "Rock");
+        assert_eq!(genre("(17)Rock & Roll"), "Rock & Roll");
+        assert_eq!(genre("Post-Rock"), "Post-Rock");
+        assert_eq!(genre("(999)"), "(999)");
+    }
+
+    #[test]
+    fn audio_start_follows_tag() {
+        let frames = frame(b"TIT2", [0, 0, 0, 2], [0, 0], &[0x00, b'a']);
+        let data = tag(4, Id3Header::FOOTER, &frames);
+        let mut buffer = Vec::new();
+
+        let tag = Id3::read_all_frames(&mut buffer, &mut Cursor::new(&data), false).unwrap();
+        assert_eq!(tag.audio_start, 10 + frames.len() as u64 + 10);
+    }
+
+    #[test]
+    fn v22_frames_are_mapped() {
+        let mut frames = b"TT2\x00\x00\x03\x00ab".to_vec();
+        frames.extend_from_slice(b"TP1\x00\x00\x02\x00c");
+        frames.extend_from_slice(b"XYZ\x00\x00\x01\x00");
+        frames.extend_from_slice(&[0u8; 6]);
+
+        let (buffer, headers) = read(&tag(2, 0, &frames));
+
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers[0].frame_id, FrameId::Tit2);
+        assert_eq!(frame_data(&buffer, &headers[0]), b"\x00ab");
+        assert_eq!(headers[1].frame_id, FrameId::Tpe1);
+    }
+
+    #[test]
+    fn unsupported_version_returns_error() {
+        let data = tag(5, 0, &[]);
+        let mut buffer = Vec::new();
+
+        let err = Id3::read_all_frames(&mut buffer, &mut Cursor::new(&data), false).unwrap_err();
+        assert!(matches!(err, Error::UnsupportedId3Version));
+    }
 }
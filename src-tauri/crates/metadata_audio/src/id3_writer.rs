@@ -0,0 +1,574 @@
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{BufReader, Read, Seek},
+    path::Path,
+};
+
+use crate::{
+    Error, Result,
+    id3::{Id3Header, TextEncoding, decode_text, resynchronise, synchsafe_u32},
+    read_into_buffer_unchecked, replace_head, write_in_place,
+};
+
+/// Padding left behind after a full rewrite, so the next edit can happen in place
+const DEFAULT_PADDING: usize = 2 * 1024;
+
+/// Tag and ID3v2.4 frame sizes are synchsafe, so they only have 28 bits
+const MAX_SYNCHSAFE: usize = (1 << 28) - 1;
+
+/// A frame as it's stored in the tag, the data is kept exactly as it was read
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawFrame {
+    pub id: [u8; 4],
+    pub flags: [u8; 2],
+    pub data: Vec<u8>,
+}
+
+impl RawFrame {
+    fn new(id: &[u8; 4], data: Vec<u8>) -> RawFrame {
+        RawFrame {
+            id: *id,
+            flags: [0, 0],
+            data,
+        }
+    }
+}
+
+/// An editable `ID3v2.3` or `ID3v2.4` tag
+#[derive(Debug, Clone, PartialEq)]
+pub struct Id3TagBlock {
+    pub major_version: u8,
+    pub frames: Vec<RawFrame>,
+}
+
+impl Default for Id3TagBlock {
+    fn default() -> Self {
+        Id3TagBlock {
+            major_version: 4,
+            frames: Vec::new(),
+        }
+    }
+}
+
+impl Id3TagBlock {
+    pub fn get(&self, id: &[u8; 4]) -> Option<&RawFrame> {
+        self.frames.iter().find(|frame| &frame.id == id)
+    }
+
+    /// Decoded value of a text frame
+    pub fn text(&self, id: &[u8; 4]) -> Option<Cow<'_, str>> {
+        decode_text(&self.get(id)?.data).ok()
+    }
+
+    pub fn remove(&mut self, id: &[u8; 4]) {
+        self.frames.retain(|frame| &frame.id != id);
+    }
+
+    /// Set a text frame such as `TIT2`, `TPE1` or `TALB`
+    pub fn set_text(&mut self, id: &[u8; 4], value: &str) {
+        let encoding = self.encoding_for(&[value]);
+
+        let mut data = vec![encoding as u8];
+        data.extend(encode(encoding, value));
+
+        self.replace(|frame| &frame.id == id, RawFrame::new(id, data));
+    }
+
+    /// `TRCK`, written as "3/12" when the total is known
+    pub fn set_track(&mut self, number: u32, total: Option<u32>) {
+        self.set_text(b"TRCK", &position(number, total));
+    }
+
+    /// `TPOS`, written as "1/2" when the total is known
+    pub fn set_disc(&mut self, number: u32, total: Option<u32>) {
+        self.set_text(b"TPOS", &position(number, total));
+    }
+
+    /// `TXXX`, only replacing the frame with the same description
+    pub fn set_user_text(&mut self, description: &str, value: &str) {
+        let encoding = self.encoding_for(&[description, value]);
+
+        let mut data = vec![encoding as u8];
+        data.extend(encode(encoding, description));
+        data.extend(terminator(encoding));
+        data.extend(encode(encoding, value));
+
+        self.replace(
+            |frame| &frame.id == b"TXXX" && description_matches(&frame.data, 0, description),
+            RawFrame::new(b"TXXX", data),
+        );
+    }
+
+    /// `USLT`, only replacing the lyrics with the same language and description
+    pub fn set_lyrics(&mut self, language: [u8; 3], description: &str, lyrics: &str) {
+        let encoding = self.encoding_for(&[description, lyrics]);
+
+        let mut data = vec![encoding as u8];
+        data.extend_from_slice(&language);
+        data.extend(encode(encoding, description));
+        data.extend(terminator(encoding));
+        data.extend(encode(encoding, lyrics));
+
+        self.replace(
+            |frame| {
+                &frame.id == b"USLT"
+                    && frame.data.get(1..4) == Some(&language)
+                    && description_matches(&frame.data, 3, description)
+            },
+            RawFrame::new(b"USLT", data),
+        );
+    }
+
+    /// `APIC`, only replacing the picture of the same type
+    pub fn set_picture(
+        &mut self,
+        picture_type: u8,
+        mime_type: &str,
+        description: &str,
+        data: &[u8],
+    ) {
+        let encoding = self.encoding_for(&[description]);
+
+        let mut frame = vec![encoding as u8];
+        // The MIME type is always Latin-1
+        frame.extend_from_slice(mime_type.as_bytes());
+        frame.push(0);
+        frame.push(picture_type);
+        frame.extend(encode(encoding, description));
+        frame.extend(terminator(encoding));
+        frame.extend_from_slice(data);
+
+        self.replace(
+            |frame| &frame.id == b"APIC" && apic_type(&frame.data) == Some(picture_type),
+            RawFrame::new(b"APIC", frame),
+        );
+    }
+
+    /// Swap out the first matching frame, keeping its position, and drop any other matches
+    fn replace(&mut self, matches: impl Fn(&RawFrame) -> bool, frame: RawFrame) {
+        match self.frames.iter().position(&matches) {
+            Some(i) => {
+                self.frames[i] = frame;
+
+                let mut index = 0;
+                self.frames.retain(|f| {
+                    index += 1;
+                    index - 1 == i || !matches(f)
+                });
+            }
+            None => self.frames.push(frame),
+        }
+    }
+
+    /// `ID3v2.4` can use UTF-8 everywhere, `ID3v2.3` only has Latin-1 and UTF-16
+    fn encoding_for(&self, texts: &[&str]) -> TextEncoding {
+        if self.major_version >= 4 {
+            TextEncoding::Utf8
+        } else if texts
+            .iter()
+            .all(|text| text.chars().all(|c| u32::from(c) <= 0xFF))
+        {
+            TextEncoding::Latin1
+        } else {
+            TextEncoding::Utf16
+        }
+    }
+
+    /// Serialise the frames, without the tag header or padding
+    fn frames_to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(self.frames.iter().map(|f| 10 + f.data.len()).sum());
+
+        for frame in &self.frames {
+            if frame.data.len() > MAX_SYNCHSAFE {
+                return Err(Error::BlockTooLarge);
+            }
+
+            let size = frame.data.len() as u32;
+            let size = if self.major_version >= 4 {
+                to_synchsafe(size)
+            } else {
+                size.to_be_bytes()
+            };
+
+            bytes.extend_from_slice(&frame.id);
+            bytes.extend_from_slice(&size);
+            bytes.extend_from_slice(&frame.flags);
+            bytes.extend_from_slice(&frame.data);
+        }
+
+        Ok(bytes)
+    }
+}
+
+pub struct Id3Writer {}
+
+impl Id3Writer {
+    /// Read the tag at the start of a file, to be edited and passed back to [`Id3Writer::write`].
+    ///
+    /// Files without a tag get an empty `ID3v2.4` tag.
+    pub fn read_tag(path: &Path) -> Result<Id3TagBlock> {
+        let mut reader = BufReader::new(File::open(path)?);
+        Ok(Self::read_frames(&mut reader)?.map_or_else(Id3TagBlock::default, |(tag, _)| tag))
+    }
+
+    /// Write `tag` to the start of the file.
+    ///
+    /// The audio, and anything after it such as an `ID3v1` trailer, is kept as it is.
+    /// When the frames fit in the old tag and its padding the file is edited in place,
+    /// otherwise it's rewritten through a temporary file.
+    pub fn write(path: &Path, tag: &Id3TagBlock) -> Result<()> {
+        if !matches!(tag.major_version, 3 | 4) {
+            return Err(Error::UnsupportedId3Version);
+        }
+
+        let mut reader = BufReader::new(File::open(path)?);
+        let old_size = Self::read_frames(&mut reader)?.map_or(0, |(_, size)| size);
+        drop(reader);
+
+        let frames = tag.frames_to_bytes()?;
+        let needed = 10 + frames.len();
+
+        if old_size > 0 && needed <= old_size {
+            let head = Self::encode_tag(tag, &frames, old_size - needed)?;
+            return write_in_place(path, 0, &head);
+        }
+
+        let head = Self::encode_tag(tag, &frames, DEFAULT_PADDING)?;
+        replace_head(path, &head, old_size as u64)
+    }
+
+    fn encode_tag(tag: &Id3TagBlock, frames: &[u8], padding: usize) -> Result<Vec<u8>> {
+        let tag_size = frames.len() + padding;
+        if tag_size > MAX_SYNCHSAFE {
+            return Err(Error::BlockTooLarge);
+        }
+
+        let mut bytes = Vec::with_capacity(10 + tag_size);
+        bytes.extend_from_slice(b"ID3");
+        bytes.extend_from_slice(&[tag.major_version, 0, 0]);
+        bytes.extend_from_slice(&to_synchsafe(tag_size as u32));
+        bytes.extend_from_slice(frames);
+        bytes.resize(10 + tag_size, 0);
+
+        Ok(bytes)
+    }
+
+    /// Read the raw frames of the tag at the start of the stream, along with the
+    /// total size the tag takes up in the file.
+    fn read_frames<R: Read + Seek>(reader: &mut R) -> Result<Option<(Id3TagBlock, usize)>> {
+        let header = match Id3Header::parse(reader) {
+            Ok(header) => header,
+            Err(Error::InvalidId3Signature) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        // ID3v2.2 frames have different IDs and no flags, so they can't be carried over
+        if header.major_version == 2 {
+            return Err(Error::UnsupportedId3Version);
+        }
+
+        let mut data = Vec::new();
+        read_into_buffer_unchecked(reader, &mut data, header.frames_len()? as usize)?;
+
+        if header.major_version == 3 && header.unsynchronisation() {
+            let len = resynchronise(&mut data);
+            data.truncate(len);
+        }
+
+        let mut tag = Id3TagBlock {
+            major_version: header.major_version,
+            frames: Vec::new(),
+        };
+
+        let mut i = 0;
+        while i + 10 <= data.len() && data[i] != 0 {
+            let id = [data[i], data[i + 1], data[i + 2], data[i + 3]];
+            let size = if header.major_version == 4 {
+                synchsafe_u32(&data[i + 4..i + 8])
+            } else {
+                u32::from_be_bytes([data[i + 4], data[i + 5], data[i + 6], data[i + 7]])
+            } as usize;
+            let mut flags = [data[i + 8], data[i + 9]];
+
+            let frame_data = data
+                .get(i + 10..i + 10 + size)
+                .ok_or(Error::InvalidFrameSize)?;
+            i += 10 + size;
+
+            // Frames flagged to be discarded when the tag is altered are dropped
+            let discard = match header.major_version {
+                3 => flags[0] & 0x80 != 0,
+                _ => flags[0] & 0x40 != 0,
+            };
+            if discard {
+                continue;
+            }
+
+            // The tag won't be unsynchronised anymore, so each frame has to say it is
+            if header.major_version == 4 && header.unsynchronisation() {
+                flags[1] |= 0x02;
+            }
+
+            tag.frames.push(RawFrame {
+                id,
+                flags,
+                data: frame_data.to_vec(),
+            });
+        }
+
+        Ok(Some((tag, header.total_size() as usize)))
+    }
+}
+
+fn position(number: u32, total: Option<u32>) -> String {
+    match total {
+        Some(total) => format!("{number}/{total}"),
+        None => number.to_string(),
+    }
+}
+
+fn to_synchsafe(value: u32) -> [u8; 4] {
+    [
+        (value >> 21) as u8 & 0x7F,
+        (value >> 14) as u8 & 0x7F,
+        (value >> 7) as u8 & 0x7F,
+        value as u8 & 0x7F,
+    ]
+}
+
+fn encode(encoding: TextEncoding, text: &str) -> Vec<u8> {
+    match encoding {
+        TextEncoding::Latin1 => text.chars().map(|c| c as u8).collect(),
+        TextEncoding::Utf16 => {
+            let mut bytes = vec![0xFF, 0xFE];
+            bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+            bytes
+        }
+        TextEncoding::Utf16Be => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+        TextEncoding::Utf8 => text.as_bytes().to_vec(),
+    }
+}
+
+fn terminator(encoding: TextEncoding) -> &'static [u8] {
+    match encoding {
+        TextEncoding::Latin1 | TextEncoding::Utf8 => &[0],
+        TextEncoding::Utf16 | TextEncoding::Utf16Be => &[0, 0],
+    }
+}
+
+/// Compare the null terminated description that follows the encoding byte and `skip` bytes
+fn description_matches(data: &[u8], skip: usize, description: &str) -> bool {
+    let Some((&encoding, rest)) = data.split_first() else {
+        return false;
+    };
+    let Some(rest) = rest.get(skip..) else {
+        return false;
+    };
+
+    // UTF-16 terminators are two bytes wide and aligned to the code units
+    let end = if matches!(encoding, 1 | 2) {
+        rest.chunks_exact(2)
+            .position(|pair| pair == [0, 0])
+            .map(|i| i * 2)
+    } else {
+        rest.iter().position(|&b| b == 0)
+    };
+
+    let text = end.map_or(rest, |end| &rest[..end]);
+    let mut field = vec![encoding];
+    field.extend_from_slice(text);
+
+    decode_text(&field).is_ok_and(|text| text == description)
+}
+
+/// Picture type of an `APIC` frame, which follows the null terminated MIME type
+fn apic_type(data: &[u8]) -> Option<u8> {
+    let mime_end = data.get(1..)?.iter().position(|&b| b == 0)?;
+    data.get(1 + mime_end + 1).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Cursor, path::PathBuf};
+
+    use super::*;
+    use crate::{
+        Metadata,
+        id3::{Frame, FrameId, Id3},
+        temp_path,
+    };
+
+    const AUDIO: &[u8] = b"\xFF\xFB\x90\x64audio frames that must survive";
+
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, data: &[u8]) -> TempFile {
+            let path = std::env::temp_dir()
+                .join(format!("metadata_audio_{}_{name}.mp3", std::process::id()));
+            fs::write(&path, data).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn tagged(tag: &Id3TagBlock, padding: usize) -> Vec<u8> {
+        let frames = tag.frames_to_bytes().unwrap();
+        let mut data = Id3Writer::encode_tag(tag, &frames, padding).unwrap();
+        data.extend_from_slice(AUDIO);
+        data
+    }
+
+    fn tag(major_version: u8) -> Id3TagBlock {
+        let mut tag = Id3TagBlock {
+            major_version,
+            frames: vec![RawFrame::new(b"PRIV", b"owner\0opaque".to_vec())],
+        };
+        tag.set_text(b"TIT2", "Hoppípolla");
+        tag.set_text(b"TPE1", "Sigur Rós");
+        tag.set_text(b"TALB", "Takk...");
+        tag
+    }
+
+    #[test]
+    fn frames_round_trip_through_reader() {
+        for major_version in [3, 4] {
+            let mut tag = tag(major_version);
+            tag.set_track(3, Some(11));
+            tag.set_disc(1, None);
+            tag.set_picture(3, "image/png", "", &[0x89, b'P', b'N', b'G']);
+
+            let data = tagged(&tag, 0);
+            let mut buffer = Vec::new();
+            let headers = Id3::read_all_frames(&mut buffer, &mut Cursor::new(&data), false)
+                .unwrap()
+                .frames;
+
+            let texts: Vec<_> = headers
+                .iter()
+                .filter_map(|header| {
+                    let start = header.data_start as usize;
+                    let data = &buffer[start..start + header.length as usize];
+                    match Frame::parse_by_id(header.frame_id, data).unwrap() {
+                        Frame::Text((id, text)) => Some((id, text.into_owned())),
+                        _ => None,
+                    }
+                })
+                .collect();
+
+            assert_eq!(
+                texts,
+                [
+                    (FrameId::Tit2, "Hoppípolla".to_owned()),
+                    (FrameId::Tpe1, "Sigur Rós".to_owned()),
+                    (FrameId::Talb, "Takk...".to_owned()),
+                    (FrameId::Trck, "3/11".to_owned()),
+                    (FrameId::Tpos, "1".to_owned()),
+                ]
+            );
+
+            let parsed = Id3Writer::read_frames(&mut Cursor::new(&data))
+                .unwrap()
+                .unwrap()
+                .0;
+            assert_eq!(parsed, tag);
+        }
+    }
+
+    #[test]
+    fn v23_uses_utf16_only_when_needed() {
+        let mut tag = tag(3);
+        tag.set_text(b"TCOM", "Jónsi");
+        tag.set_text(b"TIT3", "ヨンシー");
+
+        assert_eq!(
+            tag.get(b"TCOM").unwrap().data[0],
+            TextEncoding::Latin1 as u8
+        );
+        assert_eq!(tag.get(b"TIT3").unwrap().data[0], TextEncoding::Utf16 as u8);
+        assert_eq!(tag.text(b"TIT3").as_deref(), Some("ヨンシー"));
+    }
+
+    #[test]
+    fn user_text_and_lyrics_replace_by_description() {
+        let mut tag = tag(3);
+        tag.set_user_text("MusicBrainz Album Id", "old");
+        tag.set_user_text("REPLAYGAIN_TRACK_GAIN", "-6.5 dB");
+        tag.set_user_text("MusicBrainz Album Id", "new");
+        tag.set_lyrics(*b"eng", "", "first");
+        tag.set_lyrics(*b"isl", "", "fyrst");
+        tag.set_lyrics(*b"eng", "", "second");
+
+        let txxx: Vec<_> = tag.frames.iter().filter(|f| &f.id == b"TXXX").collect();
+        assert_eq!(txxx.len(), 2);
+        assert!(txxx[0].data.ends_with(b"new"));
+
+        let uslt: Vec<_> = tag.frames.iter().filter(|f| &f.id == b"USLT").collect();
+        assert_eq!(uslt.len(), 2);
+        assert!(uslt[0].data.ends_with(b"second"));
+    }
+
+    #[test]
+    fn padding_is_reused_in_place() {
+        let file = TempFile::new("id3_in_place", &tagged(&tag(4), 512));
+        let original_len = fs::metadata(&file.0).unwrap().len();
+
+        let mut tag = Id3Writer::read_tag(&file.0).unwrap();
+        tag.set_text(b"TIT2", "A different title");
+        tag.set_track(7, None);
+        Id3Writer::write(&file.0, &tag).unwrap();
+
+        let data = fs::read(&file.0).unwrap();
+        assert_eq!(data.len() as u64, original_len);
+        assert!(data.ends_with(AUDIO));
+
+        let mut buffer = Vec::new();
+        let meta = Metadata::from_file(&mut buffer, &file.0, false).unwrap();
+        assert_eq!(meta.name.as_deref(), Some("A different title"));
+        assert_eq!(meta.track_number, Some(7));
+
+        let tag = Id3Writer::read_tag(&file.0).unwrap();
+        assert_eq!(tag.get(b"PRIV").unwrap().data, b"owner\0opaque");
+    }
+
+    #[test]
+    fn file_is_rewritten_when_tag_grows() {
+        let mut audio = AUDIO.to_vec();
+        audio.extend_from_slice(b"TAG");
+        audio.resize(AUDIO.len() + 128, 0);
+
+        let file = TempFile::new("id3_rewrite", &audio);
+
+        let mut tag = tag(4);
+        tag.set_picture(3, "image/jpeg", "cover", &[0xFF; 4096]);
+        Id3Writer::write(&file.0, &tag).unwrap();
+
+        let data = fs::read(&file.0).unwrap();
+        assert!(data.ends_with(&audio));
+        assert!(!temp_path(&file.0).exists());
+
+        let parsed = Id3Writer::read_tag(&file.0).unwrap();
+        assert_eq!(parsed, tag);
+    }
+
+    #[test]
+    fn discardable_frames_are_dropped() {
+        let mut tag = tag(4);
+        tag.frames[0].flags = [0x40, 0];
+
+        let data = tagged(&tag, 0);
+        let parsed = Id3Writer::read_frames(&mut Cursor::new(&data))
+            .unwrap()
+            .unwrap()
+            .0;
+
+        assert!(parsed.get(b"PRIV").is_none());
+        assert_eq!(parsed.frames.len(), 3);
+    }
+}
@@ -0,0 +1,354 @@
+use std::{
+    borrow::Cow,
+    io::{Read, Seek, SeekFrom},
+};
+
+use crate::{Error, Result, id3::TextEncoding, read_into_buffer_unchecked};
+
+/// Size of the `ID3v1` trailer at the end of a file
+pub const TAG_SIZE: u64 = 128;
+
+/// `ID3v1` genres, including the Winamp extensions, indexed by the genre byte
+pub const GENRES: [&str; 192] = [
+    "Blues",
+    "Classic Rock",
+    "Country",
+    "Dance",
+    "Disco",
+    "Funk",
+    "Grunge",
+    "Hip-Hop",
+    "Jazz",
+    "Metal",
+    "New Age",
+    "Oldies",
+    "Other",
+    "Pop",
+    "R&B",
+    "Rap",
+    "Reggae",
+    "Rock",
+    "Techno",
+    "Industrial",
+    "Alternative",
+    "Ska",
+    "Death Metal",
+    "Pranks",
+    "Soundtrack",
+    "Euro-Techno",
+    "Ambient",
+    "Trip-Hop",
+    "Vocal",
+    "Jazz+Funk",
+    "Fusion",
+    "Trance",
+    "Classical",
+    "Instrumental",
+    "Acid",
+    "House",
+    "Game",
+    "Sound Clip",
+    "Gospel",
+    "Noise",
+    "AlternRock",
+    "Bass",
+    "Soul",
+    "Punk",
+    "Space",
+    "Meditative",
+    "Instrumental Pop",
+    "Instrumental Rock",
+    "Ethnic",
+    "Gothic",
+    "Darkwave",
+    "Techno-Industrial",
+    "Electronic",
+    "Pop-Folk",
+    "Eurodance",
+    "Dream",
+    "Southern Rock",
+    "Comedy",
+    "Cult",
+    "Gangsta",
+    "Top 40",
+    "Christian Rap",
+    "Pop/Funk",
+    "Jungle",
+    "Native American",
+    "Cabaret",
+    "New Wave",
+    "Psychedelic",
+    "Rave",
+    "Showtunes",
+    "Trailer",
+    "Lo-Fi",
+    "Tribal",
+    "Acid Punk",
+    "Acid Jazz",
+    "Polka",
+    "Retro",
+    "Musical",
+    "Rock & Roll",
+    "Hard Rock",
+    "Folk",
+    "Folk-Rock",
+    "National Folk",
+    "Swing",
+    "Fast Fusion",
+    "Bebop",
+    "Latin",
+    "Revival",
+    "Celtic",
+    "Bluegrass",
+    "Avantgarde",
+    "Gothic Rock",
+    "Progressive Rock",
+    "Psychedelic Rock",
+    "Symphonic Rock",
+    "Slow Rock",
+    "Big Band",
+    "Chorus",
+    "Easy Listening",
+    "Acoustic",
+    "Humour",
+    "Speech",
+    "Chanson",
+    "Opera",
+    "Chamber Music",
+    "Sonata",
+    "Symphony",
+    "Booty Bass",
+    "Primus",
+    "Porn Groove",
+    "Satire",
+    "Slow Jam",
+    "Club",
+    "Tango",
+    "Samba",
+    "Folklore",
+    "Ballad",
+    "Power Ballad",
+    "Rhythmic Soul",
+    "Freestyle",
+    "Duet",
+    "Punk Rock",
+    "Drum Solo",
+    "A Cappella",
+    "Euro-House",
+    "Dance Hall",
+    "Goa",
+    "Drum & Bass",
+    "Club-House",
+    "Hardcore",
+    "Terror",
+    "Indie",
+    "BritPop",
+    "Afro-Punk",
+    "Polsk Punk",
+    "Beat",
+    "Christian Gangsta Rap",
+    "Heavy Metal",
+    "Black Metal",
+    "Crossover",
+    "Contemporary Christian",
+    "Christian Rock",
+    "Merengue",
+    "Salsa",
+    "Thrash Metal",
+    "Anime",
+    "JPop",
+    "Synthpop",
+    "Abstract",
+    "Art Rock",
+    "Baroque",
+    "Bhangra",
+    "Big Beat",
+    "Breakbeat",
+    "Chillout",
+    "Downtempo",
+    "Dub",
+    "EBM",
+    "Eclectic",
+    "Electro",
+    "Electroclash",
+    "Emo",
+    "Experimental",
+    "Garage",
+    "Global",
+    "IDM",
+    "Illbient",
+    "Industro-Goth",
+    "Jam Band",
+    "Krautrock",
+    "Leftfield",
+    "Lounge",
+    "Math Rock",
+    "New Romantic",
+    "Nu-Breakz",
+    "Post-Punk",
+    "Post-Rock",
+    "Psytrance",
+    "Shoegaze",
+    "Space Rock",
+    "Trop Rock",
+    "World Music",
+    "Neoclassical",
+    "Audiobook",
+    "Audio Theatre",
+    "Neue Deutsche Welle",
+    "Podcast",
+    "Indie Rock",
+    "G-Funk",
+    "Dubstep",
+    "Garage Rock",
+    "Psybient",
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct Id3v1<'a> {
+    pub title: Option<Cow<'a, str>>,
+
+    pub artist: Option<Cow<'a, str>>,
+
+    pub album: Option<Cow<'a, str>>,
+
+    pub year: Option<u16>,
+
+    /// Only present in `ID3v1.1` tags, which borrow the last bytes of the comment
+    pub track_number: Option<u32>,
+
+    pub genre: Option<&'static str>,
+}
+
+impl<'a> Id3v1<'a> {
+    /// Reads the trailer at the end of the stream into the buffer.
+    ///
+    /// Returns where in the buffer the tag starts, or `None` if the stream has no trailer.
+    pub fn read_tag<R: Read + Seek>(buffer: &mut Vec<u8>, reader: &mut R) -> Result<Option<u32>> {
+        let stream_len = reader.seek(SeekFrom::End(0))?;
+        if stream_len < TAG_SIZE {
+            return Ok(None);
+        }
+
+        reader.seek(SeekFrom::Start(stream_len - TAG_SIZE))?;
+
+        let start = buffer.len();
+        read_into_buffer_unchecked(reader, buffer, TAG_SIZE as usize)?;
+
+        if &buffer[start..start + 3] != b"TAG" {
+            buffer.truncate(start);
+            return Ok(None);
+        }
+
+        Ok(Some(start as u32))
+    }
+
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Id3v1<'a>> {
+        if bytes.len() < TAG_SIZE as usize {
+            return Err(Error::TruncatedBlock);
+        }
+
+        let comment = &bytes[97..127];
+
+        // ID3v1.1 uses a zero byte in front of the last comment byte to mark a track number
+        let track_number = if comment[28] == 0 && comment[29] != 0 {
+            Some(u32::from(comment[29]))
+        } else {
+            None
+        };
+
+        Ok(Id3v1 {
+            title: Self::text_field(&bytes[3..33])?,
+            artist: Self::text_field(&bytes[33..63])?,
+            album: Self::text_field(&bytes[63..93])?,
+            year: str::from_utf8(&bytes[93..97])
+                .ok()
+                .and_then(|year| year.parse().ok()),
+            track_number,
+            genre: GENRES.get(bytes[127] as usize).copied(),
+        })
+    }
+
+    /// Fields are padded with either null bytes or spaces
+    fn text_field(bytes: &'a [u8]) -> Result<Option<Cow<'a, str>>> {
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        let text = TextEncoding::Latin1.decode(&bytes[..end])?;
+
+        Ok((!text.is_empty()).then_some(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn field(text: &[u8], len: usize) -> Vec<u8> {
+        let mut field = text.to_vec();
+        field.resize(len, 0);
+        field
+    }
+
+    fn trailer(comment: &[u8], genre: u8) -> Vec<u8> {
+        let mut tag = b"TAG".to_vec();
+        tag.extend(field(b"Hoppipolla", 30));
+        tag.extend(field(b"Sigur R\xf3s", 30));
+        tag.extend(field(b"Takk...   ", 30));
+        tag.extend_from_slice(b"2005");
+        tag.extend_from_slice(comment);
+        tag.push(genre);
+        tag
+    }
+
+    #[test]
+    fn reads_id3v11_trailer() {
+        let mut comment = field(b"comment", 28);
+        comment.extend_from_slice(&[0, 3]);
+
+        let mut data = vec![0xFF, 0xFB, 0x90, 0x64];
+        data.extend(trailer(&comment, 17));
+
+        let mut buffer = Vec::new();
+        let start = Id3v1::read_tag(&mut buffer, &mut Cursor::new(&data))
+            .unwrap()
+            .unwrap() as usize;
+
+        let tag = Id3v1::from_bytes(&buffer[start..]).unwrap();
+
+        assert_eq!(tag.title.as_deref(), Some("Hoppipolla"));
+        assert_eq!(tag.artist.as_deref(), Some("Sigur Rós"));
+        assert_eq!(tag.album.as_deref(), Some("Takk..."));
+        assert_eq!(tag.year, Some(2005));
+        assert_eq!(tag.track_number, Some(3));
+        assert_eq!(tag.genre, Some("Rock"));
+    }
+
+    #[test]
+    fn id3v10_has_no_track_number() {
+        let tag = trailer(&field(b"a comment that fills the whole field", 30), 255);
+        let tag = Id3v1::from_bytes(&tag).unwrap();
+
+        assert_eq!(tag.track_number, None);
+        assert_eq!(tag.genre, None);
+    }
+
+    #[test]
+    fn short_tag_returns_error() {
+        let tag = trailer(&field(b"Comment", 30), 0);
+        let err = Id3v1::from_bytes(&tag[..100]).unwrap_err();
+
+        assert!(matches!(err, Error::TruncatedBlock));
+    }
+
+    #[test]
+    fn missing_trailer_returns_none() {
+        let data = vec![0u8; 200];
+        let mut buffer = Vec::new();
+
+        let start = Id3v1::read_tag(&mut buffer, &mut Cursor::new(&data)).unwrap();
+
+        assert_eq!(start, None);
+        assert!(buffer.is_empty());
+    }
+}
@@ -1,46 +1,169 @@
 pub mod flac;
+pub mod flac_writer;
 mod id3;
+pub mod id3_writer;
+pub mod id3v1;
+pub mod mp4;
+mod mpeg;
+pub mod ogg;
+pub mod picture;
+pub mod properties;
+pub mod registry;
+pub mod riff;
+pub mod tag_map;
 mod traits;
 
 use std::{
-    fs::File,
-    io::{BufReader, Cursor, Read, Seek},
+    borrow::Cow,
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
 };
 
 use crate::{
-    flac::Block,
+    flac::{Block, VorbisComment},
     id3::{Frame, FrameId},
+    id3v1::Id3v1,
+    mp4::{Atom, AtomId},
+    picture::{Picture, PictureType},
+    properties::{Codec, StreamProperties},
+    riff::TextId,
 };
 
+pub use tag_map::{TagMap, TagValue};
+pub use traits::{MetadataFormat, ReadSeek};
+
+/// Album artist used for compilations that don't have one tagged
+pub const VARIOUS_ARTISTS: &str = "Various Artists";
+
 #[derive(Debug, Clone, Default)]
 /// Metadata struct that holds information about an audio file
 pub struct Metadata<'a> {
-    /// Artist name
-    pub artist: Option<&'a str>,
+    /// Track artist name
+    pub artist: Option<Cow<'a, str>>,
+
+    /// Every track artist for tags that hold multiple, `artist` is the first of them
+    pub artists: Vec<Cow<'a, str>>,
+
+    /// Album artist name, only set if the file has one tagged
+    pub album_artist: Option<Cow<'a, str>>,
+
+    /// Whether the track is part of a compilation of different artists
+    pub compilation: bool,
 
     /// Album name
-    pub album: Option<&'a str>,
+    pub album: Option<Cow<'a, str>>,
 
     /// Track name
-    pub name: Option<&'a str>,
+    pub name: Option<Cow<'a, str>>,
 
     /// Duration of the album in seconds
     pub duration: f32,
 
+    /// Codec, sample rate, bit depth, channels & bitrate of the audio
+    pub stream: StreamProperties,
+
     /// Track number
     pub track_number: Option<u32>,
 
+    /// Amount of tracks on the disc
+    pub track_total: Option<u32>,
+
+    /// Disc number
+    pub disc_number: Option<u32>,
+
+    /// Amount of discs in the release
+    pub disc_total: Option<u32>,
+
+    /// Genre name
+    pub genre: Option<Cow<'a, str>>,
+
+    /// Composer name
+    pub composer: Option<Cow<'a, str>>,
+
+    /// Every composer for tags that hold multiple, `composer` is the first of them
+    pub composers: Vec<Cow<'a, str>>,
+
+    /// Remixers of the track
+    pub remixers: Vec<Cow<'a, str>>,
+
     /// Year of publication
     pub year: Option<u16>,
 
-    /// Picture data
-    pub picture_data: Option<&'a [u8]>,
+    /// Release date as tagged, usually `yyyy-MM-dd` or a prefix of it
+    pub release_date: Option<Cow<'a, str>>,
+
+    /// Every embedded picture, in the order they're tagged
+    pub pictures: Vec<Picture<'a>>,
 }
 
+/// The formats the built-in format registry knows about
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum SupportedFormats {
     Flac,
     ID3,
+    Ogg,
+    Mp4,
+    Riff,
+}
+
+impl MetadataFormat for SupportedFormats {
+    fn detect_signature(&self, signature: &[u8]) -> bool {
+        match self {
+            SupportedFormats::Flac => signature.starts_with(b"fLaC"),
+            // Files without an ID3v2 tag start straight away with an MPEG frame sync,
+            // layer bits of 00 are reserved so AAC ADTS streams aren't picked up.
+            SupportedFormats::ID3 => {
+                signature.starts_with(b"ID3")
+                    || matches!(signature, [0xFF, b, ..] if b & 0xE0 == 0xE0 && b & 0x06 != 0)
+            }
+            SupportedFormats::Ogg => signature.starts_with(b"OggS"),
+            SupportedFormats::Mp4 => signature.get(4..8) == Some(b"ftyp"),
+            SupportedFormats::Riff => matches!(
+                (signature.get(0..4), signature.get(8..12)),
+                (Some(b"RIFF"), Some(b"WAVE")) | (Some(b"FORM"), Some(b"AIFF" | b"AIFC"))
+            ),
+        }
+    }
+
+    fn parse<'a>(
+        &self,
+        buffer: &'a mut Vec<u8>,
+        mut reader: &mut dyn ReadSeek,
+        skip_picture: bool,
+    ) -> Result<Metadata<'a>> {
+        match self {
+            SupportedFormats::Flac => Metadata::read_flac(buffer, &mut reader, skip_picture),
+            SupportedFormats::ID3 => Metadata::read_id3(buffer, &mut reader, skip_picture),
+            SupportedFormats::Ogg => Metadata::read_ogg(buffer, &mut reader, skip_picture),
+            SupportedFormats::Mp4 => Metadata::read_mp4(buffer, &mut reader, skip_picture),
+            SupportedFormats::Riff => Metadata::read_riff(buffer, &mut reader, skip_picture),
+        }
+    }
+
+    fn tags<'a>(
+        &self,
+        buffer: &'a mut Vec<u8>,
+        mut reader: &mut dyn ReadSeek,
+    ) -> Result<TagMap<'a>> {
+        match self {
+            SupportedFormats::Flac => TagMap::read_flac(buffer, &mut reader),
+            SupportedFormats::ID3 => TagMap::read_id3(buffer, &mut reader),
+            SupportedFormats::Ogg => TagMap::read_ogg(buffer, &mut reader),
+            SupportedFormats::Mp4 => TagMap::read_mp4(buffer, &mut reader),
+            SupportedFormats::Riff => TagMap::read_riff(buffer, &mut reader),
+        }
+    }
+
+    fn extensions(&self) -> &[&str] {
+        match self {
+            SupportedFormats::Flac => &["flac"],
+            SupportedFormats::ID3 => &["mp3"],
+            SupportedFormats::Ogg => &["ogg", "oga", "opus"],
+            SupportedFormats::Mp4 => &["m4a", "m4b", "mp4"],
+            SupportedFormats::Riff => &["wav", "aif", "aiff", "aifc"],
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -51,10 +174,40 @@ pub enum Error {
     UnsupportedFileType,
     #[error("Invalid FLAC signature")]
     InvalidFlacSignature,
+    #[error("Invalid Vorbis comment")]
+    InvalidVorbisComment,
+    #[error("Metadata block is too large")]
+    BlockTooLarge,
+    #[error("Metadata block is truncated")]
+    TruncatedBlock,
     #[error("Invalid ID3 signature")]
     InvalidId3Signature,
     #[error("Unsupported ID3 version")]
     UnsupportedId3Version,
+    #[error("Invalid ID3 tag size")]
+    InvalidId3TagSize,
+    #[error("Invalid frame size")]
+    InvalidFrameSize,
+    #[error("Compressed ID3 frames are not supported")]
+    CompressedId3Frame,
+    #[error("Encrypted ID3 frames are not supported")]
+    EncryptedId3Frame,
+    #[error("Invalid text encoding")]
+    InvalidTextEncoding,
+    #[error("Invalid Ogg signature")]
+    InvalidOggSignature,
+    #[error("Unsupported Ogg codec")]
+    UnsupportedOggCodec,
+    #[error("Invalid MP4 signature")]
+    InvalidMp4Signature,
+    #[error("Invalid atom size")]
+    InvalidAtomSize,
+    #[error("Invalid RIFF/AIFF signature")]
+    InvalidRiffSignature,
+    #[error("Invalid chunk size")]
+    InvalidChunkSize,
+    #[error("Invalid embedded picture")]
+    InvalidPicture,
 
     #[error(transparent)]
     Io(#[from] std::io::Error),
@@ -62,6 +215,8 @@ pub enum Error {
     Utf8(#[from] std::string::FromUtf8Error),
     #[error(transparent)]
     Str(#[from] std::str::Utf8Error),
+    #[error(transparent)]
+    Utf16(#[from] std::char::DecodeUtf16Error),
 }
 
 pub(crate) type Result<T, U = Error> = std::result::Result<T, U>;
@@ -74,17 +229,40 @@ impl<'a> Metadata<'a> {
             match block {
                 Block::StreamInfo(stream_info) => {
                     metadata.duration = stream_info.duration;
+                    metadata.stream = StreamProperties {
+                        codec: Codec::Flac,
+                        sample_rate: stream_info.sample_rate,
+                        bit_depth: Some(stream_info.bits_per_sample),
+                        channels: stream_info.channels,
+                        bitrate: 0,
+                    };
                 }
                 Block::VorbisComment(vorbis_comment) => {
-                    metadata.album = vorbis_comment.album;
-                    metadata.artist = vorbis_comment.album_artist;
-                    metadata.name = vorbis_comment.title;
-                    metadata.year = vorbis_comment.year;
+                    metadata.album = vorbis_comment.album.map(Cow::Borrowed);
+                    metadata.artist = vorbis_comment
+                        .artists
+                        .first()
+                        .copied()
+                        .or(vorbis_comment.album_artist)
+                        .map(Cow::Borrowed);
+                    metadata.artists = borrow_all(&vorbis_comment.artists);
+                    metadata.album_artist = vorbis_comment.album_artist.map(Cow::Borrowed);
+                    metadata.compilation = vorbis_comment.compilation;
+                    metadata.name = vorbis_comment.title.map(Cow::Borrowed);
+                    metadata.year = vorbis_comment
+                        .year
+                        .or_else(|| vorbis_comment.date.and_then(year_from_date));
+                    metadata.release_date = vorbis_comment.date.map(Cow::Borrowed);
                     metadata.track_number = vorbis_comment.track_number;
+                    metadata.track_total = vorbis_comment.track_total;
+                    metadata.disc_number = vorbis_comment.disc_number;
+                    metadata.disc_total = vorbis_comment.disc_total;
+                    metadata.genre = vorbis_comment.genre.map(Cow::Borrowed);
+                    metadata.composer = vorbis_comment.composers.first().map(|c| Cow::Borrowed(*c));
+                    metadata.composers = borrow_all(&vorbis_comment.composers);
+                    metadata.remixers = borrow_all(&vorbis_comment.remixers);
                 }
-                Block::Picture(picture) => {
-                    metadata.picture_data = Some(picture.data);
-                }
+                Block::Picture(picture) => metadata.pictures.push(picture),
                 Block::Unknown => {}
             }
         }
@@ -98,21 +276,152 @@ impl<'a> Metadata<'a> {
         for frame in frames {
             match frame {
                 Frame::Duration(duration) => metadata.duration = duration,
-                Frame::Text((frame_id, frame_str)) => match frame_id {
-                    FrameId::Talb => metadata.album = Some(frame_str),
-                    FrameId::Tit2 => metadata.name = Some(frame_str),
-                    FrameId::Tpe1 => metadata.artist = Some(frame_str),
+                Frame::Text((frame_id, frame_str)) => {
+                    let mut values = split_values(frame_str);
+
+                    match frame_id {
+                        FrameId::Tpe1 => {
+                            metadata.artist = values.first().cloned();
+                            metadata.artists = values;
+                        }
+                        FrameId::Tcom => {
+                            metadata.composer = values.first().cloned();
+                            metadata.composers = values;
+                        }
+                        FrameId::Tpe4 => metadata.remixers = values,
+                        // Everything else only has room for a single value
+                        _ if !values.is_empty() => {
+                            metadata.set_id3_text(frame_id, values.swap_remove(0));
+                        }
+                        _ => {}
+                    }
+                }
+                Frame::Picture(picture) => metadata.pictures.push(picture),
+                Frame::Year(year) => metadata.year = metadata.year.or(Some(year)),
+                Frame::Unknown => {}
+            }
+        }
+
+        metadata.fill_artist_from_album_artist();
+
+        metadata
+    }
+
+    fn set_id3_text(&mut self, frame_id: FrameId, text: Cow<'a, str>) {
+        match frame_id {
+            FrameId::Talb => self.album = Some(text),
+            FrameId::Tit2 => self.name = Some(text),
+            FrameId::Tpe2 => self.album_artist = Some(text),
+            // iTunes writes TCMP as "1", but it isn't part of the specification
+            FrameId::Tcmp => self.compilation = text == "1",
+            FrameId::Tcon => self.genre = Some(text),
+            // TRCK and TPOS can also hold the total, as in "3/12"
+            FrameId::Trck => (self.track_number, self.track_total) = parse_position(&text),
+            FrameId::Tpos => (self.disc_number, self.disc_total) = parse_position(&text),
+            // TDRC is a timestamp (yyyy-MM-ddTHH:mm:ss), but it always starts with the year
+            FrameId::Tdrc => {
+                self.year = year_from_date(&text).or(self.year);
+                self.release_date = Some(text);
+            }
+            _ => {}
+        }
+    }
+
+    /// The picture to use as album cover, which is the front cover unless there isn't one
+    pub fn front_cover(&self) -> Option<&Picture<'a>> {
+        // A MIME type of "-->" means the picture is only a link to the image
+        let embedded = || {
+            self.pictures
+                .iter()
+                .filter(|picture| picture.mime_type != "-->")
+        };
+        let of_type = |picture_type| embedded().find(|p| p.picture_type == picture_type);
+
+        of_type(PictureType::CoverFront)
+            .or_else(|| of_type(PictureType::Other))
+            .or_else(|| embedded().next())
+    }
+
+    /// Files tagged with only an album artist still need a track artist
+    fn fill_artist_from_album_artist(&mut self) {
+        if self.artist.is_none() {
+            self.artist.clone_from(&self.album_artist);
+        }
+    }
+
+    /// The artist the track's album is filed under.
+    ///
+    /// This is the album artist if there is one, "Various Artists" for untagged compilations,
+    /// and otherwise the track artist.
+    pub fn album_artist_or_artist(&self) -> Option<&str> {
+        match (&self.album_artist, self.compilation) {
+            (Some(album_artist), _) => Some(album_artist),
+            (None, true) => Some(VARIOUS_ARTISTS),
+            (None, false) => self.artist.as_deref(),
+        }
+    }
+
+    fn from_mp4_atoms(atoms: Vec<Atom>) -> Metadata {
+        let mut metadata = Metadata::default();
+
+        for atom in atoms {
+            match atom {
+                Atom::MovieDuration(duration) => metadata.duration = duration,
+                // Only the first track is played
+                Atom::Stream(stream) if metadata.stream.codec == Codec::Unknown => {
+                    metadata.stream = stream;
+                }
+                Atom::MediaDuration(duration) => {
+                    if metadata.duration <= 0.0 {
+                        metadata.duration = duration;
+                    }
+                }
+                Atom::Text((atom_id, text)) => match atom_id {
+                    AtomId::Title => metadata.name = Some(Cow::Borrowed(text)),
+                    AtomId::Album => metadata.album = Some(Cow::Borrowed(text)),
+                    AtomId::AlbumArtist => metadata.album_artist = Some(Cow::Borrowed(text)),
+                    AtomId::Artist => {
+                        metadata.artist = Some(Cow::Borrowed(text));
+                        metadata.artists = vec![Cow::Borrowed(text)];
+                    }
+                    AtomId::Composer => {
+                        metadata.composer = Some(Cow::Borrowed(text));
+                        metadata.composers = vec![Cow::Borrowed(text)];
+                    }
+                    AtomId::CustomGenre => metadata.genre = Some(Cow::Borrowed(text)),
                     _ => {}
                 },
-                Frame::Picture(picture_data) => metadata.picture_data = Some(picture_data),
-                Frame::Year(year) => metadata.year = Some(year),
-                Frame::Unknown => {}
+                Atom::TrackNumber(track_number) => metadata.track_number = Some(track_number),
+                Atom::DiscNumber(disc_number) => metadata.disc_number = Some(disc_number),
+                Atom::Compilation(compilation) => metadata.compilation = compilation,
+                Atom::Year(year) => metadata.year = Some(year),
+                // A free-form genre is more specific than a standard one
+                Atom::Genre(genre) => {
+                    metadata.genre.get_or_insert(Cow::Borrowed(genre));
+                }
+                // MP4 doesn't say what a cover shows, but it's the front cover by convention
+                Atom::Picture(data) => metadata
+                    .pictures
+                    .push(Picture::from_image(PictureType::CoverFront, data)),
+                Atom::Stream(_) | Atom::Unknown => {}
             }
         }
 
+        metadata.fill_artist_from_album_artist();
+
         metadata
     }
 
+    /// Fill in whatever the `ID3v2` tag didn't have from an `ID3v1` trailer
+    fn merge_id3v1(&mut self, tag: Id3v1<'a>) {
+        self.name = self.name.take().or(tag.title);
+        self.artist = self.artist.take().or(tag.artist);
+        self.album = self.album.take().or(tag.album);
+        self.year = self.year.or(tag.year);
+        self.track_number = self.track_number.or(tag.track_number);
+        self.genre = self.genre.take().or(tag.genre.map(Cow::Borrowed));
+    }
+
     fn read_flac<R: Read + Seek>(
         buffer: &'a mut Vec<u8>,
         reader: &mut R,
@@ -120,83 +429,214 @@ impl<'a> Metadata<'a> {
     ) -> Result<Metadata<'a>> {
         let block_headers = flac::Flac::read_all_blocks(buffer, reader, skip_picture)?;
 
+        // The last metadata block is followed by the audio, unless the blocks run past the end
+        let audio_start = reader.stream_position()?;
+        let audio_bytes = reader.seek(SeekFrom::End(0))?.saturating_sub(audio_start);
+
         let mut flac_blocks = Vec::with_capacity(block_headers.len());
         for header in block_headers {
-            let start = header.start as usize;
-            let end = (header.start + header.length) as usize;
-
-            let slice = &buffer[start..end];
+            let slice = slice_at(buffer, header.start as usize, header.length as usize)?;
             let block = flac::Block::parse_by_block_type(header.block_type, slice)?;
             flac_blocks.push(block);
         }
 
-        Ok(Metadata::from_flac_blocks(flac_blocks))
+        let mut metadata = Metadata::from_flac_blocks(flac_blocks);
+        metadata.stream.bitrate = StreamProperties::average_bitrate(audio_bytes, metadata.duration);
+
+        Ok(metadata)
     }
 
-    fn read_id3<R: Read + Seek>(buffer: &'a mut Vec<u8>, reader: &mut R) -> Result<Metadata<'a>> {
-        let frame_headers = id3::Id3::read_all_frames(buffer, reader)?;
+    fn read_id3<R: Read + Seek>(
+        buffer: &'a mut Vec<u8>,
+        reader: &mut R,
+        skip_picture: bool,
+    ) -> Result<Metadata<'a>> {
+        let (frame_headers, audio_start, v2_error) =
+            match id3::Id3::read_all_frames(buffer, reader, skip_picture) {
+                Ok(tag) => (tag.frames, tag.audio_start, None),
+                Err(e @ (Error::InvalidId3Signature | Error::UnsupportedId3Version)) => {
+                    (Vec::new(), 0, Some(e))
+                }
+                Err(e) => return Err(e),
+            };
+
+        let is_usable = [FrameId::Tit2, FrameId::Tpe1, FrameId::Talb]
+            .iter()
+            .all(|id| frame_headers.iter().any(|h| h.frame_id == *id));
+
+        let id3v1_start = if is_usable {
+            None
+        } else {
+            Id3v1::read_tag(buffer, reader)?
+        };
+
+        if let Some(e) = v2_error
+            && id3v1_start.is_none()
+        {
+            return Err(e);
+        }
+
+        let mpeg_stream = mpeg::read_stream(buffer, reader, audio_start)?;
 
         let mut id3_frames = Vec::with_capacity(frame_headers.len());
         for frame in frame_headers {
-            let start = frame.data_start as usize;
-            let end = start + frame.length as usize;
-
-            let slice = &buffer[start..end];
+            let slice = slice_at(buffer, frame.data_start as usize, frame.length as usize)?;
             let block = id3::Frame::parse_by_id(frame.frame_id, slice)?;
             id3_frames.push(block);
         }
 
-        Ok(Metadata::from_id3_frames(id3_frames))
+        let mut metadata = Metadata::from_id3_frames(id3_frames);
+
+        // A Xing/VBRI frame count beats TLEN, which in turn beats a bitrate estimate
+        if let Some(stream) = mpeg_stream {
+            metadata.stream = stream.properties;
+
+            if stream.exact || metadata.duration <= 0.0 {
+                metadata.duration = stream.seconds;
+            }
+        }
+
+        if let Some(start) = id3v1_start {
+            let slice = slice_at(buffer, start as usize, id3v1::TAG_SIZE as usize)?;
+            let tag = Id3v1::from_bytes(slice)?;
+            metadata.merge_id3v1(tag);
+        }
+
+        Ok(metadata)
     }
 
-    /// Create a `Metadata` struct from a valid audio file
-    pub fn from_file(
+    fn read_ogg<R: Read + Seek>(
         buffer: &'a mut Vec<u8>,
-        path: &'a Path,
+        reader: &mut R,
         skip_picture: bool,
     ) -> Result<Metadata<'a>> {
-        if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
-            if ext.eq_ignore_ascii_case("flac") {
-                let file = File::open(path)?;
-                let mut reader = BufReader::with_capacity(4 * 1024, file);
+        let headers = ogg::Ogg::read_headers(buffer, reader, skip_picture)?;
 
-                let metadata = Self::read_flac(buffer, &mut reader, skip_picture)?;
+        let comment = &headers.comment;
+        let comment = slice_at(buffer, comment.start as usize, comment.length as usize)?;
 
-                Ok(metadata)
-            } else if ext.eq_ignore_ascii_case("mp3") {
-                let file = File::open(path)?;
-                let mut reader = BufReader::with_capacity(4 * 1024, file);
+        let mut blocks = Vec::with_capacity(2);
+        blocks.push(Block::VorbisComment(VorbisComment::from_bytes(comment)?));
 
-                let metadata = Self::read_id3(buffer, &mut reader)?;
+        for picture in headers.pictures {
+            let slice = slice_at(buffer, picture.start as usize, picture.length as usize)?;
+            blocks.push(Block::Picture(Picture::from_flac_bytes(slice)?));
+        }
 
-                Ok(metadata)
-            } else {
-                Err(Error::UnsupportedFileType)
-            }
-        } else {
-            Err(Error::InvalidFilePath)
+        let mut metadata = Metadata::from_flac_blocks(blocks);
+        metadata.duration = headers.duration;
+        metadata.stream = headers.properties;
+
+        Ok(metadata)
+    }
+
+    fn read_mp4<R: Read + Seek>(
+        buffer: &'a mut Vec<u8>,
+        reader: &mut R,
+        skip_picture: bool,
+    ) -> Result<Metadata<'a>> {
+        let tag = mp4::Mp4::read_all_atoms(buffer, reader, skip_picture)?;
+
+        let mut atoms = Vec::with_capacity(tag.atoms.len());
+        for header in tag.atoms {
+            let slice = slice_at(buffer, header.start as usize, header.length as usize)?;
+            let atom = mp4::Atom::parse_by_id(header.atom_id, slice)?;
+            atoms.push(atom);
         }
+
+        let mut metadata = Metadata::from_mp4_atoms(atoms);
+        metadata.stream.bitrate =
+            StreamProperties::average_bitrate(tag.media_size, metadata.duration);
+
+        Ok(metadata)
     }
 
-    pub fn from_bytes(
+    fn read_riff<R: Read + Seek>(
         buffer: &'a mut Vec<u8>,
-        data: &[u8],
-        format: SupportedFormats,
+        reader: &mut R,
         skip_picture: bool,
     ) -> Result<Metadata<'a>> {
-        match format {
-            SupportedFormats::Flac => {
-                let mut reader = Cursor::new(data);
-                Self::read_flac(buffer, &mut reader, skip_picture)
+        let chunks = riff::Riff::read_all_chunks(buffer, reader, skip_picture)?;
+
+        let mut id3_frames = Vec::with_capacity(chunks.id3_frames.len());
+        for frame in chunks.id3_frames {
+            let slice = slice_at(buffer, frame.data_start as usize, frame.length as usize)?;
+            id3_frames.push(id3::Frame::parse_by_id(frame.frame_id, slice)?);
+        }
+
+        let mut metadata = Metadata::from_id3_frames(id3_frames);
+        metadata.duration = chunks.duration;
+        metadata.stream = StreamProperties {
+            codec: Codec::Pcm,
+            sample_rate: chunks.sample_rate,
+            bit_depth: Some(chunks.bits_per_sample as u8),
+            channels: chunks.channels as u8,
+            bitrate: 0,
+        };
+        metadata.stream.bitrate = metadata.stream.pcm_bitrate();
+
+        // An embedded ID3 tag is usually more complete, so INFO only fills in the gaps
+        for header in chunks.text {
+            let slice = slice_at(buffer, header.start as usize, header.length as usize)?;
+            let text = riff::decode_text(slice)?;
+            if text.is_empty() {
+                continue;
             }
-            SupportedFormats::ID3 => {
-                let mut reader = Cursor::new(data);
-                Self::read_id3(buffer, &mut reader)
+
+            match header.text_id {
+                TextId::Title => metadata.name = metadata.name.take().or(Some(text)),
+                TextId::Artist => metadata.artist = metadata.artist.take().or(Some(text)),
+                TextId::Album => metadata.album = metadata.album.take().or(Some(text)),
+                TextId::Genre => metadata.genre = metadata.genre.take().or(Some(text)),
+                TextId::Date => {
+                    metadata.year = metadata.year.or_else(|| year_from_date(&text));
+                    metadata.release_date = metadata.release_date.take().or(Some(text));
+                }
+                TextId::TrackNumber => {
+                    metadata.track_number = metadata.track_number.or_else(|| text.parse().ok());
+                }
+                TextId::Other => {}
             }
         }
+
+        Ok(metadata)
+    }
+
+    /// Create a `Metadata` struct from a valid audio file.
+    ///
+    /// The format is detected from the start of the file, the extension is
+    /// only used when none of the registered formats recognise it.
+    pub fn from_file(
+        buffer: &'a mut Vec<u8>,
+        path: &'a Path,
+        skip_picture: bool,
+    ) -> Result<Metadata<'a>> {
+        let file = File::open(path)?;
+        let mut reader = BufReader::with_capacity(4 * 1024, file);
+        let extension = path.extension().and_then(|s| s.to_str());
+
+        registry::formats().parse(buffer, &mut reader, extension, skip_picture)
+    }
+
+    /// Create a `Metadata` struct from an audio file already in memory
+    pub fn from_bytes(
+        buffer: &'a mut Vec<u8>,
+        data: &[u8],
+        skip_picture: bool,
+    ) -> Result<Metadata<'a>> {
+        let mut reader = Cursor::new(data);
+        registry::formats().parse(buffer, &mut reader, None, skip_picture)
+    }
+
+    /// Add a format for `from_file`, `from_bytes` and `recursive_dir` to use.
+    ///
+    /// It's tried before the built-in formats, so it can also replace one of them.
+    pub fn register_format(format: impl MetadataFormat + 'static) {
+        registry::register(Box::new(format));
     }
 
     pub fn recursive_dir(path: &Path) -> Vec<PathBuf> {
+        let formats = registry::formats();
         let mut tracks = Vec::with_capacity(3_000);
         let mut stack = vec![path.to_path_buf()];
 
@@ -206,10 +646,10 @@ impl<'a> Metadata<'a> {
                     let path = entry.path();
                     if path.is_dir() {
                         stack.push(path);
-                    } else if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
-                        if ext.eq_ignore_ascii_case("mp3") || ext.eq_ignore_ascii_case("flac") {
-                            tracks.push(path);
-                        }
+                    } else if let Some(ext) = path.extension().and_then(|s| s.to_str())
+                        && formats.by_extension(ext).is_some()
+                    {
+                        tracks.push(path);
                     }
                 }
             }
@@ -227,10 +667,11 @@ fn read_n_bits_u32(bytes: &[u8], start_bit: usize, n_bits: usize) -> u32 {
     let byte_offset = start_bit / 8;
     let bit_offset = start_bit % 8;
 
-    // Load 4 bytes (u32) from the slice
+    // Load 4 bytes (u32) from the slice, missing bytes are read as zeroes
+    let available = bytes.get(byte_offset..).unwrap_or_default();
+    let len = available.len().min(4);
     let mut buf = [0u8; 4];
-    let len = bytes.len() - byte_offset;
-    buf[..len.min(4)].copy_from_slice(&bytes[byte_offset..byte_offset + len.min(4)]);
+    buf[..len].copy_from_slice(&available[..len]);
 
     let val = u32::from_be_bytes(buf);
     let shift = 32 - n_bits - bit_offset;
@@ -245,10 +686,11 @@ fn read_n_bits_u64(bytes: &[u8], start_bit: usize, n_bits: usize) -> u64 {
     let byte_offset = start_bit / 8;
     let bit_offset = start_bit % 8;
 
-    // Load 8 bytes (u64) from the slice
+    // Load 8 bytes (u64) from the slice, missing bytes are read as zeroes
+    let available = bytes.get(byte_offset..).unwrap_or_default();
+    let len = available.len().min(8);
     let mut buf = [0u8; 8];
-    let len = bytes.len() - byte_offset;
-    buf[..len.min(8)].copy_from_slice(&bytes[byte_offset..byte_offset + len.min(8)]);
+    buf[..len].copy_from_slice(&available[..len]);
 
     let val = u64::from_be_bytes(buf);
     let shift = 64 - n_bits - bit_offset;
@@ -257,18 +699,70 @@ fn read_n_bits_u64(bytes: &[u8], start_bit: usize, n_bits: usize) -> u64 {
 
 /// Convert a little-endian slice of bytes to a `u32` integer
 #[inline(always)]
-fn u32_from_bytes_le(bytes: &[u8], offset: &mut usize) -> u32 {
-    let res = u32::from_le_bytes(bytes[*offset..*offset + 4].try_into().unwrap());
+fn u32_from_bytes_le(bytes: &[u8], offset: &mut usize) -> Result<u32> {
+    let &[a, b, c, d] = slice_at(bytes, *offset, 4)? else {
+        return Err(Error::TruncatedBlock);
+    };
+
     *offset += 4;
-    res
+    Ok(u32::from_le_bytes([a, b, c, d]))
 }
 
 /// Convert a big-endian slice of bytes to a `u32` integer
 #[inline(always)]
-fn u32_from_bytes_be(bytes: &[u8], offset: &mut usize) -> u32 {
-    let res = u32::from_be_bytes(bytes[*offset..*offset + 4].try_into().unwrap());
+fn u32_from_bytes_be(bytes: &[u8], offset: &mut usize) -> Result<u32> {
+    let &[a, b, c, d] = slice_at(bytes, *offset, 4)? else {
+        return Err(Error::TruncatedBlock);
+    };
+
     *offset += 4;
-    res
+    Ok(u32::from_be_bytes([a, b, c, d]))
+}
+
+/// `len` bytes starting at `start`, or `TruncatedBlock` if the slice is too short
+fn slice_at(bytes: &[u8], start: usize, len: usize) -> Result<&[u8]> {
+    start
+        .checked_add(len)
+        .and_then(|end| bytes.get(start..end))
+        .ok_or(Error::TruncatedBlock)
+}
+
+/// Split a position such as "3/12" into its number and total, either may be missing
+fn parse_position(text: &str) -> (Option<u32>, Option<u32>) {
+    let (number, total) = text.split_once('/').unwrap_or((text, ""));
+
+    (number.trim().parse().ok(), total.trim().parse().ok())
+}
+
+/// Split a text holding multiple null separated values, dropping empty ones
+pub(crate) fn split_values(text: Cow<'_, str>) -> Vec<Cow<'_, str>> {
+    if !text.contains('\0') {
+        return vec![text];
+    }
+
+    match text {
+        Cow::Borrowed(text) => text
+            .split('\0')
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(Cow::Borrowed)
+            .collect(),
+        Cow::Owned(text) => text
+            .split('\0')
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(|value| Cow::Owned(value.to_owned()))
+            .collect(),
+    }
+}
+
+fn borrow_all<'a>(values: &[&'a str]) -> Vec<Cow<'a, str>> {
+    values.iter().map(|&value| Cow::Borrowed(value)).collect()
+}
+
+/// Dates are tagged as `yyyy`, `yyyy-MM-dd` or a full timestamp, all starting with the year
+fn year_from_date(date: &str) -> Option<u16> {
+    date.get(0..4).and_then(|year| year.parse().ok())
 }
 
 #[inline(always)]
@@ -294,48 +788,103 @@ pub(crate) fn read_into_buffer_unchecked<R: Read>(
         buffer.set_len(end_offset);
     };
 
-    reader.read_exact(&mut buffer[start_offset..end_offset])?;
+    // The buffer must not be left holding bytes that were never read
+    if let Err(e) = reader.read_exact(&mut buffer[start_offset..end_offset]) {
+        buffer.truncate(start_offset);
+        return Err(e.into());
+    }
 
     Ok(())
 }
 
+/// Overwrite part of a file without touching the rest of it
+pub(crate) fn write_in_place(path: &Path, offset: u64, data: &[u8]) -> Result<()> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(data)?;
+    file.sync_data()?;
+
+    Ok(())
+}
+
+/// Replace everything in front of `audio_start` with `head`.
+///
+/// The new file is written next to the original and renamed over it once it's
+/// complete, so a failed write never leaves a half-written file behind.
+pub(crate) fn replace_head(path: &Path, head: &[u8], audio_start: u64) -> Result<()> {
+    let temp_path = temp_path(path);
+
+    let result = (|| -> Result<()> {
+        let mut source = File::open(path)?;
+        let permissions = source.metadata()?.permissions();
+
+        let mut temp = BufWriter::new(File::create(&temp_path)?);
+        temp.write_all(head)?;
+
+        source.seek(SeekFrom::Start(audio_start))?;
+        std::io::copy(&mut source, &mut temp)?;
+
+        let temp = temp.into_inner().map_err(|e| e.into_error())?;
+        temp.sync_all()?;
+        std::fs::set_permissions(&temp_path, permissions)?;
+
+        Ok(())
+    })();
+
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(e);
+    }
+
+    std::fs::rename(&temp_path, path)?;
+
+    Ok(())
+}
+
+/// The temporary file lives next to the original so the rename stays on one filesystem
+pub(crate) fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".veil-tmp");
+    path.with_file_name(name)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn u32_from_bytes_little_endian() {
-        let result = u32_from_bytes_le(&[0x00, 0x00, 0x00, 0x00], &mut 0);
+        let result = u32_from_bytes_le(&[0x00, 0x00, 0x00, 0x00], &mut 0).unwrap();
         assert_eq!(result, 0x0000);
 
-        let result = u32_from_bytes_le(&[0x01, 0x02, 0x03, 0x04], &mut 0);
+        let result = u32_from_bytes_le(&[0x01, 0x02, 0x03, 0x04], &mut 0).unwrap();
         assert_eq!(result, 0x04030201);
 
-        let result = u32_from_bytes_le(&[0x4, 0x3, 0x2, 0x1], &mut 0);
+        let result = u32_from_bytes_le(&[0x4, 0x3, 0x2, 0x1], &mut 0).unwrap();
         assert_eq!(result, 0x01020304);
 
-        let result = u32_from_bytes_le(&[0x10, 0x20, 0x30, 0x40], &mut 0);
+        let result = u32_from_bytes_le(&[0x10, 0x20, 0x30, 0x40], &mut 0).unwrap();
         assert_eq!(result, 0x40302010);
 
-        let result = u32_from_bytes_le(&[0x10, 0x20, 0x40, 0x30], &mut 0);
+        let result = u32_from_bytes_le(&[0x10, 0x20, 0x40, 0x30], &mut 0).unwrap();
         assert_eq!(result, 0x30402010);
     }
 
     #[test]
     fn u32_from_bytes_big_endian() {
-        let result = u32_from_bytes_be(&[0x00, 0x00, 0x00, 0x00], &mut 0);
+        let result = u32_from_bytes_be(&[0x00, 0x00, 0x00, 0x00], &mut 0).unwrap();
         assert_eq!(result, 0x0000);
 
-        let result = u32_from_bytes_be(&[0x01, 0x02, 0x03, 0x04], &mut 0);
+        let result = u32_from_bytes_be(&[0x01, 0x02, 0x03, 0x04], &mut 0).unwrap();
         assert_eq!(result, 0x01020304);
 
-        let result = u32_from_bytes_be(&[0x4, 0x3, 0x2, 0x1], &mut 0);
+        let result = u32_from_bytes_be(&[0x4, 0x3, 0x2, 0x1], &mut 0).unwrap();
         assert_eq!(result, 0x04030201);
 
-        let result = u32_from_bytes_be(&[0x10, 0x20, 0x30, 0x40], &mut 0);
+        let result = u32_from_bytes_be(&[0x10, 0x20, 0x30, 0x40], &mut 0).unwrap();
         assert_eq!(result, 0x10203040);
 
-        let result = u32_from_bytes_be(&[0x10, 0x20, 0x40, 0x30], &mut 0);
+        let result = u32_from_bytes_be(&[0x10, 0x20, 0x40, 0x30], &mut 0).unwrap();
         assert_eq!(result, 0x10204030);
     }
 
@@ -348,53 +897,328 @@ mod tests {
         assert_eq!(val, 0b1010_1100);
     }
 
+    #[test]
+    fn stream_info_reads_channels_and_bit_depth() {
+        // 44.1 kHz, 2 channels, 16 bits per sample, 441000 samples
+        let mut bytes = [0u8; 34];
+        bytes[10..18].copy_from_slice(&[0x0A, 0xC4, 0x42, 0xF0, 0x00, 0x06, 0xBA, 0xA8]);
+
+        let stream_info = crate::flac::StreamInfo::from_bytes(&bytes).unwrap();
+
+        assert_eq!(stream_info.sample_rate, 44_100);
+        assert_eq!(stream_info.channels, 2);
+        assert_eq!(stream_info.bits_per_sample, 16);
+        assert_eq!(stream_info.total_samples, 441_000);
+        assert_eq!(stream_info.duration, 10.0);
+    }
+
     #[test]
     fn metadata_from_flac_blocks_populates_fields() {
         let blocks = vec![
             Block::StreamInfo(crate::flac::StreamInfo {
                 duration: 123.0,
                 sample_rate: 20_000,
+                channels: 2,
+                bits_per_sample: 24,
                 total_samples: 60_000,
             }),
             Block::VorbisComment(crate::flac::VorbisComment {
                 vendor_string: Some("Vendor"),
                 album: Some("Album"),
                 album_artist: Some("Artist"),
+                artists: vec![],
+                compilation: false,
                 title: Some("Track"),
-                year: Some(2024),
+                year: None,
+                date: Some("2024-03-01"),
                 track_number: Some(3),
+                track_total: Some(12),
+                disc_number: Some(2),
+                disc_total: Some(2),
+                genre: Some("Post-Rock"),
+                composers: vec!["Composer"],
+                remixers: vec![],
             }),
         ];
 
         let meta = Metadata::from_flac_blocks(blocks);
 
         assert_eq!(meta.duration, 123.0);
-        assert_eq!(meta.album, Some("Album"));
-        assert_eq!(meta.artist, Some("Artist"));
-        assert_eq!(meta.name, Some("Track"));
+        assert_eq!(meta.stream.codec, Codec::Flac);
+        assert_eq!(meta.stream.bit_depth, Some(24));
+        assert_eq!(meta.stream.channels, 2);
+        assert_eq!(meta.album.as_deref(), Some("Album"));
+        assert_eq!(meta.artist.as_deref(), Some("Artist"));
+        assert_eq!(meta.name.as_deref(), Some("Track"));
         assert_eq!(meta.year, Some(2024));
+        assert_eq!(meta.release_date.as_deref(), Some("2024-03-01"));
         assert_eq!(meta.track_number, Some(3));
+        assert_eq!(meta.track_total, Some(12));
+        assert_eq!(meta.disc_number, Some(2));
+        assert_eq!(meta.disc_total, Some(2));
+        assert_eq!(meta.genre.as_deref(), Some("Post-Rock"));
+        assert_eq!(meta.composer.as_deref(), Some("Composer"));
+    }
+
+    #[test]
+    fn vorbis_comment_reads_positions_and_totals() {
+        let mut data = 0u32.to_le_bytes().to_vec();
+        let comments = [
+            "TRACKNUMBER=3/12",
+            "DISCNUMBER=2",
+            "DISCTOTAL=3",
+            "DATE=2005-09-12",
+        ];
+        data.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            data.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            data.extend_from_slice(comment.as_bytes());
+        }
+
+        let vorbis = VorbisComment::from_bytes(&data).unwrap();
+
+        assert_eq!(vorbis.track_number, Some(3));
+        assert_eq!(vorbis.track_total, Some(12));
+        assert_eq!(vorbis.disc_number, Some(2));
+        assert_eq!(vorbis.disc_total, Some(3));
+        assert_eq!(vorbis.date, Some("2005-09-12"));
+    }
+
+    #[test]
+    fn vorbis_comment_keeps_repeated_fields() {
+        let mut data = 0u32.to_le_bytes().to_vec();
+        let comments = ["artist=Main", "ARTIST=Featured", "Composer=Composer"];
+        data.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            data.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            data.extend_from_slice(comment.as_bytes());
+        }
+
+        let vorbis = VorbisComment::from_bytes(&data).unwrap();
+
+        assert_eq!(vorbis.artists, ["Main", "Featured"]);
+        assert_eq!(vorbis.composers, ["Composer"]);
+    }
+
+    #[test]
+    fn truncated_vorbis_comment_returns_error() {
+        let mut data = 0u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(&11u32.to_le_bytes());
+        data.extend_from_slice(b"TITLE=Track");
+
+        // The second comment is missing entirely
+        let err = VorbisComment::from_bytes(&data).unwrap_err();
+        assert!(matches!(err, Error::TruncatedBlock));
+
+        // The first comment claims more bytes than there are
+        let err = VorbisComment::from_bytes(&data[..data.len() - 1]).unwrap_err();
+        assert!(matches!(err, Error::TruncatedBlock));
+    }
+
+    #[test]
+    fn truncated_stream_info_returns_error() {
+        let err = crate::flac::StreamInfo::from_bytes(&[0u8; 17]).unwrap_err();
+        assert!(matches!(err, Error::TruncatedBlock));
+    }
+
+    #[test]
+    fn truncated_files_never_panic() {
+        let mut data = b"fLaC".to_vec();
+        let mut stream_info = [0u8; 34];
+        stream_info[10..18].copy_from_slice(&[0x0A, 0xC4, 0x42, 0xF0, 0x00, 0x06, 0xBA, 0xA8]);
+        data.extend_from_slice(&[0x00, 0, 0, 34]);
+        data.extend_from_slice(&stream_info);
+
+        let mut comment = 0u32.to_le_bytes().to_vec();
+        comment.extend_from_slice(&1u32.to_le_bytes());
+        comment.extend_from_slice(&11u32.to_le_bytes());
+        comment.extend_from_slice(b"TITLE=Track");
+        data.extend_from_slice(&[0x84, 0, 0, comment.len() as u8]);
+        data.extend(comment);
+
+        let mut id3 = b"ID3\x03\x00\x00\x00\x00\x00\x11".to_vec();
+        id3.extend_from_slice(b"TIT2\x00\x00\x00\x07\x00\x00\x00Title!");
+        id3.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x64]);
+
+        for file in [data, id3] {
+            for len in 0..file.len() {
+                let mut buffer = Vec::new();
+                let _ = Metadata::from_bytes(&mut buffer, &file[..len], false);
+            }
+        }
     }
 
     #[test]
     fn metadata_from_id3_frames_populates_fields() {
         let frames = vec![
-            Frame::Text((FrameId::Tit2, "Track")),
-            Frame::Text((FrameId::Tpe1, "Artist")),
-            Frame::Text((FrameId::Talb, "Album")),
+            Frame::Text((FrameId::Tit2, Cow::Borrowed("Track"))),
+            Frame::Text((FrameId::Tpe1, Cow::Borrowed("Artist"))),
+            Frame::Text((FrameId::Talb, Cow::Borrowed("Album"))),
+            Frame::Text((FrameId::Trck, Cow::Borrowed("4/10"))),
+            Frame::Text((FrameId::Tpos, Cow::Borrowed("1/2"))),
+            Frame::Text((FrameId::Tcon, Cow::Borrowed("Shoegaze"))),
+            Frame::Text((FrameId::Tcom, Cow::Borrowed("Composer"))),
             Frame::Year(2023),
             Frame::Duration(180.0),
         ];
 
         let meta = Metadata::from_id3_frames(frames);
 
-        assert_eq!(meta.name, Some("Track"));
-        assert_eq!(meta.artist, Some("Artist"));
-        assert_eq!(meta.album, Some("Album"));
+        assert_eq!(meta.track_number, Some(4));
+        assert_eq!(meta.track_total, Some(10));
+        assert_eq!(meta.disc_number, Some(1));
+        assert_eq!(meta.disc_total, Some(2));
+        assert_eq!(meta.genre.as_deref(), Some("Shoegaze"));
+        assert_eq!(meta.composer.as_deref(), Some("Composer"));
+
+        assert_eq!(meta.name.as_deref(), Some("Track"));
+        assert_eq!(meta.artist.as_deref(), Some("Artist"));
+        assert_eq!(meta.album.as_deref(), Some("Album"));
         assert_eq!(meta.year, Some(2023));
         assert_eq!(meta.duration, 180.0);
     }
 
+    #[test]
+    fn metadata_from_mp4_atoms_separates_album_artist() {
+        let atoms = vec![
+            Atom::MediaDuration(90.0),
+            Atom::MovieDuration(91.0),
+            Atom::Text((AtomId::Artist, "Artist")),
+            Atom::Text((AtomId::AlbumArtist, "Album Artist")),
+            Atom::Text((AtomId::Title, "Track")),
+            Atom::TrackNumber(5),
+        ];
+
+        let meta = Metadata::from_mp4_atoms(atoms);
+
+        assert_eq!(meta.duration, 91.0);
+        assert_eq!(meta.artist.as_deref(), Some("Artist"));
+        assert_eq!(meta.album_artist.as_deref(), Some("Album Artist"));
+        assert_eq!(meta.album_artist_or_artist(), Some("Album Artist"));
+        assert_eq!(meta.name.as_deref(), Some("Track"));
+        assert_eq!(meta.track_number, Some(5));
+    }
+
+    #[test]
+    fn multiple_values_are_split() {
+        let frames = vec![
+            Frame::Text((FrameId::Tpe1, Cow::Borrowed("Main\0Featured"))),
+            Frame::Text((FrameId::Tcom, Cow::Owned("First\0\0Second".to_owned()))),
+            Frame::Text((FrameId::Tpe4, Cow::Borrowed("Remixer"))),
+            Frame::Text((FrameId::Tit2, Cow::Borrowed("Track\0Alternate"))),
+        ];
+
+        let meta = Metadata::from_id3_frames(frames);
+
+        assert_eq!(meta.artist.as_deref(), Some("Main"));
+        assert_eq!(meta.artists, ["Main", "Featured"]);
+        assert_eq!(meta.composer.as_deref(), Some("First"));
+        assert_eq!(meta.composers, ["First", "Second"]);
+        assert_eq!(meta.remixers, ["Remixer"]);
+        assert_eq!(meta.name.as_deref(), Some("Track"));
+    }
+
+    #[test]
+    fn front_cover_is_preferred() {
+        let picture = |picture_type, data| Picture {
+            picture_type,
+            mime_type: Cow::Borrowed("image/jpeg"),
+            data,
+            ..Default::default()
+        };
+
+        let frames = vec![
+            Frame::Picture(picture(PictureType::Artist, b"artist")),
+            Frame::Picture(picture(PictureType::CoverFront, b"front")),
+            Frame::Picture(picture(PictureType::CoverBack, b"back")),
+        ];
+
+        let meta = Metadata::from_id3_frames(frames);
+        assert_eq!(meta.pictures.len(), 3);
+        assert_eq!(meta.front_cover().unwrap().data, b"front");
+
+        let frames = vec![Frame::Picture(picture(PictureType::CoverBack, b"back"))];
+
+        let meta = Metadata::from_id3_frames(frames);
+        assert_eq!(meta.front_cover().unwrap().data, b"back");
+    }
+
+    #[test]
+    fn compilations_are_filed_under_album_artist() {
+        let frames = vec![
+            Frame::Text((FrameId::Tpe1, Cow::Borrowed("Track Artist"))),
+            Frame::Text((FrameId::Tcmp, Cow::Borrowed("1"))),
+        ];
+
+        let meta = Metadata::from_id3_frames(frames);
+        assert!(meta.compilation);
+        assert_eq!(meta.artist.as_deref(), Some("Track Artist"));
+        assert_eq!(meta.album_artist_or_artist(), Some(VARIOUS_ARTISTS));
+
+        let frames = vec![Frame::Text((FrameId::Tpe2, Cow::Borrowed("Album Artist")))];
+
+        let meta = Metadata::from_id3_frames(frames);
+        assert!(!meta.compilation);
+        assert_eq!(meta.artist.as_deref(), Some("Album Artist"));
+    }
+
+    #[test]
+    fn id3v1_fills_in_missing_fields() {
+        let mut data = vec![0xFF, 0xFB, 0x90, 0x64];
+        data.extend_from_slice(b"TAG");
+        for (text, len) in [
+            (&b"Title"[..], 30),
+            (b"Artist", 30),
+            (b"Album", 30),
+            (b"1999", 4),
+        ] {
+            let mut field = text.to_vec();
+            field.resize(len, 0);
+            data.extend(field);
+        }
+        data.extend_from_slice(&[0u8; 29]);
+        data.extend_from_slice(&[7, 12]);
+
+        let mut buffer = Vec::new();
+        let meta = Metadata::from_bytes(&mut buffer, &data, false).unwrap();
+
+        assert_eq!(meta.name.as_deref(), Some("Title"));
+        assert_eq!(meta.artist.as_deref(), Some("Artist"));
+        assert_eq!(meta.album.as_deref(), Some("Album"));
+        assert_eq!(meta.year, Some(1999));
+        assert_eq!(meta.track_number, Some(7));
+    }
+
+    #[test]
+    fn untagged_mp3_returns_error() {
+        let mut buffer = Vec::new();
+        let data = [0u8; 256];
+
+        let err = SupportedFormats::ID3
+            .parse(&mut buffer, &mut Cursor::new(&data), false)
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidId3Signature));
+    }
+
+    #[test]
+    fn format_is_sniffed_regardless_of_extension() {
+        // A FLAC file with nothing but an empty Vorbis comment, named as an MP3
+        let mut data = b"fLaC".to_vec();
+        data.extend_from_slice(&[0x84, 0, 0, 8]);
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+
+        let path = std::env::temp_dir().join("metadata_audio_sniff_test.mp3");
+        std::fs::write(&path, &data).unwrap();
+
+        let mut buffer = Vec::new();
+        let meta = Metadata::from_file(&mut buffer, &path, false);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(meta.is_ok());
+    }
+
     #[test]
     fn invalid_flac_signature_returns_error() {
         let mut buffer = Vec::new();
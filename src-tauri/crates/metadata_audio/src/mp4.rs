@@ -0,0 +1,896 @@
+use std::{
+    borrow::Cow,
+    io::{Read, Seek, SeekFrom},
+};
+
+use crate::{
+    Error, Result, id3v1,
+    picture::{Picture, PictureType},
+    properties::{Codec, StreamProperties},
+    read_into_buffer_unchecked, slice_at,
+    tag_map::{TagMap, TagValue},
+    u32_from_bytes_be,
+};
+
+/// Size of the type indicator and locale in front of an item's `data` payload
+const DATA_PREFIX_SIZE: u64 = 8;
+
+/// Real files nest atoms a handful of levels deep, anything past this is skipped
+const MAX_DEPTH: u8 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AtomId {
+    /// `mvhd`, duration of the whole movie
+    Mvhd,
+    /// `mdhd`, duration of a single track
+    Mdhd,
+    /// `stsd`, describes the codec of a track
+    Stsd,
+    /// `©nam`
+    Title,
+    /// `©ART`
+    Artist,
+    /// `aART`
+    AlbumArtist,
+    /// `©alb`
+    Album,
+    /// `©wrt`
+    Composer,
+    /// `cpil`, a single byte flag
+    Compilation,
+    /// `trkn`
+    TrackNumber,
+    /// `disk`
+    DiscNumber,
+    /// `©day`
+    Date,
+    /// `covr`
+    Cover,
+    /// `gnre`, an `ID3v1` genre index plus one
+    Genre,
+    /// `©gen`
+    CustomGenre,
+    Unknown,
+}
+
+impl AtomId {
+    fn from_item_bytes(id: &[u8; 4]) -> AtomId {
+        match id {
+            b"\xA9nam" => AtomId::Title,
+            b"\xA9ART" => AtomId::Artist,
+            b"aART" => AtomId::AlbumArtist,
+            b"\xA9alb" => AtomId::Album,
+            b"\xA9wrt" => AtomId::Composer,
+            b"cpil" => AtomId::Compilation,
+            b"trkn" => AtomId::TrackNumber,
+            b"disk" => AtomId::DiscNumber,
+            b"\xA9day" => AtomId::Date,
+            b"covr" => AtomId::Cover,
+            b"gnre" => AtomId::Genre,
+            b"\xA9gen" => AtomId::CustomGenre,
+            _ => AtomId::Unknown,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Atom<'a> {
+    MovieDuration(f32),
+    MediaDuration(f32),
+    Text((AtomId, &'a str)),
+    TrackNumber(u32),
+    DiscNumber(u32),
+    Compilation(bool),
+    Year(u16),
+    Genre(&'static str),
+    Picture(&'a [u8]),
+    Stream(StreamProperties),
+    Unknown,
+}
+
+#[derive(Debug)]
+pub struct Mp4Tag {
+    pub atoms: Vec<AtomHeader>,
+
+    /// Size of the `mdat` atoms, which hold the audio
+    pub media_size: u64,
+}
+
+#[derive(Debug)]
+pub struct AtomHeader {
+    pub atom_id: AtomId,
+    pub start: u32,
+    pub length: u32,
+}
+
+/// The name of an atom and where its body ends in the stream
+struct RawAtom {
+    name: [u8; 4],
+    end: u64,
+}
+
+impl RawAtom {
+    fn parse<R: Read + Seek>(reader: &mut R, parent_end: u64) -> Result<RawAtom> {
+        let start = reader.stream_position()?;
+
+        let mut buf = [0u8; 8];
+        reader.read_exact(&mut buf)?;
+
+        let name = [buf[4], buf[5], buf[6], buf[7]];
+        let end = match u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) {
+            // The atom runs until the end of its parent
+            0 => parent_end,
+            // The real size follows as a 64-bit integer
+            1 => {
+                let mut size = [0u8; 8];
+                reader.read_exact(&mut size)?;
+                start
+                    .checked_add(u64::from_be_bytes(size))
+                    .ok_or(Error::InvalidAtomSize)?
+            }
+            size => start + u64::from(size),
+        };
+
+        if end > parent_end || end < reader.stream_position()? {
+            return Err(Error::InvalidAtomSize);
+        }
+
+        Ok(RawAtom { name, end })
+    }
+}
+
+impl<'a> Atom<'a> {
+    pub fn parse_by_id(atom_id: AtomId, data: &'a [u8]) -> Result<Atom<'a>> {
+        let atom = match atom_id {
+            AtomId::Mvhd => Self::header_duration(data).map_or(Atom::Unknown, Atom::MovieDuration),
+            AtomId::Mdhd => Self::header_duration(data).map_or(Atom::Unknown, Atom::MediaDuration),
+            AtomId::Title
+            | AtomId::Artist
+            | AtomId::AlbumArtist
+            | AtomId::Album
+            | AtomId::Composer
+            | AtomId::CustomGenre => Atom::Text((atom_id, std::str::from_utf8(data)?.trim())),
+            // Both are reserved(2), number(2), total(2)
+            AtomId::TrackNumber | AtomId::DiscNumber => match data.get(2..4) {
+                Some(&[high, low]) if atom_id == AtomId::TrackNumber => {
+                    Atom::TrackNumber(u32::from(u16::from_be_bytes([high, low])))
+                }
+                Some(&[high, low]) => Atom::DiscNumber(u32::from(u16::from_be_bytes([high, low]))),
+                _ => Atom::Unknown,
+            },
+            AtomId::Date => std::str::from_utf8(data)?
+                .get(0..4)
+                .and_then(|year| year.parse().ok())
+                .map_or(Atom::Unknown, Atom::Year),
+            AtomId::Genre => match data {
+                &[high, low] => u16::from_be_bytes([high, low])
+                    .checked_sub(1)
+                    .and_then(|index| id3v1::GENRES.get(index as usize).copied())
+                    .map_or(Atom::Unknown, Atom::Genre),
+                _ => Atom::Unknown,
+            },
+            AtomId::Compilation => match data.first() {
+                Some(&flag) => Atom::Compilation(flag != 0),
+                None => Atom::Unknown,
+            },
+            AtomId::Cover => Atom::Picture(data),
+            AtomId::Stsd => Self::sample_entry(data).map_or(Atom::Unknown, Atom::Stream),
+            AtomId::Unknown => Atom::Unknown,
+        };
+
+        Ok(atom)
+    }
+
+    /// The first sample entry of `stsd`, an audio sample entry is laid out as size(4),
+    /// format(4), reserved(6), data reference index(2), version(2), revision(2), vendor(4),
+    /// channels(2), sample size(2), compression ID(2), packet size(2) & a 16.16 sample rate
+    fn sample_entry(data: &[u8]) -> Option<StreamProperties> {
+        let entry = data.get(8..)?;
+
+        let codec = match entry.get(4..8)? {
+            b"mp4a" => Codec::Aac,
+            b"alac" => Codec::Alac,
+            b"fLaC" => Codec::Flac,
+            b"Opus" => Codec::Opus,
+            b".mp3" => Codec::Mp3,
+            _ => Codec::Unknown,
+        };
+
+        let channels = u16::from_be_bytes(entry.get(24..26)?.try_into().ok()?);
+        let sample_size = u16::from_be_bytes(entry.get(26..28)?.try_into().ok()?);
+        let sample_rate = u16::from_be_bytes(entry.get(32..34)?.try_into().ok()?);
+
+        let mut properties = StreamProperties {
+            codec,
+            sample_rate: u32::from(sample_rate),
+            bit_depth: codec.is_lossless().then_some(sample_size as u8),
+            channels: channels as u8,
+            bitrate: 0,
+        };
+
+        // The ALAC config has room for sample rates above 65535 Hz, and the real bit depth
+        if codec == Codec::Alac
+            && let Some(config) = entry.get(36..72)
+            && &config[4..8] == b"alac"
+        {
+            properties.bit_depth = Some(config[17]);
+            properties.channels = config[21];
+            properties.sample_rate = u32::from_be_bytes(config[32..36].try_into().ok()?);
+        }
+
+        Some(properties)
+    }
+
+    /// `mvhd` and `mdhd` share the layout up to the duration
+    fn header_duration(data: &[u8]) -> Option<f32> {
+        let (timescale, duration) = match data.first()? {
+            0 => (
+                u32::from_be_bytes(data.get(12..16)?.try_into().ok()?),
+                u64::from(u32::from_be_bytes(data.get(16..20)?.try_into().ok()?)),
+            ),
+            1 => (
+                u32::from_be_bytes(data.get(20..24)?.try_into().ok()?),
+                u64::from_be_bytes(data.get(24..32)?.try_into().ok()?),
+            ),
+            _ => return None,
+        };
+
+        (timescale > 0 && duration > 0).then(|| duration as f32 / timescale as f32)
+    }
+}
+
+pub struct Mp4 {}
+
+impl Mp4 {
+    pub fn read_all_atoms<R: Read + Seek>(
+        block_buffer: &mut Vec<u8>,
+        reader: &mut R,
+        skip_picture: bool,
+    ) -> Result<Mp4Tag> {
+        let stream_end = Self::check_signature(reader)?;
+
+        let mut headers = Vec::with_capacity(8);
+        let mut media_size = 0;
+
+        // moov is often placed after the audio data, so the whole top level is walked
+        while reader.stream_position()? + 8 <= stream_end {
+            let atom = RawAtom::parse(reader, stream_end)?;
+
+            match &atom.name {
+                b"moov" => {
+                    Self::read_children(
+                        block_buffer,
+                        reader,
+                        &atom,
+                        skip_picture,
+                        &mut headers,
+                        0,
+                    )?;
+                }
+                b"mdat" => media_size += atom.end - reader.stream_position()?,
+                _ => {}
+            }
+
+            reader.seek(SeekFrom::Start(atom.end))?;
+        }
+
+        Ok(Mp4Tag {
+            atoms: headers,
+            media_size,
+        })
+    }
+
+    /// Reads the body of `moov.udta.meta.ilst`, returning where it starts in the buffer and
+    /// its length, or `None` if the file has no item list
+    pub fn read_ilst<R: Read + Seek>(
+        block_buffer: &mut Vec<u8>,
+        reader: &mut R,
+    ) -> Result<Option<(u32, u32)>> {
+        let stream_end = Self::check_signature(reader)?;
+        let stream = RawAtom {
+            name: [0; 4],
+            end: stream_end,
+        };
+
+        let Some(moov) = Self::find_child(reader, &stream, b"moov")? else {
+            return Ok(None);
+        };
+        let Some(udta) = Self::find_child(reader, &moov, b"udta")? else {
+            return Ok(None);
+        };
+        let Some(meta) = Self::find_child(reader, &udta, b"meta")? else {
+            return Ok(None);
+        };
+
+        let mut version = [0u8; 4];
+        reader.read_exact(&mut version)?;
+        if version != [0; 4] {
+            reader.seek_relative(-4)?;
+        }
+
+        let Some(ilst) = Self::find_child(reader, &meta, b"ilst")? else {
+            return Ok(None);
+        };
+
+        let start = block_buffer.len() as u32;
+        let length = ilst.end - reader.stream_position()?;
+        read_into_buffer_unchecked(reader, block_buffer, length as usize)?;
+
+        Ok(Some((start, length as u32)))
+    }
+
+    /// Every ISO-BMFF file starts with a ftyp atom, returns where the stream ends
+    fn check_signature<R: Read + Seek>(reader: &mut R) -> Result<u64> {
+        let stream_start = reader.stream_position()?;
+        let stream_end = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(stream_start))?;
+
+        let mut signature = [0u8; 8];
+        reader.read_exact(&mut signature)?;
+        if &signature[4..8] != b"ftyp" {
+            return Err(Error::InvalidMp4Signature);
+        }
+        reader.seek(SeekFrom::Start(stream_start))?;
+
+        Ok(stream_end)
+    }
+
+    /// Walks the children of `parent`, leaving the reader at the body of the first `name`
+    fn find_child<R: Read + Seek>(
+        reader: &mut R,
+        parent: &RawAtom,
+        name: &[u8; 4],
+    ) -> Result<Option<RawAtom>> {
+        while reader.stream_position()? + 8 <= parent.end {
+            let atom = RawAtom::parse(reader, parent.end)?;
+            if &atom.name == name {
+                return Ok(Some(atom));
+            }
+
+            reader.seek(SeekFrom::Start(atom.end))?;
+        }
+
+        Ok(None)
+    }
+
+    fn read_children<R: Read + Seek>(
+        block_buffer: &mut Vec<u8>,
+        reader: &mut R,
+        parent: &RawAtom,
+        skip_picture: bool,
+        headers: &mut Vec<AtomHeader>,
+        depth: u8,
+    ) -> Result<()> {
+        if depth >= MAX_DEPTH {
+            return Ok(());
+        }
+
+        while reader.stream_position()? + 8 <= parent.end {
+            let atom = RawAtom::parse(reader, parent.end)?;
+
+            match &atom.name {
+                b"trak" | b"mdia" | b"minf" | b"stbl" | b"udta" | b"ilst" => {
+                    Self::read_children(
+                        block_buffer,
+                        reader,
+                        &atom,
+                        skip_picture,
+                        headers,
+                        depth + 1,
+                    )?;
+                }
+                b"meta" => {
+                    // iTunes writes meta as a full box, QuickTime doesn't
+                    let mut version = [0u8; 4];
+                    reader.read_exact(&mut version)?;
+                    if version != [0; 4] {
+                        reader.seek_relative(-4)?;
+                    }
+
+                    Self::read_children(
+                        block_buffer,
+                        reader,
+                        &atom,
+                        skip_picture,
+                        headers,
+                        depth + 1,
+                    )?;
+                }
+                b"mvhd" | b"mdhd" | b"stsd" => {
+                    let atom_id = match &atom.name {
+                        b"mvhd" => AtomId::Mvhd,
+                        b"mdhd" => AtomId::Mdhd,
+                        _ => AtomId::Stsd,
+                    };
+
+                    let length = atom.end - reader.stream_position()?;
+                    Self::push_header(block_buffer, reader, atom_id, length, headers)?;
+                }
+                name if &parent.name == b"ilst" => {
+                    let atom_id = AtomId::from_item_bytes(name);
+
+                    if atom_id != AtomId::Unknown && !(skip_picture && atom_id == AtomId::Cover) {
+                        Self::read_item_data(block_buffer, reader, &atom, atom_id, headers)?;
+                    }
+                }
+                _ => {}
+            }
+
+            reader.seek(SeekFrom::Start(atom.end))?;
+        }
+
+        Ok(())
+    }
+
+    /// An item stores its value in a `data` child, only the first one is used
+    fn read_item_data<R: Read + Seek>(
+        block_buffer: &mut Vec<u8>,
+        reader: &mut R,
+        item: &RawAtom,
+        atom_id: AtomId,
+        headers: &mut Vec<AtomHeader>,
+    ) -> Result<()> {
+        while reader.stream_position()? + 8 <= item.end {
+            let atom = RawAtom::parse(reader, item.end)?;
+
+            if &atom.name == b"data" {
+                let length = atom
+                    .end
+                    .checked_sub(reader.stream_position()? + DATA_PREFIX_SIZE)
+                    .ok_or(Error::InvalidAtomSize)?;
+
+                reader.seek_relative(DATA_PREFIX_SIZE as i64)?;
+                return Self::push_header(block_buffer, reader, atom_id, length, headers);
+            }
+
+            reader.seek(SeekFrom::Start(atom.end))?;
+        }
+
+        Ok(())
+    }
+
+    fn push_header<R: Read>(
+        block_buffer: &mut Vec<u8>,
+        reader: &mut R,
+        atom_id: AtomId,
+        length: u64,
+        headers: &mut Vec<AtomHeader>,
+    ) -> Result<()> {
+        let start = block_buffer.len() as u32;
+        read_into_buffer_unchecked(reader, block_buffer, length as usize)?;
+
+        headers.push(AtomHeader {
+            atom_id,
+            start,
+            length: length as u32,
+        });
+
+        Ok(())
+    }
+}
+
+/// Add every item of an `ilst` body to a `TagMap`.
+///
+/// Freeform `----` items are keyed by their name, and every `data` child of an item is
+/// kept, as some taggers write multiple values that way.
+pub(crate) fn push_items<'a>(tags: &mut TagMap<'a>, ilst: &'a [u8]) {
+    for (name, body) in child_atoms(ilst) {
+        let mut key = item_key(&name);
+
+        for (child_name, child) in child_atoms(body) {
+            match &child_name {
+                // Both are a version(4) followed by the text
+                b"name" => {
+                    if let Some(name) = child.get(4..) {
+                        key = String::from_utf8_lossy(name).into_owned().into();
+                    }
+                }
+                b"data" => {
+                    let Some((kind, value)) = child
+                        .split_at_checked(DATA_PREFIX_SIZE as usize)
+                        .map(|(prefix, value)| {
+                            (
+                                u32::from_be_bytes([0, prefix[1], prefix[2], prefix[3]]),
+                                value,
+                            )
+                        })
+                    else {
+                        continue;
+                    };
+
+                    push_data(tags, &name, key.clone(), kind, value);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// `data` is type(4), locale(4) and the value, where the type is one of the well-known types
+fn push_data<'a>(
+    tags: &mut TagMap<'a>,
+    name: &[u8; 4],
+    key: Cow<'a, str>,
+    kind: u32,
+    value: &'a [u8],
+) {
+    let value = match (name, kind) {
+        // Both are reserved(2), number(2), total(2)
+        (b"trkn" | b"disk", 0) if value.len() >= 6 => {
+            let number = u16::from_be_bytes([value[2], value[3]]);
+            let total = u16::from_be_bytes([value[4], value[5]]);
+
+            tags.push(key, TagValue::Number(i64::from(number)));
+            if total > 0 {
+                let total_key = if name == b"trkn" {
+                    "TRACKTOTAL"
+                } else {
+                    "DISCTOTAL"
+                };
+                tags.push(total_key, TagValue::Number(i64::from(total)));
+            }
+            return;
+        }
+        (b"gnre", _) => match value {
+            &[high, low] => u16::from_be_bytes([high, low])
+                .checked_sub(1)
+                .and_then(|index| id3v1::GENRES.get(index as usize))
+                .map_or(TagValue::Binary(value), |&genre| {
+                    TagValue::Text(Cow::Borrowed(genre))
+                }),
+            _ => TagValue::Binary(value),
+        },
+        (_, 1) => std::str::from_utf8(value).map_or(TagValue::Binary(value), |text| {
+            TagValue::Text(Cow::Borrowed(text.trim()))
+        }),
+        (_, 2) => decode_utf16_be(value).map_or(TagValue::Binary(value), |text| {
+            TagValue::Text(Cow::Owned(text))
+        }),
+        (_, 13 | 14 | 27) => TagValue::Picture(Picture::from_image(PictureType::CoverFront, value)),
+        (_, 21) => be_integer(value, true).map_or(TagValue::Binary(value), TagValue::Number),
+        (_, 22) => be_integer(value, false).map_or(TagValue::Binary(value), TagValue::Number),
+        _ => TagValue::Binary(value),
+    };
+
+    tags.push(key, value);
+}
+
+/// A big-endian integer of 1 to 8 bytes
+fn be_integer(bytes: &[u8], signed: bool) -> Option<i64> {
+    if bytes.is_empty() || bytes.len() > 8 {
+        return None;
+    }
+
+    let unsigned = bytes.iter().fold(0u64, |acc, &b| (acc << 8) | u64::from(b));
+    if !signed {
+        return i64::try_from(unsigned).ok();
+    }
+
+    // Sign extend from the top bit of the first byte
+    let shift = 64 - bytes.len() * 8;
+    Some(((unsigned << shift) as i64) >> shift)
+}
+
+fn decode_utf16_be(bytes: &[u8]) -> Option<String> {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]));
+
+    char::decode_utf16(units)
+        .collect::<std::result::Result<String, _>>()
+        .ok()
+}
+
+/// The atoms directly inside a body held in memory, stopping at the first one that doesn't fit
+fn child_atoms(mut bytes: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    std::iter::from_fn(move || {
+        let mut i = 0;
+        let size = u32_from_bytes_be(bytes, &mut i).ok()? as usize;
+        let name = slice_at(bytes, i, 4).ok()?;
+        let name = [name[0], name[1], name[2], name[3]];
+
+        let body = slice_at(bytes, 8, size.checked_sub(8)?).ok()?;
+        bytes = &bytes[size..];
+
+        Some((name, body))
+    })
+}
+
+/// The Vorbis comment name of an item, unknown items keep their atom name
+fn item_key(name: &[u8; 4]) -> Cow<'static, str> {
+    let key = match name {
+        b"\xA9nam" => "TITLE",
+        b"\xA9ART" => "ARTIST",
+        b"aART" => "ALBUMARTIST",
+        b"\xA9alb" => "ALBUM",
+        b"\xA9wrt" => "COMPOSER",
+        b"cpil" => "COMPILATION",
+        b"trkn" => "TRACKNUMBER",
+        b"disk" => "DISCNUMBER",
+        b"\xA9day" => "DATE",
+        b"covr" => "PICTURE",
+        b"gnre" | b"\xA9gen" => "GENRE",
+        b"\xA9cmt" => "COMMENT",
+        b"\xA9lyr" => "LYRICS",
+        b"\xA9grp" => "GROUPING",
+        b"tmpo" => "BPM",
+        b"cprt" => "COPYRIGHT",
+        b"\xA9too" => "ENCODEDBY",
+        b"soal" => "ALBUMSORT",
+        b"soar" => "ARTISTSORT",
+        b"sonm" => "TITLESORT",
+        b"soaa" => "ALBUMARTISTSORT",
+        b"soco" => "COMPOSERSORT",
+        b"desc" => "DESCRIPTION",
+        // Every byte of an atom name is Latin-1, as in ©
+        _ => return Cow::Owned(name.iter().map(|&b| b as char).collect()),
+    };
+
+    Cow::Borrowed(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn atom(name: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut atom = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        atom.extend_from_slice(name);
+        atom.extend_from_slice(body);
+        atom
+    }
+
+    fn item(name: &[u8], data_type: u32, value: &[u8]) -> Vec<u8> {
+        let mut data = data_type.to_be_bytes().to_vec();
+        data.extend_from_slice(&[0u8; 4]); // locale
+        data.extend_from_slice(value);
+        atom(name, &atom(b"data", &data))
+    }
+
+    fn mvhd(timescale: u32, duration: u32) -> Vec<u8> {
+        let mut body = vec![0u8; 12];
+        body.extend_from_slice(&timescale.to_be_bytes());
+        body.extend_from_slice(&duration.to_be_bytes());
+        body.resize(100, 0);
+        atom(b"mvhd", &body)
+    }
+
+    fn file(moov_children: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = atom(b"ftyp", b"M4A \0\0\0\0");
+        data.extend(atom(b"mdat", &[0u8; 32]));
+        data.extend(atom(b"moov", &moov_children.concat()));
+        data
+    }
+
+    fn ilst(items: &[Vec<u8>]) -> Vec<u8> {
+        let mut meta = vec![0u8; 4];
+        meta.extend(atom(b"hdlr", &[0u8; 25]));
+        meta.extend(atom(b"ilst", &items.concat()));
+        atom(b"udta", &atom(b"meta", &meta))
+    }
+
+    fn read(data: &[u8], skip_picture: bool) -> (Vec<u8>, Vec<AtomHeader>) {
+        let mut buffer = Vec::new();
+        let headers = Mp4::read_all_atoms(&mut buffer, &mut Cursor::new(data), skip_picture)
+            .unwrap()
+            .atoms;
+        (buffer, headers)
+    }
+
+    fn parse<'a>(buffer: &'a [u8], headers: &[AtomHeader]) -> Vec<Atom<'a>> {
+        headers
+            .iter()
+            .map(|h| {
+                let data = &buffer[h.start as usize..(h.start + h.length) as usize];
+                Atom::parse_by_id(h.atom_id, data).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reads_ilst_items() {
+        let data = file(&[
+            mvhd(44_100, 44_100 * 200),
+            ilst(&[
+                item(b"\xA9nam", 1, b"Track"),
+                item(b"\xA9ART", 1, b"Artist"),
+                item(b"aART", 1, b"Album Artist"),
+                item(b"\xA9alb", 1, b"Album"),
+                item(b"trkn", 0, &[0, 0, 0, 4, 0, 12, 0, 0]),
+                item(b"disk", 0, &[0, 0, 0, 2, 0, 2]),
+                item(b"\xA9day", 1, b"2011-05-03T07:00:00Z"),
+                item(b"gnre", 0, &[0, 18]),
+                item(b"covr", 13, b"\xFF\xD8jpeg"),
+                item(b"----", 1, b"ignored"),
+                item(b"cpil", 21, &[1]),
+            ]),
+        ]);
+
+        let (buffer, headers) = read(&data, false);
+        let atoms = parse(&buffer, &headers);
+
+        assert!(matches!(atoms[0], Atom::MovieDuration(d) if (d - 200.0).abs() < 0.001));
+        assert!(matches!(atoms[1], Atom::Text((AtomId::Title, "Track"))));
+        assert!(matches!(atoms[2], Atom::Text((AtomId::Artist, "Artist"))));
+        assert!(matches!(
+            atoms[3],
+            Atom::Text((AtomId::AlbumArtist, "Album Artist"))
+        ));
+        assert!(matches!(atoms[4], Atom::Text((AtomId::Album, "Album"))));
+        assert!(matches!(atoms[5], Atom::TrackNumber(4)));
+        assert!(matches!(atoms[6], Atom::DiscNumber(2)));
+        assert!(matches!(atoms[7], Atom::Year(2011)));
+        assert!(matches!(atoms[8], Atom::Genre("Rock")));
+        assert!(matches!(atoms[9], Atom::Picture(b"\xFF\xD8jpeg")));
+        assert!(matches!(atoms[10], Atom::Compilation(true)));
+        assert_eq!(atoms.len(), 11);
+    }
+
+    #[test]
+    fn ilst_items_are_kept_for_tag_map() {
+        let mut freeform = atom(b"mean", b"\0\0\0\0com.apple.iTunes");
+        freeform.extend(atom(b"name", b"\0\0\0\0replaygain_track_gain"));
+        freeform.extend(atom(b"data", b"\0\0\0\x01\0\0\0\0-6.5 dB"));
+
+        let data = file(&[ilst(&[
+            item(b"\xA9nam", 1, b"Track"),
+            item(b"trkn", 0, &[0, 0, 0, 3, 0, 12, 0, 0]),
+            item(b"tmpo", 21, &[0, 120]),
+            atom(b"----", &freeform),
+            item(b"xyz1", 0, b"\x01\x02"),
+        ])]);
+
+        let mut buffer = Vec::new();
+        let tags = TagMap::from_bytes(&mut buffer, &data).unwrap();
+
+        assert_eq!(
+            tags.into_iter().collect::<Vec<_>>(),
+            [
+                (
+                    Cow::Borrowed("TITLE"),
+                    TagValue::Text(Cow::Borrowed("Track"))
+                ),
+                (Cow::Borrowed("TRACKNUMBER"), TagValue::Number(3)),
+                (Cow::Borrowed("TRACKTOTAL"), TagValue::Number(12)),
+                (Cow::Borrowed("BPM"), TagValue::Number(120)),
+                (
+                    Cow::Borrowed("REPLAYGAIN_TRACK_GAIN"),
+                    TagValue::Text(Cow::Borrowed("-6.5 dB"))
+                ),
+                (Cow::Borrowed("XYZ1"), TagValue::Binary(b"\x01\x02")),
+            ]
+        );
+    }
+
+    #[test]
+    fn cover_is_skipped() {
+        let data = file(&[ilst(&[item(b"covr", 13, &[0xFF; 64])])]);
+        let (buffer, headers) = read(&data, true);
+
+        assert!(headers.is_empty());
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn mdhd_duration_is_read_from_track() {
+        let mut mdhd = vec![1u8, 0, 0, 0];
+        mdhd.extend_from_slice(&[0u8; 16]);
+        mdhd.extend_from_slice(&48_000u32.to_be_bytes());
+        mdhd.extend_from_slice(&(48_000u64 * 90).to_be_bytes());
+        mdhd.extend_from_slice(&[0u8; 4]);
+
+        let trak = atom(b"trak", &atom(b"mdia", &atom(b"mdhd", &mdhd)));
+        let (buffer, headers) = read(&file(&[trak]), false);
+        let atoms = parse(&buffer, &headers);
+
+        assert!(matches!(atoms[0], Atom::MediaDuration(d) if (d - 90.0).abs() < 0.001));
+    }
+
+    fn stsd(format: &[u8; 4], sample_size: u16, sample_rate: u16, extra: &[u8]) -> Vec<u8> {
+        let mut entry = format.to_vec();
+        entry.extend_from_slice(&[0u8; 16]);
+        entry.extend_from_slice(&2u16.to_be_bytes());
+        entry.extend_from_slice(&sample_size.to_be_bytes());
+        entry.extend_from_slice(&[0u8; 4]);
+        entry.extend_from_slice(&sample_rate.to_be_bytes());
+        entry.extend_from_slice(&[0u8; 2]);
+        entry.extend_from_slice(extra);
+
+        let mut body = vec![0u8; 4];
+        body.extend_from_slice(&1u32.to_be_bytes());
+        body.extend_from_slice(&((entry.len() + 4) as u32).to_be_bytes());
+        body.extend(entry);
+
+        let stbl = atom(b"stbl", &atom(b"stsd", &body));
+        atom(b"trak", &atom(b"mdia", &atom(b"minf", &stbl)))
+    }
+
+    #[test]
+    fn sample_entry_is_read() {
+        let (buffer, headers) = read(&file(&[stsd(b"mp4a", 16, 44_100, &[])]), false);
+        let atoms = parse(&buffer, &headers);
+
+        let Atom::Stream(properties) = atoms[0] else {
+            panic!("stsd wasn't parsed as stream properties");
+        };
+        assert_eq!(properties.codec, Codec::Aac);
+        assert_eq!(properties.sample_rate, 44_100);
+        assert_eq!(properties.channels, 2);
+        assert_eq!(properties.bit_depth, None);
+
+        // 24-bit 96 kHz ALAC, where the sample rate doesn't fit the sample entry
+        let mut config = 0u32.to_be_bytes().to_vec();
+        config.extend_from_slice(&[0u8; 4]);
+        config.extend_from_slice(&[0, 24, 40, 10, 14, 2]);
+        config.extend_from_slice(&[0u8; 10]);
+        config.extend_from_slice(&96_000u32.to_be_bytes());
+
+        let alac = stsd(b"alac", 16, 0, &atom(b"alac", &config));
+        let (buffer, headers) = read(&file(&[alac]), false);
+        let atoms = parse(&buffer, &headers);
+
+        let Atom::Stream(properties) = atoms[0] else {
+            panic!("stsd wasn't parsed as stream properties");
+        };
+        assert_eq!(properties.codec, Codec::Alac);
+        assert_eq!(properties.sample_rate, 96_000);
+        assert_eq!(properties.bit_depth, Some(24));
+    }
+
+    #[test]
+    fn quicktime_meta_without_version() {
+        let meta = atom(b"meta", &atom(b"ilst", &item(b"\xA9nam", 1, b"Track")));
+        let (buffer, headers) = read(&file(&[atom(b"udta", &meta)]), false);
+        let atoms = parse(&buffer, &headers);
+
+        assert!(matches!(atoms[0], Atom::Text((AtomId::Title, "Track"))));
+    }
+
+    #[test]
+    fn oversized_atom_returns_error() {
+        let mut data = file(&[mvhd(1, 1)]);
+        let moov_start = data.len() - (8 + 108);
+        data[moov_start + 8..moov_start + 12].copy_from_slice(&1000u32.to_be_bytes());
+
+        let mut buffer = Vec::new();
+        let err = Mp4::read_all_atoms(&mut buffer, &mut Cursor::new(&data), false).unwrap_err();
+
+        assert!(matches!(err, Error::InvalidAtomSize));
+    }
+
+    #[test]
+    fn overflowing_64_bit_size_returns_error() {
+        let mut data = atom(b"ftyp", b"M4A \0\0\0\0");
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(b"moov");
+        data.extend_from_slice(&u64::MAX.to_be_bytes());
+
+        let mut buffer = Vec::new();
+        let err = Mp4::read_all_atoms(&mut buffer, &mut Cursor::new(&data), false).unwrap_err();
+
+        assert!(matches!(err, Error::InvalidAtomSize));
+    }
+
+    #[test]
+    fn deeply_nested_atoms_are_skipped() {
+        let mut nested = ilst(&[item(b"\xA9nam", 1, b"Track")]);
+        for _ in 0..1000 {
+            nested = atom(b"udta", &nested);
+        }
+
+        let (_, headers) = read(&file(&[nested]), false);
+
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn invalid_signature_returns_error() {
+        let mut buffer = Vec::new();
+        let err =
+            Mp4::read_all_atoms(&mut buffer, &mut Cursor::new(&[0u8; 64]), false).unwrap_err();
+
+        assert!(matches!(err, Error::InvalidMp4Signature));
+    }
+}
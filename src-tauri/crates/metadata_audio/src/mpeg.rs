@@ -0,0 +1,406 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::{
+    Result, id3v1,
+    properties::{Codec, StreamProperties},
+};
+
+/// How far past the tag to look for the first audio frame
+const MAX_SYNC_SEARCH: u64 = 64 * 1024;
+
+const BITRATES_V1: [[u32; 15]; 3] = [
+    [
+        0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+    ],
+    [
+        0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+    ],
+    [
+        0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ],
+];
+
+const BITRATES_V2: [[u32; 15]; 3] = [
+    [
+        0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+    ],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MpegVersion {
+    V1,
+    V2,
+    V25,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Layer {
+    Layer1,
+    Layer2,
+    Layer3,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FrameHeader {
+    version: MpegVersion,
+    layer: Layer,
+
+    /// Bitrate in kbit/s
+    bitrate: u32,
+    sample_rate: u32,
+    padding: bool,
+    mono: bool,
+}
+
+impl FrameHeader {
+    fn parse(bytes: &[u8]) -> Option<FrameHeader> {
+        let header = u32::from_be_bytes(bytes.get(0..4)?.try_into().ok()?);
+
+        if header >> 21 != 0x7FF {
+            return None;
+        }
+
+        let version = match (header >> 19) & 0b11 {
+            0b00 => MpegVersion::V25,
+            0b10 => MpegVersion::V2,
+            0b11 => MpegVersion::V1,
+            _ => return None,
+        };
+
+        let layer = match (header >> 17) & 0b11 {
+            0b01 => Layer::Layer3,
+            0b10 => Layer::Layer2,
+            0b11 => Layer::Layer1,
+            _ => return None,
+        };
+
+        let bitrate_index = ((header >> 12) & 0b1111) as usize;
+        let sample_rate_index = ((header >> 10) & 0b11) as usize;
+
+        // Free format streams (index 0) have no fixed frame length to sync on
+        if bitrate_index == 0 || bitrate_index == 15 || sample_rate_index == 3 {
+            return None;
+        }
+
+        let bitrate = match version {
+            MpegVersion::V1 => BITRATES_V1[layer as usize][bitrate_index],
+            MpegVersion::V2 | MpegVersion::V25 => BITRATES_V2[layer as usize][bitrate_index],
+        };
+
+        let sample_rate = match version {
+            MpegVersion::V1 => [44_100, 48_000, 32_000][sample_rate_index],
+            MpegVersion::V2 => [22_050, 24_000, 16_000][sample_rate_index],
+            MpegVersion::V25 => [11_025, 12_000, 8_000][sample_rate_index],
+        };
+
+        Some(FrameHeader {
+            version,
+            layer,
+            bitrate,
+            sample_rate,
+            padding: (header >> 9) & 1 != 0,
+            mono: (header >> 6) & 0b11 == 0b11,
+        })
+    }
+
+    fn samples_per_frame(&self) -> u32 {
+        match (self.layer, self.version) {
+            (Layer::Layer1, _) => 384,
+            (Layer::Layer2, _) | (Layer::Layer3, MpegVersion::V1) => 1152,
+            (Layer::Layer3, MpegVersion::V2 | MpegVersion::V25) => 576,
+        }
+    }
+
+    fn frame_len(&self) -> usize {
+        let padding = u32::from(self.padding);
+
+        let len = if self.layer == Layer::Layer1 {
+            (12 * self.bitrate * 1000 / self.sample_rate + padding) * 4
+        } else {
+            self.samples_per_frame() / 8 * self.bitrate * 1000 / self.sample_rate + padding
+        };
+
+        len as usize
+    }
+
+    /// Offset of a Xing/Info header, which sits right after the side information
+    fn xing_offset(&self) -> usize {
+        let side_info = match (self.version, self.mono) {
+            (MpegVersion::V1, false) => 32,
+            (MpegVersion::V1, true) | (MpegVersion::V2 | MpegVersion::V25, false) => 17,
+            (MpegVersion::V2 | MpegVersion::V25, true) => 9,
+        };
+
+        4 + side_info
+    }
+
+    fn duration_from_frames(&self, frames: u32) -> f32 {
+        frames as f32 * self.samples_per_frame() as f32 / self.sample_rate as f32
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MpegStream {
+    pub seconds: f32,
+
+    /// Whether the duration came from a Xing/Info or VBRI header rather than an estimate
+    pub exact: bool,
+
+    pub properties: StreamProperties,
+}
+
+/// Find the first MPEG audio frame after `audio_start` and work out the stream duration
+/// and properties.
+///
+/// VBR files are expected to have a Xing/Info or VBRI header in their first frame,
+/// otherwise the duration is estimated from the bitrate of that frame.
+pub fn read_stream<R: Read + Seek>(
+    buffer: &mut Vec<u8>,
+    reader: &mut R,
+    audio_start: u64,
+) -> Result<Option<MpegStream>> {
+    let stream_len = reader.seek(SeekFrom::End(0))?;
+    let audio_end = stream_len - trailer_len(reader, stream_len)?;
+
+    reader.seek(SeekFrom::Start(audio_start))?;
+
+    let start = buffer.len();
+    reader.by_ref().take(MAX_SYNC_SEARCH).read_to_end(buffer)?;
+    let data = &buffer[start..];
+
+    let Some((offset, header)) = find_first_frame(data) else {
+        buffer.truncate(start);
+        return Ok(None);
+    };
+
+    let frame = &data[offset..];
+    let audio_bytes = audio_end.saturating_sub(audio_start + offset as u64);
+
+    let (seconds, exact) = match read_xing(frame, &header).or_else(|| read_vbri(frame)) {
+        Some(frames) => (header.duration_from_frames(frames), true),
+        None => (
+            audio_bytes as f32 * 8.0 / (header.bitrate as f32 * 1000.0),
+            false,
+        ),
+    };
+
+    // A frame count gives an exact duration to average the bitrate over, without one the
+    // stream is taken to be CBR at the bitrate of its first frame
+    let bitrate = if exact {
+        StreamProperties::average_bitrate(audio_bytes, seconds)
+    } else {
+        header.bitrate
+    };
+
+    let stream = MpegStream {
+        seconds,
+        exact,
+        properties: StreamProperties {
+            codec: match header.layer {
+                Layer::Layer1 => Codec::Mp1,
+                Layer::Layer2 => Codec::Mp2,
+                Layer::Layer3 => Codec::Mp3,
+            },
+            sample_rate: header.sample_rate,
+            bit_depth: None,
+            channels: if header.mono { 1 } else { 2 },
+            bitrate,
+        },
+    };
+
+    buffer.truncate(start);
+
+    Ok(Some(stream))
+}
+
+/// Length of an `ID3v1` trailer at the end of the stream, if there is one
+fn trailer_len<R: Read + Seek>(reader: &mut R, stream_len: u64) -> Result<u64> {
+    if stream_len < id3v1::TAG_SIZE {
+        return Ok(0);
+    }
+
+    reader.seek(SeekFrom::Start(stream_len - id3v1::TAG_SIZE))?;
+
+    let mut signature = [0u8; 3];
+    reader.read_exact(&mut signature)?;
+
+    Ok(if &signature == b"TAG" {
+        id3v1::TAG_SIZE
+    } else {
+        0
+    })
+}
+
+/// Search for a frame sync, only accepting it if the frame after it is valid too.
+fn find_first_frame(data: &[u8]) -> Option<(usize, FrameHeader)> {
+    let mut i = 0;
+
+    while i + 4 <= data.len() {
+        if data[i] != 0xFF {
+            i += 1;
+            continue;
+        }
+
+        if let Some(header) = FrameHeader::parse(&data[i..]) {
+            let next = i + header.frame_len();
+
+            // A false sync is unlikely to be followed by a matching frame header,
+            // unless the frame runs right up to the end of what was read.
+            let confirmed = match data.get(next..next + 4) {
+                Some(bytes) => FrameHeader::parse(bytes).is_some_and(|next_header| {
+                    next_header.version == header.version
+                        && next_header.layer == header.layer
+                        && next_header.sample_rate == header.sample_rate
+                }),
+                None => next >= data.len(),
+            };
+
+            if confirmed {
+                return Some((i, header));
+            }
+        }
+
+        i += 1;
+    }
+
+    None
+}
+
+fn read_xing(frame: &[u8], header: &FrameHeader) -> Option<u32> {
+    let offset = header.xing_offset();
+    let tag = frame.get(offset..offset + 4)?;
+
+    if tag != b"Xing" && tag != b"Info" {
+        return None;
+    }
+
+    let flags = u32::from_be_bytes(frame.get(offset + 4..offset + 8)?.try_into().ok()?);
+
+    // The frame count is optional, without it the header is of no use for duration
+    if flags & 0x1 == 0 {
+        return None;
+    }
+
+    let frames = u32::from_be_bytes(frame.get(offset + 8..offset + 12)?.try_into().ok()?);
+    (frames > 0).then_some(frames)
+}
+
+fn read_vbri(frame: &[u8]) -> Option<u32> {
+    // VBRI always sits 32 bytes after the frame header
+    if frame.get(36..40)? != b"VBRI" {
+        return None;
+    }
+
+    let frames = u32::from_be_bytes(frame.get(50..54)?.try_into().ok()?);
+    (frames > 0).then_some(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// MPEG-1 Layer III, 128 kbit/s, 44.1 kHz, stereo
+    const HEADER_128K: [u8; 4] = [0xFF, 0xFB, 0x90, 0x00];
+
+    fn frame(header: [u8; 4]) -> Vec<u8> {
+        let len = FrameHeader::parse(&header).unwrap().frame_len();
+        let mut frame = header.to_vec();
+        frame.resize(len, 0);
+        frame
+    }
+
+    fn duration(data: &[u8]) -> Option<MpegStream> {
+        let mut buffer = Vec::new();
+        read_stream(&mut buffer, &mut Cursor::new(data), 0).unwrap()
+    }
+
+    #[test]
+    fn parses_frame_header() {
+        let header = FrameHeader::parse(&HEADER_128K).unwrap();
+
+        assert_eq!(header.version, MpegVersion::V1);
+        assert_eq!(header.layer, Layer::Layer3);
+        assert_eq!(header.bitrate, 128);
+        assert_eq!(header.sample_rate, 44_100);
+        assert_eq!(header.frame_len(), 417);
+        assert_eq!(header.samples_per_frame(), 1152);
+    }
+
+    #[test]
+    fn cbr_duration_is_estimated_from_bitrate() {
+        let mut data = Vec::new();
+        for _ in 0..100 {
+            data.extend(frame(HEADER_128K));
+        }
+
+        let duration = duration(&data).unwrap();
+
+        assert!(!duration.exact);
+        assert!((duration.seconds - 100.0 * 417.0 * 8.0 / 128_000.0).abs() < 0.01);
+
+        let properties = duration.properties;
+        assert_eq!(properties.codec, Codec::Mp3);
+        assert_eq!(properties.sample_rate, 44_100);
+        assert_eq!(properties.channels, 2);
+        assert_eq!(properties.bitrate, 128);
+        assert_eq!(properties.bit_depth, None);
+    }
+
+    #[test]
+    fn xing_frame_count_is_used() {
+        let mut first = frame(HEADER_128K);
+        first[36..40].copy_from_slice(b"Xing");
+        first[40..44].copy_from_slice(&1u32.to_be_bytes());
+        first[44..48].copy_from_slice(&10_000u32.to_be_bytes());
+
+        let mut data = first;
+        data.extend(frame(HEADER_128K));
+
+        let duration = duration(&data).unwrap();
+
+        assert!(duration.exact);
+        assert!((duration.seconds - 10_000.0 * 1152.0 / 44_100.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn vbri_frame_count_is_used() {
+        let mut first = frame(HEADER_128K);
+        first[36..40].copy_from_slice(b"VBRI");
+        first[50..54].copy_from_slice(&2_000u32.to_be_bytes());
+
+        let mut data = first;
+        data.extend(frame(HEADER_128K));
+
+        let duration = duration(&data).unwrap();
+
+        assert!(duration.exact);
+        assert!((duration.seconds - 2_000.0 * 1152.0 / 44_100.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn false_sync_is_skipped() {
+        // Junk that looks like a frame header, but isn't followed by another frame
+        let mut data = vec![0x00, 0xFF, 0xFB, 0x90, 0x00, 0x12, 0x34];
+        data.extend(frame(HEADER_128K));
+        data.extend(frame(HEADER_128K));
+
+        let mut buffer = Vec::new();
+        let found = find_first_frame(&data);
+        assert_eq!(found.map(|(offset, _)| offset), Some(7));
+
+        assert!(
+            read_stream(&mut buffer, &mut Cursor::new(&data), 0)
+                .unwrap()
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn no_audio_frames_returns_none() {
+        assert!(duration(&[0u8; 1024]).is_none());
+    }
+}
@@ -0,0 +1,486 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::{
+    Error, Result,
+    properties::{self, StreamProperties},
+    read_into_buffer_unchecked,
+};
+
+/// How much of the end of the stream to search for the last page
+const MAX_LAST_PAGE_SEARCH: u64 = 64 * 1024;
+
+/// Opus granule positions are always counted at 48 kHz
+const OPUS_SAMPLE_RATE: u32 = 48_000;
+
+const PAGE_HEADER_SIZE: usize = 27;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Codec {
+    Vorbis,
+    Opus,
+}
+
+#[derive(Debug)]
+struct PageHeader {
+    serial: u32,
+    segment_count: u8,
+    lacing: [u8; 255],
+}
+
+impl PageHeader {
+    fn parse<R: Read>(reader: &mut R) -> Result<PageHeader> {
+        let mut buf = [0u8; PAGE_HEADER_SIZE];
+        reader.read_exact(&mut buf)?;
+
+        if &buf[0..4] != b"OggS" || buf[4] != 0 {
+            return Err(Error::InvalidOggSignature);
+        }
+
+        let segment_count = buf[26];
+        let mut lacing = [0u8; 255];
+        reader.read_exact(&mut lacing[..segment_count as usize])?;
+
+        Ok(PageHeader {
+            serial: u32::from_le_bytes(buf[14..18].try_into().unwrap()),
+            segment_count,
+            lacing,
+        })
+    }
+
+    fn lacing(&self) -> &[u8] {
+        &self.lacing[..self.segment_count as usize]
+    }
+
+    fn body_len(&self) -> u64 {
+        self.lacing().iter().map(|&l| u64::from(l)).sum()
+    }
+}
+
+#[derive(Debug)]
+pub struct Packet {
+    pub start: u32,
+    pub length: u32,
+}
+
+#[derive(Debug)]
+pub struct OggHeaders {
+    pub codec: Codec,
+
+    /// The Vorbis comment, without the codec specific packet prefix
+    pub comment: Packet,
+
+    /// Every decoded `METADATA_BLOCK_PICTURE`, laid out like a FLAC picture block
+    pub pictures: Vec<Packet>,
+
+    pub duration: f32,
+
+    pub properties: StreamProperties,
+}
+
+pub struct Ogg {}
+
+impl Ogg {
+    pub fn read_headers<R: Read + Seek>(
+        buffer: &mut Vec<u8>,
+        reader: &mut R,
+        skip_picture: bool,
+    ) -> Result<OggHeaders> {
+        let (serial, packets) = Self::read_packets(buffer, reader, 2)?;
+        let headers_end = reader.stream_position()?;
+        let (ident, comment) = (&packets[0], &packets[1]);
+
+        let ident_data = &buffer[ident.start as usize..(ident.start + ident.length) as usize];
+        let comment_data =
+            &buffer[comment.start as usize..(comment.start + comment.length) as usize];
+
+        let (codec, sample_rate, channels, pre_skip, comment_prefix) = if ident_data
+            .starts_with(b"OpusHead")
+            && ident_data.len() >= 19
+            && comment_data.starts_with(b"OpusTags")
+        {
+            let pre_skip = u16::from_le_bytes([ident_data[10], ident_data[11]]);
+            (
+                Codec::Opus,
+                OPUS_SAMPLE_RATE,
+                ident_data[9],
+                u64::from(pre_skip),
+                8,
+            )
+        } else if ident_data.starts_with(b"\x01vorbis")
+            && ident_data.len() >= 30
+            && comment_data.starts_with(b"\x03vorbis")
+        {
+            let sample_rate = u32::from_le_bytes(ident_data[12..16].try_into().unwrap());
+            (Codec::Vorbis, sample_rate, ident_data[11], 0, 7)
+        } else {
+            return Err(Error::UnsupportedOggCodec);
+        };
+
+        let comment = Packet {
+            start: comment.start + comment_prefix,
+            length: comment.length - comment_prefix,
+        };
+
+        let pictures = if skip_picture {
+            Vec::new()
+        } else {
+            Self::decode_pictures(buffer, &comment)
+        };
+
+        let stream_len = reader.seek(SeekFrom::End(0))?;
+        let duration = match Self::last_granule_position(buffer, reader, serial)? {
+            Some(granule) if sample_rate > 0 => {
+                granule.saturating_sub(pre_skip) as f32 / sample_rate as f32
+            }
+            _ => -1.0,
+        };
+
+        let properties = StreamProperties {
+            codec: match codec {
+                Codec::Vorbis => properties::Codec::Vorbis,
+                Codec::Opus => properties::Codec::Opus,
+            },
+            sample_rate,
+            bit_depth: None,
+            channels,
+            // Everything after the comment is audio, apart from the small Vorbis setup header
+            bitrate: StreamProperties::average_bitrate(stream_len - headers_end, duration),
+        };
+
+        Ok(OggHeaders {
+            codec,
+            comment,
+            pictures,
+            duration,
+            properties,
+        })
+    }
+
+    /// Reads the first `count` packets of the first logical stream into the buffer.
+    ///
+    /// Packets can span several pages, but since only the page bodies are copied
+    /// each packet still ends up contiguous in the buffer.
+    fn read_packets<R: Read + Seek>(
+        buffer: &mut Vec<u8>,
+        reader: &mut R,
+        count: usize,
+    ) -> Result<(u32, Vec<Packet>)> {
+        let mut packets = Vec::with_capacity(count);
+        let mut packet_start = buffer.len();
+        let mut stream_serial = None;
+
+        while packets.len() < count {
+            let page = PageHeader::parse(reader)?;
+
+            // Skip pages belonging to other multiplexed streams
+            if *stream_serial.get_or_insert(page.serial) != page.serial {
+                reader.seek_relative(page.body_len() as i64)?;
+                continue;
+            }
+
+            for &lacing in page.lacing() {
+                if packets.len() == count {
+                    break;
+                }
+
+                read_into_buffer_unchecked(reader, buffer, lacing as usize)?;
+
+                // A lacing value below 255 terminates the packet
+                if lacing < 255 {
+                    packets.push(Packet {
+                        start: packet_start as u32,
+                        length: (buffer.len() - packet_start) as u32,
+                    });
+                    packet_start = buffer.len();
+                }
+            }
+        }
+
+        Ok((stream_serial.unwrap_or_default(), packets))
+    }
+
+    /// Find the granule position of the last page of the stream, which is the total
+    /// number of samples including any pre-skip.
+    fn last_granule_position<R: Read + Seek>(
+        buffer: &mut Vec<u8>,
+        reader: &mut R,
+        serial: u32,
+    ) -> Result<Option<u64>> {
+        let stream_len = reader.seek(SeekFrom::End(0))?;
+        let search_len = stream_len.min(MAX_LAST_PAGE_SEARCH);
+        reader.seek(SeekFrom::Start(stream_len - search_len))?;
+
+        let start = buffer.len();
+        read_into_buffer_unchecked(reader, buffer, search_len as usize)?;
+        let data = &buffer[start..];
+
+        let mut granule = None;
+        let mut i = data.len().saturating_sub(PAGE_HEADER_SIZE);
+        loop {
+            let page = &data[i..];
+            if page.len() >= PAGE_HEADER_SIZE
+                && page.starts_with(b"OggS")
+                && u32::from_le_bytes(page[14..18].try_into().unwrap()) == serial
+            {
+                let position = u64::from_le_bytes(page[6..14].try_into().unwrap());
+
+                // Pages where no packet finishes have a granule position of -1
+                if position != u64::MAX {
+                    granule = Some(position);
+                    break;
+                }
+            }
+
+            if i == 0 {
+                break;
+            }
+            i -= 1;
+        }
+
+        buffer.truncate(start);
+
+        Ok(granule)
+    }
+
+    /// Base64 decode every `METADATA_BLOCK_PICTURE` comment onto the end of the buffer
+    fn decode_pictures(buffer: &mut Vec<u8>, comment: &Packet) -> Vec<Packet> {
+        let values = Self::find_comments(
+            &buffer[comment.start as usize..(comment.start + comment.length) as usize],
+            b"METADATA_BLOCK_PICTURE",
+        );
+
+        values
+            .into_iter()
+            .filter_map(|(offset, length)| {
+                Self::decode_base64(buffer, comment.start as usize + offset, length)
+            })
+            .collect()
+    }
+
+    /// Base64 decode `length` bytes at `value_start` onto the end of the buffer
+    fn decode_base64(buffer: &mut Vec<u8>, value_start: usize, length: usize) -> Option<Packet> {
+        let start = buffer.len();
+        let mut acc = 0u32;
+        let mut bits = 0;
+
+        for i in value_start..value_start + length {
+            let c = buffer[i];
+            let sextet = match c {
+                b'A'..=b'Z' => c - b'A',
+                b'a'..=b'z' => c - b'a' + 26,
+                b'0'..=b'9' => c - b'0' + 52,
+                b'+' => 62,
+                b'/' => 63,
+                b'=' => break,
+                _ => {
+                    buffer.truncate(start);
+                    return None;
+                }
+            };
+
+            acc = (acc << 6) | u32::from(sextet);
+            bits += 6;
+
+            if bits >= 8 {
+                bits -= 8;
+                buffer.push((acc >> bits) as u8);
+                acc &= (1 << bits) - 1;
+            }
+        }
+
+        Some(Packet {
+            start: start as u32,
+            length: (buffer.len() - start) as u32,
+        })
+    }
+
+    /// Returns the offset and length of every value for `key` within a Vorbis comment
+    fn find_comments(bytes: &[u8], key: &[u8]) -> Vec<(usize, usize)> {
+        let read_u32 = |i: usize| -> Option<usize> {
+            Some(u32::from_le_bytes(bytes.get(i..i + 4)?.try_into().ok()?) as usize)
+        };
+
+        let mut values = Vec::new();
+        let Some(mut i) = read_u32(0).map(|vendor_length| 4 + vendor_length) else {
+            return values;
+        };
+        let Some(num_comments) = read_u32(i) else {
+            return values;
+        };
+        i += 4;
+
+        for _ in 0..num_comments {
+            let Some(length) = read_u32(i) else {
+                break;
+            };
+            i += 4;
+
+            let Some(comment) = bytes.get(i..i + length) else {
+                break;
+            };
+            if comment.len() > key.len()
+                && comment[key.len()] == b'='
+                && comment[..key.len()].eq_ignore_ascii_case(key)
+            {
+                values.push((i + key.len() + 1, length - key.len() - 1));
+            }
+
+            i += length;
+        }
+
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::picture::Picture;
+
+    const SERIAL: u32 = 0x1234;
+
+    fn page(serial: u32, granule: u64, lacing: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut page = b"OggS\0\0".to_vec();
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&serial.to_le_bytes());
+        page.extend_from_slice(&[0u8; 8]); // sequence number & checksum
+        page.push(lacing.len() as u8);
+        page.extend_from_slice(lacing);
+        page.extend_from_slice(body);
+        page
+    }
+
+    fn lacing(len: usize) -> Vec<u8> {
+        let mut lacing = vec![255; len / 255];
+        lacing.push((len % 255) as u8);
+        lacing
+    }
+
+    fn comment(comments: &[&[u8]]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&6u32.to_le_bytes());
+        data.extend_from_slice(b"vendor");
+        data.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            data.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            data.extend_from_slice(comment);
+        }
+        data
+    }
+
+    fn opus_stream(comments: &[&[u8]], pre_skip: u16, last_granule: u64) -> Vec<u8> {
+        let mut head = b"OpusHead\x01\x02".to_vec();
+        head.extend_from_slice(&pre_skip.to_le_bytes());
+        head.extend_from_slice(&48_000u32.to_le_bytes());
+        head.extend_from_slice(&[0, 0, 0]);
+
+        let mut tags = b"OpusTags".to_vec();
+        tags.extend(comment(comments));
+
+        let mut data = page(SERIAL, 0, &lacing(head.len()), &head);
+        data.extend(page(SERIAL, 0, &lacing(tags.len()), &tags));
+        data.extend(page(SERIAL, last_granule, &[10], &[0u8; 10]));
+        data
+    }
+
+    fn read(data: &[u8]) -> (Vec<u8>, OggHeaders) {
+        let mut buffer = Vec::new();
+        let headers = Ogg::read_headers(&mut buffer, &mut Cursor::new(data), false).unwrap();
+        (buffer, headers)
+    }
+
+    fn slice<'a>(buffer: &'a [u8], packet: &Packet) -> &'a [u8] {
+        &buffer[packet.start as usize..(packet.start + packet.length) as usize]
+    }
+
+    #[test]
+    fn opus_duration_subtracts_pre_skip() {
+        let data = opus_stream(&[b"TITLE=Track"], 312, 48_000 * 10 + 312);
+        let (buffer, headers) = read(&data);
+
+        assert_eq!(headers.codec, Codec::Opus);
+        assert!((headers.duration - 10.0).abs() < 0.001);
+        assert!(slice(&buffer, &headers.comment).starts_with(&6u32.to_le_bytes()));
+    }
+
+    #[test]
+    fn vorbis_duration_uses_sample_rate() {
+        let mut ident = b"\x01vorbis".to_vec();
+        ident.extend_from_slice(&0u32.to_le_bytes());
+        ident.push(2);
+        ident.extend_from_slice(&44_100u32.to_le_bytes());
+        ident.extend_from_slice(&[0u8; 14]);
+
+        let mut tags = b"\x03vorbis".to_vec();
+        tags.extend(comment(&[b"ALBUM=Album"]));
+        tags.push(1); // framing bit
+
+        let mut data = page(SERIAL, 0, &lacing(ident.len()), &ident);
+        data.extend(page(SERIAL, 0, &lacing(tags.len()), &tags));
+        data.extend(page(SERIAL, 44_100 * 3, &[1], &[0]));
+
+        let (_, headers) = read(&data);
+
+        assert_eq!(headers.codec, Codec::Vorbis);
+        assert!((headers.duration - 3.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn packets_span_pages() {
+        let title = [b"TITLE=".as_slice(), &[b'a'; 600]].concat();
+        let mut data = opus_stream(&[&title], 0, 48_000);
+
+        // Re-split the comment packet over two pages
+        let head_len = PAGE_HEADER_SIZE + 1 + 19;
+        let tags_page = data.drain(head_len..).collect::<Vec<_>>();
+        let segment_count = tags_page[26] as usize;
+        let tags_body_start = PAGE_HEADER_SIZE + segment_count;
+        let tags = &tags_page[tags_body_start..tags_page.len() - (PAGE_HEADER_SIZE + 1 + 10)];
+
+        data.extend(page(SERIAL, u64::MAX, &[255, 255], &tags[..510]));
+        data.extend(page(SERIAL, 0, &lacing(tags.len() - 510), &tags[510..]));
+        data.extend(page(SERIAL, 48_000, &[10], &[0u8; 10]));
+
+        let (buffer, headers) = read(&data);
+
+        assert_eq!(slice(&buffer, &headers.comment), &tags[8..]);
+        assert!((headers.duration - 1.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn other_streams_are_skipped() {
+        let mut data = opus_stream(&[], 0, 48_000 * 2);
+        let other = page(SERIAL + 1, 0, &[4], b"junk");
+        let head_len = PAGE_HEADER_SIZE + 1 + 19;
+        data.splice(head_len..head_len, other.iter().copied());
+
+        // A trailing page from another stream shouldn't affect the duration
+        data.extend(page(SERIAL + 1, 48_000 * 100, &[4], b"junk"));
+
+        let (_, headers) = read(&data);
+        assert!((headers.duration - 2.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn metadata_block_picture_is_decoded() {
+        // A FLAC picture block holding the bytes "hello"
+        let comment = b"metadata_block_picture=AAAAAwAAAAppbWFnZS9qcGVnAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAFaGVsbG8=";
+        let data = opus_stream(&[comment], 0, 48_000);
+
+        let (buffer, headers) = read(&data);
+        let picture = slice(&buffer, &headers.pictures[0]);
+
+        assert_eq!(headers.pictures.len(), 1);
+        assert_eq!(Picture::from_flac_bytes(picture).unwrap().data, b"hello");
+    }
+
+    #[test]
+    fn invalid_signature_returns_error() {
+        let mut buffer = Vec::new();
+        let err = Ogg::read_headers(&mut buffer, &mut Cursor::new(&[0u8; 64]), false).unwrap_err();
+
+        assert!(matches!(err, Error::InvalidOggSignature));
+    }
+}
@@ -0,0 +1,249 @@
+use std::borrow::Cow;
+
+use crate::{Error, Result, u32_from_bytes_be};
+
+/// What a picture shows, shared by `ID3v2` `APIC` frames and FLAC picture blocks
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum PictureType {
+    #[default]
+    Other = 0,
+    /// 32x32 PNG file icon
+    FileIcon = 1,
+    OtherFileIcon = 2,
+    CoverFront = 3,
+    CoverBack = 4,
+    LeafletPage = 5,
+    /// Label side of the CD or vinyl
+    Media = 6,
+    LeadArtist = 7,
+    Artist = 8,
+    Conductor = 9,
+    Band = 10,
+    Composer = 11,
+    Lyricist = 12,
+    RecordingLocation = 13,
+    DuringRecording = 14,
+    DuringPerformance = 15,
+    /// Screen capture of a movie or video
+    ScreenCapture = 16,
+    BrightColouredFish = 17,
+    Illustration = 18,
+    BandLogo = 19,
+    PublisherLogo = 20,
+}
+
+impl PictureType {
+    pub fn from_u32(value: u32) -> PictureType {
+        match value {
+            1 => PictureType::FileIcon,
+            2 => PictureType::OtherFileIcon,
+            3 => PictureType::CoverFront,
+            4 => PictureType::CoverBack,
+            5 => PictureType::LeafletPage,
+            6 => PictureType::Media,
+            7 => PictureType::LeadArtist,
+            8 => PictureType::Artist,
+            9 => PictureType::Conductor,
+            10 => PictureType::Band,
+            11 => PictureType::Composer,
+            12 => PictureType::Lyricist,
+            13 => PictureType::RecordingLocation,
+            14 => PictureType::DuringRecording,
+            15 => PictureType::DuringPerformance,
+            16 => PictureType::ScreenCapture,
+            17 => PictureType::BrightColouredFish,
+            18 => PictureType::Illustration,
+            19 => PictureType::BandLogo,
+            20 => PictureType::PublisherLogo,
+            _ => PictureType::Other,
+        }
+    }
+}
+
+/// An embedded picture, `width` and `height` are 0 when they're unknown
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Picture<'a> {
+    pub picture_type: PictureType,
+    pub mime_type: Cow<'a, str>,
+    pub description: Cow<'a, str>,
+    pub width: u32,
+    pub height: u32,
+    pub data: &'a [u8],
+}
+
+impl<'a> Picture<'a> {
+    /// A picture that only comes with its image data, as MP4 covers do
+    pub fn from_image(picture_type: PictureType, data: &'a [u8]) -> Picture<'a> {
+        let mut picture = Picture {
+            picture_type,
+            data,
+            ..Default::default()
+        };
+        picture.fill_from_image();
+
+        picture
+    }
+
+    /// Parse a FLAC picture block, which Ogg also uses for `METADATA_BLOCK_PICTURE`
+    pub fn from_flac_bytes(bytes: &'a [u8]) -> Result<Picture<'a>> {
+        let mut i = 0;
+
+        let picture_type = PictureType::from_u32(read_u32(bytes, &mut i)?);
+        let mime_type = read_string(bytes, &mut i)?;
+        let description = read_string(bytes, &mut i)?;
+        let width = read_u32(bytes, &mut i)?;
+        let height = read_u32(bytes, &mut i)?;
+
+        // Colour depth & amount of indexed colours
+        i += 8;
+
+        let data = read_slice(bytes, &mut i)?;
+
+        let mut picture = Picture {
+            picture_type,
+            mime_type,
+            description,
+            width,
+            height,
+            data,
+        };
+        picture.fill_from_image();
+
+        Ok(picture)
+    }
+
+    /// Fill in the MIME type and dimensions the tag left out by looking at the image itself
+    pub(crate) fn fill_from_image(&mut self) {
+        let Some((mime_type, width, height)) = image_info(self.data) else {
+            return;
+        };
+
+        if self.mime_type.is_empty() {
+            self.mime_type = Cow::Borrowed(mime_type);
+        }
+
+        if self.width == 0 || self.height == 0 {
+            self.width = width;
+            self.height = height;
+        }
+    }
+}
+
+/// MIME type and dimensions of a PNG or JPEG image
+fn image_info(data: &[u8]) -> Option<(&'static str, u32, u32)> {
+    if let Some(header) = data.strip_prefix(b"\x89PNG\r\n\x1a\n") {
+        // The IHDR chunk always comes first, after its length & type
+        let width = u32::from_be_bytes(header.get(8..12)?.try_into().ok()?);
+        let height = u32::from_be_bytes(header.get(12..16)?.try_into().ok()?);
+
+        return Some(("image/png", width, height));
+    }
+
+    let mut i = 2;
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+
+    // Walk the JPEG segments until a start of frame, which holds the dimensions
+    while let [0xFF, marker, high, low, ..] = *data.get(i..)? {
+        let length = u16::from_be_bytes([high, low]) as usize;
+
+        if matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            let frame = data.get(i + 5..i + 9)?;
+            let height = u16::from_be_bytes([frame[0], frame[1]]);
+            let width = u16::from_be_bytes([frame[2], frame[3]]);
+
+            return Some(("image/jpeg", u32::from(width), u32::from(height)));
+        }
+
+        i += 2 + length;
+    }
+
+    Some(("image/jpeg", 0, 0))
+}
+
+fn read_u32(bytes: &[u8], i: &mut usize) -> Result<u32> {
+    if *i + 4 > bytes.len() {
+        return Err(Error::InvalidPicture);
+    }
+
+    u32_from_bytes_be(bytes, i)
+}
+
+fn read_slice<'a>(bytes: &'a [u8], i: &mut usize) -> Result<&'a [u8]> {
+    let length = read_u32(bytes, i)? as usize;
+    let slice = bytes.get(*i..*i + length).ok_or(Error::InvalidPicture)?;
+    *i += length;
+
+    Ok(slice)
+}
+
+fn read_string<'a>(bytes: &'a [u8], i: &mut usize) -> Result<Cow<'a, str>> {
+    Ok(String::from_utf8_lossy(read_slice(bytes, i)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flac_picture(picture_type: u32, mime_type: &str, width: u32, data: &[u8]) -> Vec<u8> {
+        let mut bytes = picture_type.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&(mime_type.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(mime_type.as_bytes());
+        bytes.extend_from_slice(&5u32.to_be_bytes());
+        bytes.extend_from_slice(b"front");
+        bytes.extend_from_slice(&width.to_be_bytes());
+        bytes.extend_from_slice(&width.to_be_bytes());
+        bytes.extend_from_slice(&[0; 8]);
+        bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn flac_picture_is_parsed() {
+        let bytes = flac_picture(3, "image/jpeg", 500, b"jpeg");
+        let picture = Picture::from_flac_bytes(&bytes).unwrap();
+
+        assert_eq!(picture.picture_type, PictureType::CoverFront);
+        assert_eq!(picture.mime_type, "image/jpeg");
+        assert_eq!(picture.description, "front");
+        assert_eq!((picture.width, picture.height), (500, 500));
+        assert_eq!(picture.data, b"jpeg");
+    }
+
+    #[test]
+    fn truncated_flac_picture_is_an_error() {
+        let bytes = flac_picture(3, "image/jpeg", 500, b"jpeg");
+        let err = Picture::from_flac_bytes(&bytes[..bytes.len() - 1]).unwrap_err();
+
+        assert!(matches!(err, Error::InvalidPicture));
+    }
+
+    #[test]
+    fn missing_details_are_read_from_the_image() {
+        let data = png(300, 200);
+        let picture = Picture::from_image(PictureType::CoverFront, &data);
+
+        assert_eq!(picture.mime_type, "image/png");
+        assert_eq!((picture.width, picture.height), (300, 200));
+
+        // Baseline JPEG with an APP0 segment in front of the start of frame
+        let data = [
+            0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00, 0xFF, 0xC0, 0x00, 0x0B, 0x08, 0x00,
+            0x40, 0x00, 0x80,
+        ];
+        let picture = Picture::from_image(PictureType::Other, &data);
+
+        assert_eq!(picture.mime_type, "image/jpeg");
+        assert_eq!((picture.width, picture.height), (128, 64));
+    }
+}
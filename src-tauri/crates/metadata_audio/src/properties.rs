@@ -0,0 +1,96 @@
+/// Codec of the audio stream
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Codec {
+    #[default]
+    Unknown,
+    Flac,
+    /// Apple Lossless
+    Alac,
+    /// Uncompressed audio, as stored in WAV and AIFF
+    Pcm,
+    Mp1,
+    Mp2,
+    Mp3,
+    Aac,
+    Vorbis,
+    Opus,
+}
+
+impl Codec {
+    /// Name of the codec as shown to users
+    pub fn name(self) -> &'static str {
+        match self {
+            Codec::Unknown => "Unknown",
+            Codec::Flac => "FLAC",
+            Codec::Alac => "ALAC",
+            Codec::Pcm => "PCM",
+            Codec::Mp1 => "MP1",
+            Codec::Mp2 => "MP2",
+            Codec::Mp3 => "MP3",
+            Codec::Aac => "AAC",
+            Codec::Vorbis => "Vorbis",
+            Codec::Opus => "Opus",
+        }
+    }
+
+    pub fn is_lossless(self) -> bool {
+        matches!(self, Codec::Flac | Codec::Alac | Codec::Pcm)
+    }
+}
+
+/// Properties of the audio stream itself, rather than what it's tagged with
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct StreamProperties {
+    pub codec: Codec,
+
+    /// Sample rate in Hz
+    pub sample_rate: u32,
+
+    /// Bits per sample, which lossy codecs don't have
+    pub bit_depth: Option<u8>,
+
+    pub channels: u8,
+
+    /// Average bitrate in kbit/s
+    pub bitrate: u32,
+}
+
+impl StreamProperties {
+    /// Average bitrate in kbit/s of `audio_bytes` played over `duration` seconds
+    pub(crate) fn average_bitrate(audio_bytes: u64, duration: f32) -> u32 {
+        if duration <= 0.0 {
+            return 0;
+        }
+
+        (audio_bytes as f64 * 8.0 / f64::from(duration) / 1000.0).round() as u32
+    }
+
+    /// Bitrate in kbit/s of uncompressed audio with these properties
+    pub(crate) fn pcm_bitrate(&self) -> u32 {
+        let bits_per_second = u64::from(self.sample_rate)
+            * u64::from(self.channels)
+            * u64::from(self.bit_depth.unwrap_or(0));
+
+        (bits_per_second / 1000) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bitrates() {
+        assert_eq!(StreamProperties::average_bitrate(40_000, 1.0), 320);
+        assert_eq!(StreamProperties::average_bitrate(40_000, 0.0), 0);
+
+        let cd = StreamProperties {
+            codec: Codec::Pcm,
+            sample_rate: 44_100,
+            bit_depth: Some(16),
+            channels: 2,
+            bitrate: 0,
+        };
+        assert_eq!(cd.pcm_bitrate(), 1411);
+    }
+}
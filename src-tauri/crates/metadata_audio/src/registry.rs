@@ -0,0 +1,220 @@
+use std::{
+    io::SeekFrom,
+    sync::{LazyLock, PoisonError, RwLock, RwLockReadGuard},
+};
+
+use crate::{
+    Error, Metadata, Result, SupportedFormats,
+    tag_map::TagMap,
+    traits::{MetadataFormat, ReadSeek},
+};
+
+/// How many bytes are read from the start of a stream to detect its format.
+///
+/// `RIFF`/`FORM` need 12 bytes to also see the form type.
+pub const SIGNATURE_LEN: usize = 12;
+
+static REGISTRY: LazyLock<RwLock<FormatRegistry>> =
+    LazyLock::new(|| RwLock::new(FormatRegistry::default()));
+
+pub struct FormatRegistry {
+    formats: Vec<Box<dyn MetadataFormat>>,
+}
+
+impl Default for FormatRegistry {
+    fn default() -> Self {
+        // Raw MPEG sync is the weakest signature, so ID3 has to come last
+        let formats: Vec<Box<dyn MetadataFormat>> = vec![
+            Box::new(SupportedFormats::Flac),
+            Box::new(SupportedFormats::Ogg),
+            Box::new(SupportedFormats::Mp4),
+            Box::new(SupportedFormats::Riff),
+            Box::new(SupportedFormats::ID3),
+        ];
+
+        FormatRegistry { formats }
+    }
+}
+
+impl FormatRegistry {
+    /// Formats registered later are tried first, so they can take over built-in signatures
+    pub fn register(&mut self, format: Box<dyn MetadataFormat>) {
+        self.formats.insert(0, format);
+    }
+
+    pub fn detect(&self, signature: &[u8]) -> Option<&dyn MetadataFormat> {
+        self.formats
+            .iter()
+            .find(|format| format.detect_signature(signature))
+            .map(AsRef::as_ref)
+    }
+
+    pub fn by_extension(&self, extension: &str) -> Option<&dyn MetadataFormat> {
+        self.formats
+            .iter()
+            .find(|format| {
+                format
+                    .extensions()
+                    .iter()
+                    .any(|ext| ext.eq_ignore_ascii_case(extension))
+            })
+            .map(AsRef::as_ref)
+    }
+
+    /// Sniff the stream and hand it to the matching format, falling back to
+    /// the extension for streams without a recognisable signature.
+    pub fn parse<'a>(
+        &self,
+        buffer: &'a mut Vec<u8>,
+        reader: &mut dyn ReadSeek,
+        extension: Option<&str>,
+        skip_picture: bool,
+    ) -> Result<Metadata<'a>> {
+        self.sniff(reader, extension)?
+            .parse(buffer, reader, skip_picture)
+    }
+
+    /// Sniff the stream like `parse` does, and read every tag of it instead
+    pub fn tags<'a>(
+        &self,
+        buffer: &'a mut Vec<u8>,
+        reader: &mut dyn ReadSeek,
+        extension: Option<&str>,
+    ) -> Result<TagMap<'a>> {
+        self.sniff(reader, extension)?.tags(buffer, reader)
+    }
+
+    fn sniff(
+        &self,
+        reader: &mut dyn ReadSeek,
+        extension: Option<&str>,
+    ) -> Result<&dyn MetadataFormat> {
+        let mut signature = [0u8; SIGNATURE_LEN];
+        let len = read_signature(reader, &mut signature)?;
+
+        self.detect(&signature[..len])
+            .or_else(|| extension.and_then(|ext| self.by_extension(ext)))
+            .ok_or(Error::UnsupportedFileType)
+    }
+}
+
+pub fn formats() -> RwLockReadGuard<'static, FormatRegistry> {
+    REGISTRY.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn register(format: Box<dyn MetadataFormat>) {
+    REGISTRY
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .register(format);
+}
+
+/// Reads the start of the stream and rewinds to where it was
+fn read_signature(reader: &mut dyn ReadSeek, signature: &mut [u8]) -> Result<usize> {
+    let start = reader.stream_position()?;
+
+    let mut len = 0;
+    while len < signature.len() {
+        match reader.read(&mut signature[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+
+    reader.seek(SeekFrom::Start(start))?;
+
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    struct Fake;
+
+    impl MetadataFormat for Fake {
+        fn detect_signature(&self, signature: &[u8]) -> bool {
+            signature.starts_with(b"fLaC")
+        }
+
+        fn parse<'a>(
+            &self,
+            _buffer: &'a mut Vec<u8>,
+            _reader: &mut dyn ReadSeek,
+            _skip_picture: bool,
+        ) -> Result<Metadata<'a>> {
+            Ok(Metadata {
+                duration: 42.0,
+                ..Default::default()
+            })
+        }
+
+        fn extensions(&self) -> &[&str] {
+            &["fake"]
+        }
+    }
+
+    /// The first extension of the detected format, which is unique per built-in format
+    fn detected(signature: &[u8]) -> Option<String> {
+        let registry = FormatRegistry::default();
+        let format = registry.detect(signature)?;
+
+        Some(format.extensions()[0].to_owned())
+    }
+
+    #[test]
+    fn magic_bytes_are_detected() {
+        assert_eq!(detected(b"fLaC\0\0\0\x22"), Some("flac".into()));
+        assert_eq!(detected(b"ID3\x04\0\0"), Some("mp3".into()));
+        assert_eq!(detected(&[0xFF, 0xFB, 0x90, 0x64]), Some("mp3".into()));
+        assert_eq!(detected(b"OggS\0\x02"), Some("ogg".into()));
+        assert_eq!(detected(b"\0\0\0\x20ftypM4A "), Some("m4a".into()));
+        assert_eq!(detected(b"RIFF\0\0\0\0WAVE"), Some("wav".into()));
+        assert_eq!(detected(b"FORM\0\0\0\0AIFF"), Some("wav".into()));
+        assert_eq!(detected(b"RIFF\0\0\0\0AVI "), None);
+        assert_eq!(detected(&[0u8; 12]), None);
+    }
+
+    #[test]
+    fn registered_formats_are_tried_first() {
+        let mut registry = FormatRegistry::default();
+        registry.register(Box::new(Fake));
+
+        let mut buffer = Vec::new();
+        let meta = registry
+            .parse(
+                &mut buffer,
+                &mut Cursor::new(b"fLaC, but not really"),
+                None,
+                false,
+            )
+            .unwrap();
+
+        assert_eq!(meta.duration, 42.0);
+        assert!(registry.by_extension("FAKE").is_some());
+    }
+
+    #[test]
+    fn extension_is_used_when_sniffing_fails() {
+        let registry = FormatRegistry::default();
+        let mut buffer = Vec::new();
+
+        // Junk before the first frame hides the MPEG sync from the sniffer
+        let err = registry
+            .parse(
+                &mut buffer,
+                &mut Cursor::new([0u8; 256]),
+                Some("mp3"),
+                false,
+            )
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidId3Signature));
+
+        let err = registry
+            .parse(&mut buffer, &mut Cursor::new([0u8; 256]), None, false)
+            .unwrap_err();
+        assert!(matches!(err, Error::UnsupportedFileType));
+    }
+}
@@ -0,0 +1,523 @@
+use std::{
+    borrow::Cow,
+    io::{Read, Seek, SeekFrom},
+};
+
+use crate::{
+    Error, Result,
+    id3::{FrameHeader, Id3, TextEncoding},
+    read_into_buffer_unchecked,
+};
+
+/// Chunk sizes are little-endian in RIFF and big-endian in AIFF
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RiffFormat {
+    Wav,
+    Aiff,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextId {
+    /// `INAM` or AIFF `NAME`
+    Title,
+    /// `IART` or AIFF `AUTH`
+    Artist,
+    /// `IPRD`
+    Album,
+    /// `ICRD`
+    Date,
+    /// `ITRK` or `IPRT`
+    TrackNumber,
+    /// `IGNR`
+    Genre,
+    /// Any other INFO entry or AIFF text chunk, only read for a `TagMap`
+    Other,
+}
+
+impl TextId {
+    fn from_bytes(format: RiffFormat, id: &[u8; 4]) -> Option<TextId> {
+        match (format, id) {
+            (RiffFormat::Wav, b"INAM") | (RiffFormat::Aiff, b"NAME") => Some(TextId::Title),
+            (RiffFormat::Wav, b"IART") | (RiffFormat::Aiff, b"AUTH") => Some(TextId::Artist),
+            (RiffFormat::Wav, b"IPRD") => Some(TextId::Album),
+            (RiffFormat::Wav, b"ICRD") => Some(TextId::Date),
+            (RiffFormat::Wav, b"ITRK" | b"IPRT") => Some(TextId::TrackNumber),
+            (RiffFormat::Wav, b"IGNR") => Some(TextId::Genre),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TextHeader {
+    pub text_id: TextId,
+    pub id: [u8; 4],
+    pub start: u32,
+    pub length: u32,
+}
+
+#[derive(Debug)]
+pub struct RiffChunks {
+    pub format: RiffFormat,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub bits_per_sample: u16,
+    pub duration: f32,
+
+    /// LIST/INFO entries for WAV, text chunks for AIFF
+    pub text: Vec<TextHeader>,
+
+    /// Frames of an embedded `id3 ` or `ID3 ` chunk
+    pub id3_frames: Vec<FrameHeader>,
+}
+
+struct Chunk {
+    id: [u8; 4],
+    start: u64,
+    size: u64,
+    /// Where the body ends, including the pad byte chunks are aligned with
+    end: u64,
+}
+
+impl Chunk {
+    fn parse<R: Read + Seek>(reader: &mut R, format: RiffFormat, form_end: u64) -> Result<Chunk> {
+        let mut buf = [0u8; 8];
+        reader.read_exact(&mut buf)?;
+
+        let id = [buf[0], buf[1], buf[2], buf[3]];
+        let size = u64::from(match format {
+            RiffFormat::Wav => u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]),
+            RiffFormat::Aiff => u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]),
+        });
+
+        let body_start = reader.stream_position()?;
+        let mut end = body_start + size + (size & 1);
+
+        if end > form_end {
+            // Recorders that were cut off often leave the audio chunk size wrong
+            if &id != b"data" && &id != b"SSND" {
+                return Err(Error::InvalidChunkSize);
+            }
+            end = form_end;
+        }
+
+        Ok(Chunk {
+            id,
+            start: body_start,
+            size: size.min(end - body_start),
+            end,
+        })
+    }
+}
+
+pub struct Riff {}
+
+impl Riff {
+    /// Reads the chunks `Metadata` uses, without the pictures of an embedded ID3 tag if
+    /// `skip_picture` is set
+    pub fn read_all_chunks<R: Read + Seek>(
+        block_buffer: &mut Vec<u8>,
+        reader: &mut R,
+        skip_picture: bool,
+    ) -> Result<RiffChunks> {
+        Self::read(block_buffer, reader, false, skip_picture)
+    }
+
+    /// Reads every text chunk & INFO entry and every frame of an embedded ID3 tag
+    pub fn read_every_chunk<R: Read + Seek>(
+        block_buffer: &mut Vec<u8>,
+        reader: &mut R,
+    ) -> Result<RiffChunks> {
+        Self::read(block_buffer, reader, true, false)
+    }
+
+    fn read<R: Read + Seek>(
+        block_buffer: &mut Vec<u8>,
+        reader: &mut R,
+        every_chunk: bool,
+        skip_picture: bool,
+    ) -> Result<RiffChunks> {
+        let mut header = [0u8; 12];
+        reader.read_exact(&mut header)?;
+
+        let format = match (&header[0..4], &header[8..12]) {
+            (b"RIFF", b"WAVE") => RiffFormat::Wav,
+            (b"FORM", b"AIFF" | b"AIFC") => RiffFormat::Aiff,
+            _ => return Err(Error::InvalidRiffSignature),
+        };
+
+        let form_start = reader.stream_position()?;
+        let stream_end = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(form_start))?;
+
+        let mut chunks = RiffChunks {
+            format,
+            sample_rate: 0,
+            channels: 0,
+            bits_per_sample: 0,
+            duration: -1.0,
+            text: Vec::new(),
+            id3_frames: Vec::new(),
+        };
+
+        let mut byte_rate = 0;
+        let mut data_size = None;
+
+        while reader.stream_position()? + 8 <= stream_end {
+            let chunk = Chunk::parse(reader, format, stream_end)?;
+
+            match (format, &chunk.id) {
+                (RiffFormat::Wav, b"fmt ") if chunk.size >= 16 => {
+                    let mut fmt = [0u8; 16];
+                    reader.read_exact(&mut fmt)?;
+
+                    chunks.channels = u16::from_le_bytes([fmt[2], fmt[3]]);
+                    chunks.sample_rate = u32::from_le_bytes(fmt[4..8].try_into().unwrap());
+                    byte_rate = u32::from_le_bytes(fmt[8..12].try_into().unwrap());
+                    chunks.bits_per_sample = u16::from_le_bytes([fmt[14], fmt[15]]);
+                }
+                (RiffFormat::Wav, b"data") => data_size = Some(chunk.size),
+                (RiffFormat::Wav, b"LIST") if chunk.size >= 4 => {
+                    let mut list_type = [0u8; 4];
+                    reader.read_exact(&mut list_type)?;
+
+                    if &list_type == b"INFO" {
+                        Self::read_info(
+                            block_buffer,
+                            reader,
+                            &chunk,
+                            &mut chunks.text,
+                            every_chunk,
+                        )?;
+                    }
+                }
+                (RiffFormat::Aiff, b"COMM") if chunk.size >= 18 => {
+                    let mut comm = [0u8; 18];
+                    reader.read_exact(&mut comm)?;
+
+                    chunks.channels = u16::from_be_bytes([comm[0], comm[1]]);
+                    let frames = u32::from_be_bytes(comm[2..6].try_into().unwrap());
+                    chunks.bits_per_sample = u16::from_be_bytes([comm[6], comm[7]]);
+                    chunks.sample_rate = extended_to_u32(comm[8..18].try_into().unwrap());
+
+                    if chunks.sample_rate > 0 {
+                        chunks.duration = frames as f32 / chunks.sample_rate as f32;
+                    }
+                }
+                (RiffFormat::Aiff, id) => {
+                    let text_id = match TextId::from_bytes(format, id) {
+                        Some(text_id) => Some(text_id),
+                        None if every_chunk && matches!(id, b"(c) " | b"ANNO") => {
+                            Some(TextId::Other)
+                        }
+                        None => None,
+                    };
+
+                    if let Some(text_id) = text_id {
+                        Self::push_text(
+                            block_buffer,
+                            reader,
+                            text_id,
+                            *id,
+                            chunk.size,
+                            &mut chunks.text,
+                        )?;
+                    }
+                }
+                _ => {}
+            }
+
+            if &chunk.id == b"id3 " || &chunk.id == b"ID3 " {
+                reader.seek(SeekFrom::Start(chunk.start))?;
+
+                let tag = if every_chunk {
+                    Id3::read_every_frame(block_buffer, reader)
+                } else {
+                    Id3::read_all_frames(block_buffer, reader, skip_picture)
+                };
+
+                match tag {
+                    Ok(tag) => chunks.id3_frames = tag.frames,
+                    Err(e) => logging::warn!("Skipping embedded ID3 chunk: {e}"),
+                }
+            }
+
+            reader.seek(SeekFrom::Start(chunk.end))?;
+        }
+
+        if let Some(data_size) = data_size
+            && byte_rate > 0
+        {
+            chunks.duration = data_size as f32 / byte_rate as f32;
+        }
+
+        Ok(chunks)
+    }
+
+    fn read_info<R: Read + Seek>(
+        block_buffer: &mut Vec<u8>,
+        reader: &mut R,
+        list: &Chunk,
+        text: &mut Vec<TextHeader>,
+        every_entry: bool,
+    ) -> Result<()> {
+        while reader.stream_position()? + 8 <= list.end {
+            let entry = Chunk::parse(reader, RiffFormat::Wav, list.end)?;
+
+            let text_id = TextId::from_bytes(RiffFormat::Wav, &entry.id)
+                .or(every_entry.then_some(TextId::Other));
+
+            if let Some(text_id) = text_id {
+                Self::push_text(block_buffer, reader, text_id, entry.id, entry.size, text)?;
+            }
+
+            reader.seek(SeekFrom::Start(entry.end))?;
+        }
+
+        Ok(())
+    }
+
+    fn push_text<R: Read>(
+        block_buffer: &mut Vec<u8>,
+        reader: &mut R,
+        text_id: TextId,
+        id: [u8; 4],
+        length: u64,
+        text: &mut Vec<TextHeader>,
+    ) -> Result<()> {
+        let start = block_buffer.len() as u32;
+        read_into_buffer_unchecked(reader, block_buffer, length as usize)?;
+
+        text.push(TextHeader {
+            text_id,
+            id,
+            start,
+            length: length as u32,
+        });
+
+        Ok(())
+    }
+}
+
+/// INFO strings are meant to be ASCII, but UTF-8 and Latin-1 are both common
+pub fn decode_text(bytes: &[u8]) -> Result<Cow<'_, str>> {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    let bytes = &bytes[..end];
+
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Ok(Cow::Borrowed(text.trim())),
+        Err(_) => TextEncoding::Latin1.decode(bytes),
+    }
+}
+
+/// The Vorbis comment name of an INFO entry or AIFF text chunk, others keep their ID
+pub(crate) fn tag_key(format: RiffFormat, id: &[u8; 4]) -> Cow<'static, str> {
+    let key = match (format, id) {
+        (RiffFormat::Wav, b"INAM") | (RiffFormat::Aiff, b"NAME") => "TITLE",
+        (RiffFormat::Wav, b"IART") | (RiffFormat::Aiff, b"AUTH") => "ARTIST",
+        (RiffFormat::Wav, b"IPRD") => "ALBUM",
+        (RiffFormat::Wav, b"ICRD") => "DATE",
+        (RiffFormat::Wav, b"ITRK" | b"IPRT") => "TRACKNUMBER",
+        (RiffFormat::Wav, b"IGNR") => "GENRE",
+        (RiffFormat::Wav, b"ICMT") | (RiffFormat::Aiff, b"ANNO") => "COMMENT",
+        (RiffFormat::Wav, b"ICOP") | (RiffFormat::Aiff, b"(c) ") => "COPYRIGHT",
+        (RiffFormat::Wav, b"ISFT") => "ENCODERSETTINGS",
+        _ => return Cow::Owned(String::from_utf8_lossy(id).trim().to_owned()),
+    };
+
+    Cow::Borrowed(key)
+}
+
+/// Convert the 80-bit IEEE 754 extended float AIFF uses for the sample rate
+fn extended_to_u32(bytes: [u8; 10]) -> u32 {
+    let exponent = i32::from(u16::from_be_bytes([bytes[0], bytes[1]]) & 0x7FFF);
+    let mantissa = u64::from_be_bytes(bytes[2..10].try_into().unwrap());
+
+    // The mantissa has an explicit integer bit, so shift it down to the whole part
+    let shift = 16383 + 63 - exponent;
+    if mantissa == 0 || !(0..64).contains(&shift) {
+        return 0;
+    }
+
+    (mantissa >> shift) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    const RATE_44100: [u8; 10] = [0x40, 0x0E, 0xAC, 0x44, 0, 0, 0, 0, 0, 0];
+
+    fn chunk(format: RiffFormat, id: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        match format {
+            RiffFormat::Wav => chunk.extend_from_slice(&(body.len() as u32).to_le_bytes()),
+            RiffFormat::Aiff => chunk.extend_from_slice(&(body.len() as u32).to_be_bytes()),
+        }
+        chunk.extend_from_slice(body);
+        if body.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn wav(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body = chunks.concat();
+        let mut data = b"RIFF".to_vec();
+        data.extend_from_slice(&(body.len() as u32 + 4).to_le_bytes());
+        data.extend_from_slice(b"WAVE");
+        data.extend(body);
+        data
+    }
+
+    fn fmt(sample_rate: u32, channels: u16, bits: u16) -> Vec<u8> {
+        let block_align = channels * bits / 8;
+        let mut fmt = 1u16.to_le_bytes().to_vec();
+        fmt.extend_from_slice(&channels.to_le_bytes());
+        fmt.extend_from_slice(&sample_rate.to_le_bytes());
+        fmt.extend_from_slice(&(sample_rate * u32::from(block_align)).to_le_bytes());
+        fmt.extend_from_slice(&block_align.to_le_bytes());
+        fmt.extend_from_slice(&bits.to_le_bytes());
+        chunk(RiffFormat::Wav, b"fmt ", &fmt)
+    }
+
+    fn read(data: &[u8]) -> (Vec<u8>, RiffChunks) {
+        let mut buffer = Vec::new();
+        let chunks = Riff::read_all_chunks(&mut buffer, &mut Cursor::new(data), false).unwrap();
+        (buffer, chunks)
+    }
+
+    fn text<'a>(buffer: &'a [u8], chunks: &RiffChunks, text_id: TextId) -> Option<Cow<'a, str>> {
+        let header = chunks.text.iter().find(|h| h.text_id == text_id)?;
+        let start = header.start as usize;
+        decode_text(&buffer[start..start + header.length as usize]).ok()
+    }
+
+    #[test]
+    fn wav_duration_and_info() {
+        let mut info = b"INFO".to_vec();
+        info.extend(chunk(RiffFormat::Wav, b"INAM", b"Track\0"));
+        info.extend(chunk(RiffFormat::Wav, b"IART", b"Artist\0"));
+        info.extend(chunk(RiffFormat::Wav, b"IPRD", b"Alb\xe9m\0"));
+        info.extend(chunk(RiffFormat::Wav, b"ITRK", b"7\0"));
+
+        let data = wav(&[
+            fmt(48_000, 2, 16),
+            chunk(RiffFormat::Wav, b"LIST", &info),
+            chunk(RiffFormat::Wav, b"data", &vec![0u8; 48_000 * 4 * 2]),
+        ]);
+
+        let (buffer, chunks) = read(&data);
+
+        assert_eq!(chunks.format, RiffFormat::Wav);
+        assert_eq!(chunks.sample_rate, 48_000);
+        assert_eq!(chunks.channels, 2);
+        assert_eq!(chunks.bits_per_sample, 16);
+        assert!((chunks.duration - 2.0).abs() < 0.001);
+
+        assert_eq!(
+            text(&buffer, &chunks, TextId::Title).as_deref(),
+            Some("Track")
+        );
+        assert_eq!(
+            text(&buffer, &chunks, TextId::Artist).as_deref(),
+            Some("Artist")
+        );
+        assert_eq!(
+            text(&buffer, &chunks, TextId::Album).as_deref(),
+            Some("Albém")
+        );
+        assert_eq!(
+            text(&buffer, &chunks, TextId::TrackNumber).as_deref(),
+            Some("7")
+        );
+    }
+
+    #[test]
+    fn odd_chunks_are_padded() {
+        let data = wav(&[
+            chunk(RiffFormat::Wav, b"junk", &[1, 2, 3]),
+            fmt(8_000, 1, 8),
+            chunk(RiffFormat::Wav, b"data", &[0u8; 8_000]),
+        ]);
+
+        let (_, chunks) = read(&data);
+
+        assert_eq!(chunks.sample_rate, 8_000);
+        assert!((chunks.duration - 1.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn truncated_data_chunk_is_clamped() {
+        let mut data = wav(&[
+            fmt(8_000, 1, 8),
+            chunk(RiffFormat::Wav, b"data", &[0u8; 4_000]),
+        ]);
+        let size_offset = data.len() - 4_000 - 4;
+        data[size_offset..size_offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        let (_, chunks) = read(&data);
+
+        assert!((chunks.duration - 0.5).abs() < 0.001);
+    }
+
+    #[test]
+    fn aiff_comm_and_id3_chunk() {
+        let mut comm = 2u16.to_be_bytes().to_vec();
+        comm.extend_from_slice(&(44_100u32 * 3).to_be_bytes());
+        comm.extend_from_slice(&16u16.to_be_bytes());
+        comm.extend_from_slice(&RATE_44100);
+
+        let mut id3 = b"ID3\x03\x00\x00\x00\x00\x00\x11".to_vec();
+        id3.extend_from_slice(b"TIT2\x00\x00\x00\x07\x00\x00\x00Title!");
+
+        let chunks = [
+            chunk(RiffFormat::Aiff, b"COMM", &comm),
+            chunk(RiffFormat::Aiff, b"NAME", b"Name"),
+            chunk(RiffFormat::Aiff, b"ID3 ", &id3),
+            chunk(RiffFormat::Aiff, b"SSND", &[0u8; 64]),
+        ]
+        .concat();
+
+        let mut data = b"FORM".to_vec();
+        data.extend_from_slice(&(chunks.len() as u32 + 4).to_be_bytes());
+        data.extend_from_slice(b"AIFF");
+        data.extend(chunks);
+
+        let (buffer, chunks) = read(&data);
+
+        assert_eq!(chunks.format, RiffFormat::Aiff);
+        assert_eq!(chunks.sample_rate, 44_100);
+        assert_eq!(chunks.channels, 2);
+        assert!((chunks.duration - 3.0).abs() < 0.001);
+        assert_eq!(
+            text(&buffer, &chunks, TextId::Title).as_deref(),
+            Some("Name")
+        );
+
+        let frame = &chunks.id3_frames[0];
+        let start = frame.data_start as usize;
+        assert_eq!(&buffer[start..start + frame.length as usize], b"\x00Title!");
+    }
+
+    #[test]
+    fn extended_float_sample_rates() {
+        assert_eq!(extended_to_u32(RATE_44100), 44_100);
+        assert_eq!(
+            extended_to_u32([0x40, 0x0E, 0xBB, 0x80, 0, 0, 0, 0, 0, 0]),
+            48_000
+        );
+        assert_eq!(extended_to_u32([0; 10]), 0);
+    }
+
+    #[test]
+    fn invalid_signature_returns_error() {
+        let mut buffer = Vec::new();
+        let err =
+            Riff::read_all_chunks(&mut buffer, &mut Cursor::new(&[0u8; 64]), false).unwrap_err();
+
+        assert!(matches!(err, Error::InvalidRiffSignature));
+    }
+}
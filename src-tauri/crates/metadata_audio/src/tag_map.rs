@@ -0,0 +1,350 @@
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{BufReader, Cursor, Read, Seek},
+    path::Path,
+};
+
+use crate::{
+    Error, Result, flac,
+    id3::{self, Id3},
+    id3v1::{self, Id3v1},
+    mp4::Mp4,
+    ogg::Ogg,
+    picture::Picture,
+    registry,
+    riff::{self, Riff},
+    slice_at,
+};
+
+/// A single tag value, typed as the format stores it.
+///
+/// Text based formats like `ID3v2` and Vorbis comments keep numbers as text, exactly as
+/// they're tagged.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TagValue<'a> {
+    Text(Cow<'a, str>),
+    Number(i64),
+    /// Anything that isn't text, like `PRIV` frames or unknown MP4 data
+    Binary(&'a [u8]),
+    Picture(Picture<'a>),
+}
+
+/// Every tag of a file in the order they're stored, where a key can hold any number of values.
+///
+/// Keys are upper case. Fields with a common meaning are named as in Vorbis comments, like
+/// `TITLE` or `ALBUMARTIST`, anything else keeps the name the format gives it: the frame ID,
+/// the `TXXX` description or the name of an iTunes freeform atom.
+#[derive(Clone, Debug, Default)]
+pub struct TagMap<'a> {
+    entries: Vec<(Cow<'a, str>, TagValue<'a>)>,
+}
+
+impl<'a> TagMap<'a> {
+    pub fn new() -> TagMap<'a> {
+        TagMap::default()
+    }
+
+    /// Add a value to the end, keys are trimmed & upper cased
+    pub fn push(&mut self, key: impl Into<Cow<'a, str>>, value: TagValue<'a>) {
+        let key = normalize_key(key.into());
+        if !key.is_empty() {
+            self.entries.push((key, value));
+        }
+    }
+
+    /// The first value of `key`
+    pub fn get(&self, key: &str) -> Option<&TagValue<'a>> {
+        self.get_all(key).next()
+    }
+
+    /// Every value of `key`, in the order they're stored
+    pub fn get_all(&self, key: &str) -> impl Iterator<Item = &TagValue<'a>> {
+        self.entries
+            .iter()
+            .filter(move |(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &TagValue<'a>)> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (key.as_ref(), value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Read every tag of an audio file, the format is detected like `Metadata::from_file` does
+    pub fn from_file(buffer: &'a mut Vec<u8>, path: &Path) -> Result<TagMap<'a>> {
+        let file = File::open(path)?;
+        let mut reader = BufReader::with_capacity(4 * 1024, file);
+        let extension = path.extension().and_then(|s| s.to_str());
+
+        registry::formats().tags(buffer, &mut reader, extension)
+    }
+
+    /// Read every tag of an audio file already in memory
+    pub fn from_bytes(buffer: &'a mut Vec<u8>, data: &[u8]) -> Result<TagMap<'a>> {
+        let mut reader = Cursor::new(data);
+        registry::formats().tags(buffer, &mut reader, None)
+    }
+
+    pub(crate) fn read_flac<R: Read + Seek>(
+        buffer: &'a mut Vec<u8>,
+        reader: &mut R,
+    ) -> Result<TagMap<'a>> {
+        let headers = flac::Flac::read_all_blocks(buffer, reader, false)?;
+        let buffer: &'a [u8] = buffer;
+
+        let mut tags = TagMap::new();
+        for header in headers {
+            let slice = slice_at(buffer, header.start as usize, header.length as usize)?;
+
+            match header.block_type {
+                flac::BlockType::VorbisComment => tags.push_vorbis_comment(slice, false)?,
+                flac::BlockType::Picture => tags.push_picture(slice),
+                flac::BlockType::StreamInfo | flac::BlockType::Unknown => {}
+            }
+        }
+
+        Ok(tags)
+    }
+
+    pub(crate) fn read_ogg<R: Read + Seek>(
+        buffer: &'a mut Vec<u8>,
+        reader: &mut R,
+    ) -> Result<TagMap<'a>> {
+        let headers = Ogg::read_headers(buffer, reader, false)?;
+        let buffer: &'a [u8] = buffer;
+
+        let comment = &headers.comment;
+        let comment = slice_at(buffer, comment.start as usize, comment.length as usize)?;
+
+        // The base64 pictures are replaced by the ones decoded onto the end of the buffer
+        let mut tags = TagMap::new();
+        tags.push_vorbis_comment(comment, true)?;
+
+        for picture in headers.pictures {
+            let slice = slice_at(buffer, picture.start as usize, picture.length as usize)?;
+            tags.push_picture(slice);
+        }
+
+        Ok(tags)
+    }
+
+    /// `ID3v1` is only used for files without an `ID3v2` tag, as it can't hold anything new
+    pub(crate) fn read_id3<R: Read + Seek>(
+        buffer: &'a mut Vec<u8>,
+        reader: &mut R,
+    ) -> Result<TagMap<'a>> {
+        let frames = match Id3::read_every_frame(buffer, reader) {
+            Ok(tag) => tag.frames,
+            Err(Error::InvalidId3Signature | Error::UnsupportedId3Version) => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        let id3v1_start = if frames.is_empty() {
+            Id3v1::read_tag(buffer, reader)?
+        } else {
+            None
+        };
+
+        let buffer: &'a [u8] = buffer;
+
+        let mut tags = TagMap::new();
+        for frame in &frames {
+            let slice = slice_at(buffer, frame.data_start as usize, frame.length as usize)?;
+            id3::push_frame(&mut tags, frame, slice);
+        }
+
+        if let Some(start) = id3v1_start {
+            let slice = slice_at(buffer, start as usize, id3v1::TAG_SIZE as usize)?;
+            tags.push_id3v1(Id3v1::from_bytes(slice)?);
+        }
+
+        Ok(tags)
+    }
+
+    pub(crate) fn read_mp4<R: Read + Seek>(
+        buffer: &'a mut Vec<u8>,
+        reader: &mut R,
+    ) -> Result<TagMap<'a>> {
+        let ilst = Mp4::read_ilst(buffer, reader)?;
+        let buffer: &'a [u8] = buffer;
+
+        let mut tags = TagMap::new();
+        if let Some((start, length)) = ilst {
+            crate::mp4::push_items(
+                &mut tags,
+                slice_at(buffer, start as usize, length as usize)?,
+            );
+        }
+
+        Ok(tags)
+    }
+
+    pub(crate) fn read_riff<R: Read + Seek>(
+        buffer: &'a mut Vec<u8>,
+        reader: &mut R,
+    ) -> Result<TagMap<'a>> {
+        let chunks = Riff::read_every_chunk(buffer, reader)?;
+        let buffer: &'a [u8] = buffer;
+
+        let mut tags = TagMap::new();
+        for header in &chunks.text {
+            let slice = slice_at(buffer, header.start as usize, header.length as usize)?;
+            let value = riff::decode_text(slice).map_or(TagValue::Binary(slice), TagValue::Text);
+            tags.push(riff::tag_key(chunks.format, &header.id), value);
+        }
+
+        for frame in &chunks.id3_frames {
+            let slice = slice_at(buffer, frame.data_start as usize, frame.length as usize)?;
+            id3::push_frame(&mut tags, frame, slice);
+        }
+
+        Ok(tags)
+    }
+
+    fn push_vorbis_comment(&mut self, bytes: &'a [u8], skip_pictures: bool) -> Result<()> {
+        let (vendor, fields) = flac::VorbisComment::fields(bytes)?;
+        self.push("VENDOR", TagValue::Text(Cow::Borrowed(vendor)));
+
+        for (key, value) in fields {
+            if skip_pictures && key.eq_ignore_ascii_case(b"METADATA_BLOCK_PICTURE") {
+                continue;
+            }
+
+            self.push(
+                String::from_utf8_lossy(key),
+                TagValue::Text(Cow::Borrowed(value.trim())),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// A picture block that can't be parsed is kept as is, so it still shows up
+    fn push_picture(&mut self, bytes: &'a [u8]) {
+        let value =
+            Picture::from_flac_bytes(bytes).map_or(TagValue::Binary(bytes), TagValue::Picture);
+        self.push("PICTURE", value);
+    }
+
+    fn push_id3v1(&mut self, tag: Id3v1<'a>) {
+        let fields = [
+            ("TITLE", tag.title),
+            ("ARTIST", tag.artist),
+            ("ALBUM", tag.album),
+            ("GENRE", tag.genre.map(Cow::Borrowed)),
+        ];
+
+        for (key, value) in fields {
+            if let Some(value) = value {
+                self.push(key, TagValue::Text(value));
+            }
+        }
+
+        if let Some(year) = tag.year {
+            self.push("YEAR", TagValue::Number(i64::from(year)));
+        }
+
+        if let Some(track_number) = tag.track_number {
+            self.push("TRACKNUMBER", TagValue::Number(i64::from(track_number)));
+        }
+    }
+}
+
+impl<'a> IntoIterator for TagMap<'a> {
+    type Item = (Cow<'a, str>, TagValue<'a>);
+    type IntoIter = std::vec::IntoIter<Self::Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+/// Trim a key and upper case it, without allocating if it already is
+fn normalize_key(key: Cow<'_, str>) -> Cow<'_, str> {
+    let is_normalized = |k: &str| !k.bytes().any(|b| b.is_ascii_lowercase());
+
+    match key {
+        Cow::Borrowed(k) if is_normalized(k.trim()) => Cow::Borrowed(k.trim()),
+        key => Cow::Owned(key.trim().to_ascii_uppercase()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_normalized() {
+        let mut tags = TagMap::new();
+        tags.push(" replaygain_track_gain ", TagValue::Text("-6.5 dB".into()));
+        tags.push("ARTIST", TagValue::Text("Main".into()));
+        tags.push("artist", TagValue::Text("Featured".into()));
+        tags.push("", TagValue::Number(1));
+
+        assert_eq!(tags.len(), 3);
+        assert_eq!(
+            tags.iter().map(|(key, _)| key).collect::<Vec<_>>(),
+            ["REPLAYGAIN_TRACK_GAIN", "ARTIST", "ARTIST"]
+        );
+        assert_eq!(
+            tags.get_all("Artist").collect::<Vec<_>>(),
+            [
+                &TagValue::Text("Main".into()),
+                &TagValue::Text("Featured".into())
+            ]
+        );
+        assert_eq!(tags.get("missing"), None);
+    }
+
+    #[test]
+    fn flac_keeps_custom_fields_in_order() {
+        let fields: [&[u8]; 4] = [
+            b"TITLE=Title",
+            b"replaygain_album_gain=-7.1 dB",
+            b"ARTIST=Main",
+            b"ARTIST=Featured",
+        ];
+
+        let mut comment = 6u32.to_le_bytes().to_vec();
+        comment.extend_from_slice(b"vendor");
+        comment.extend_from_slice(&(fields.len() as u32).to_le_bytes());
+        for field in fields {
+            comment.extend_from_slice(&(field.len() as u32).to_le_bytes());
+            comment.extend_from_slice(field);
+        }
+
+        let mut data = b"fLaC".to_vec();
+        data.extend_from_slice(&[0x84, 0, 0, comment.len() as u8]);
+        data.extend(comment);
+
+        let mut buffer = Vec::new();
+        let tags = TagMap::from_bytes(&mut buffer, &data).unwrap();
+
+        let text = |value: &TagValue| match value {
+            TagValue::Text(text) => text.to_string(),
+            _ => panic!("expected text"),
+        };
+        assert_eq!(
+            tags.iter()
+                .map(|(key, value)| format!("{key}={}", text(value)))
+                .collect::<Vec<_>>(),
+            [
+                "VENDOR=vendor",
+                "TITLE=Title",
+                "REPLAYGAIN_ALBUM_GAIN=-7.1 dB",
+                "ARTIST=Main",
+                "ARTIST=Featured"
+            ]
+        );
+    }
+}
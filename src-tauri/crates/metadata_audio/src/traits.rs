@@ -1,16 +1,32 @@
-use crate::{Metadata, Result};
+use crate::{Error, Metadata, Result, tag_map::TagMap};
 use std::io::{Read, Seek};
 
-pub trait MetadataFormat<'a>: Sized {
-    /// Checks if the stream signature matches this format.
-    fn detect_signature<R: Read + Seek>(reader: &mut R) -> Result<bool>;
+/// Any stream a format can be parsed from, kept object safe so formats can be boxed
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+pub trait MetadataFormat: Send + Sync {
+    /// Checks if the first bytes of the stream match this format.
+    ///
+    /// `signature` holds up to [`SIGNATURE_LEN`](crate::registry::SIGNATURE_LEN) bytes,
+    /// fewer if the stream is shorter than that.
+    fn detect_signature(&self, signature: &[u8]) -> bool;
 
     /// Parses the stream into a unified `Metadata` object.
-    fn parse<R: Read + Seek>(
+    fn parse<'a>(
+        &self,
         buffer: &'a mut Vec<u8>,
-        reader: &mut R,
+        reader: &mut dyn ReadSeek,
         skip_picture: bool,
     ) -> Result<Metadata<'a>>;
-}
 
-// TODO: Add traits for FLAC & ID3 to allow for versioning
+    /// Reads every tag of the stream, formats that don't support this return
+    /// `UnsupportedFileType`
+    fn tags<'a>(&self, _buffer: &'a mut Vec<u8>, _reader: &mut dyn ReadSeek) -> Result<TagMap<'a>> {
+        Err(Error::UnsupportedFileType)
+    }
+
+    /// File extensions used when scanning folders, and as a fallback when sniffing fails
+    fn extensions(&self) -> &[&str];
+}
@@ -17,6 +17,26 @@ export const commands = {
       else return { status: "error", error: e as any };
     }
   },
+  /**
+   * Stop the library import in progress, tracks imported so far are kept
+   */
+  async cancelImport(): Promise<void> {
+    await TAURI_INVOKE("cancel_import");
+  },
+  /**
+   * Every tag stored in the file of a track, in the order the file has them
+   */
+  async getTrackTags(id: number): Promise<Result<TrackTag[], FrontendError>> {
+    try {
+      return {
+        status: "ok",
+        data: await TAURI_INVOKE("get_track_tags", { id }),
+      };
+    } catch (e) {
+      if (e instanceof Error) throw e;
+      else return { status: "error", error: e as any };
+    }
+  },
   async getAlbumWithTracks(
     id: number,
   ): Promise<Result<AlbumWithTracks, FrontendError>> {
@@ -378,6 +398,10 @@ export type Albums = {
    * Path to album folder from where it was imported
    */
   path: string;
+  /**
+   * Whether the album is a compilation of different artists
+   */
+  compilation: boolean;
 };
 export type ArtistWithAlbums = {
   artist: Artists;
@@ -385,6 +409,10 @@ export type ArtistWithAlbums = {
    * All albums belonging to artist
    */
   albums: AlbumWithTracks[];
+  /**
+   * Tracks the artist is credited on from albums that don't belong to them
+   */
+  appears_on: Tracks[];
 };
 export type Artists = {
   /**
@@ -431,12 +459,17 @@ export type LibraryConfig = {
    * The directory where all the music files are
    */
   music_dir: string | null;
+  /**
+   * If changes to the music directory should be imported while VEIL is open
+   */
+  watch_music_dir: boolean;
 };
 export type MetadataEvent =
   | { event: "Started"; data: { id: number } }
   | { event: "Total"; data: { id: number; total: number } }
   | { event: "Progress"; data: { id: number; current: number } }
-  | { event: "Finished"; data: { id: number } };
+  | { event: "Finished"; data: { id: number; summary: ScanSummary } }
+  | { event: "Cancelled"; data: { id: number } };
 export type PlayButtonState = "Playing" | "Paused";
 /**
  * Playback behavior and queue state
@@ -566,6 +599,43 @@ export type RepeatMode =
    * Repeat the track when the end of the track is hit.
    */
   | "Track";
+/**
+ * How the files in the music folder compared to the library
+ */
+export type ScanSummary = {
+  /**
+   * Files that weren't in the library
+   */
+  added: number;
+  /**
+   * Files that changed since they were imported
+   */
+  updated: number;
+  /**
+   * Tracks whose file is gone, or is missing the tags a track needs now
+   */
+  removed: number;
+  /**
+   * Tracks whose file was moved or renamed, which keep their playlist entries
+   */
+  moved: number;
+  /**
+   * Files that haven't changed, which aren't read again
+   */
+  unchanged: number;
+  /**
+   * Copies of a track already in the library, which are skipped
+   */
+  duplicates: number;
+  /**
+   * Tracks kept as unavailable, because the music folder couldn't be reached
+   */
+  unavailable: number;
+  /**
+   * Unavailable tracks whose music folder could be reached again
+   */
+  restored: number;
+};
 export type Search = {
   /**
    * ID of the search item
@@ -581,6 +651,28 @@ export type Search = {
   search_type: string;
 };
 export type ThemeMode = "Dark" | "Light" | "System";
+/**
+ * A single raw tag of a track, as shown in the file info inspector
+ */
+export type TrackTag = { key: string; value: TrackTagValue };
+export type TrackTagValue =
+  | { type: "Text"; data: string }
+  | { type: "Number"; data: number }
+  /**
+   * Binary data isn't sent over as a whole, only its size and the first bytes in hex
+   */
+  | { type: "Binary"; data: { size: number; preview: string } }
+  | {
+      type: "Picture";
+      data: {
+        picture_type: number;
+        mime_type: string;
+        description: string;
+        width: number;
+        height: number;
+        size: number;
+      };
+    };
 export type Tracks = {
   /**
    * ID of track in database
@@ -591,7 +683,7 @@ export type Tracks = {
    */
   album_id: number;
   /**
-   * ID of artist in database
+   * ID of track artist in database, the album artist is linked through the album
    */
   artist_id: number;
   /**
@@ -599,7 +691,7 @@ export type Tracks = {
    */
   album_name: string;
   /**
-   * Artist name
+   * Track artist name
    */
   artist_name: string;
   /**
@@ -626,6 +718,59 @@ export type Tracks = {
    * Hash of the metadata
    */
   hash: string;
+  /**
+   * Disc number in album, 1 if the track isn't tagged with one
+   */
+  disc_number: number;
+  /**
+   * Amount of tracks on the disc
+   */
+  track_total: number | null;
+  /**
+   * Amount of discs in album
+   */
+  disc_total: number | null;
+  /**
+   * Genre of track
+   */
+  genre: string | null;
+  /**
+   * Composer of track
+   */
+  composer: string | null;
+  /**
+   * Release date as tagged, e.g. "2005-09-12" or just "2005"
+   */
+  release_date: string | null;
+  /**
+   * Codec of the audio, e.g. "FLAC" or "MP3"
+   */
+  codec: string | null;
+  /**
+   * Sample rate in Hz
+   */
+  sample_rate: number | null;
+  /**
+   * Bits per sample, which lossy codecs don't have
+   */
+  bit_depth: number | null;
+  /**
+   * Amount of audio channels
+   */
+  channels: number | null;
+  /**
+   * Average bitrate in kbit/s
+   */
+  bitrate: number | null;
+  /**
+   * Whether the codec is lossless
+   */
+  lossless: boolean;
+  /**
+   * Whether the file can be reached, tracks of a music folder that's unmounted are kept
+   * but can't be played
+   */
+  available: boolean;
 };
 export type UIUpdateEvent =
   /**
@@ -634,7 +779,11 @@ export type UIUpdateEvent =
   | { type: "ShuffleButton"; data: { enabled: boolean } }
   | { type: "LoopButton"; data: { mode: RepeatMode } }
   | { type: "PlayButton"; data: { state: PlayButtonState } }
-  | { type: "TrackChange"; data: { track: Tracks } };
+  | { type: "TrackChange"; data: { track: Tracks } }
+  /**
+   * The library changed while watching the music directory
+   */
+  | { type: "LibraryUpdated"; data: { summary: ScanSummary } };
 /**
  * UI configuration such as theme or other future endeavors
  */
@@ -667,6 +816,7 @@ export type VeilConfigEvent = {
   discord_enabled: boolean | null;
   last_fm_enabled: boolean | null;
   music_dir: string | null;
+  watch_music_dir: boolean | null;
   last_fm_session_key: string | null;
   queue_origin: QueueOrigin | null;
   queue_idx: number | null;
@@ -1,4 +1,4 @@
-import { type FrontendError, toastBus } from "@/composables/";
+import { type FrontendError, type Tracks, toastBus } from "@/composables/";
 import { convertFileSrc } from "@tauri-apps/api/core";
 import { Ref } from "vue";
 
@@ -57,6 +57,29 @@ export function formatTime(format: string, seconds: number): string {
   throw new Error("Invalid format");
 }
 
+/**
+ * Returns a short description of the audio quality of a track
+ * @param {Tracks} track - The track to describe
+ * @returns {string} Codec with bit depth & sample rate for lossless, or bitrate for lossy audio
+ *
+ * @example
+ * // Returns "FLAC 24/96"
+ * formatQuality(track)
+ *
+ * @example
+ * // Returns "MP3 320 kbps"
+ * formatQuality(track)
+ */
+export function formatQuality(track: Tracks): string {
+  if (!track.codec) return "";
+
+  if (track.lossless && track.bit_depth && track.sample_rate) {
+    return `${track.codec} ${track.bit_depth}/${track.sample_rate / 1000}`;
+  }
+
+  return track.bitrate ? `${track.codec} ${track.bitrate} kbps` : track.codec;
+}
+
 /**
  * Returns the image path if it is not empty, otherwise returns the placeholder image path.
  * @param {string} imagePath - The image path to check